@@ -1,3 +1,4 @@
+use super::float::Float;
 use super::ray::Ray;
 use super::vector3::Vector3;
 
@@ -12,21 +13,19 @@ impl Aabb {
         Aabb { min, max }
     }
 
-    pub fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> bool {
+    pub fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> bool {
         for a in 0..3 {
-            let tmin;
-            let tmax;
-            let t0: f32 = ((self.min[a] - r.origin()[a]) / r.direction()[a])
+            let t0: Float = ((self.min[a] - r.origin()[a]) / r.direction()[a])
                 .min((self.max[a] - r.origin()[a]) / r.direction()[a]);
-            let t1: f32 = ((self.min[a] - r.origin()[a]) / r.direction()[a])
+            let t1: Float = ((self.min[a] - r.origin()[a]) / r.direction()[a])
                 .max((self.max[a] - r.origin()[a]) / r.direction()[a]);
-            tmin = t0.max(t_min);
-            tmax = t1.min(t_max);
+            let tmin = t0.max(t_min);
+            let tmax = t1.min(t_max);
             if tmax <= tmin {
                 return false;
             }
         }
-        return true;
+        true
     }
 }
 
@@ -43,3 +42,297 @@ pub fn surrounding_box(box0: &Aabb, box1: &Aabb) -> Aabb {
     );
     Aabb::new(small, big)
 }
+
+// a ray prepared for slab tests, the direction is inverted once
+// so testing a box only needs multiplications
+pub struct SlabRay {
+    pub origin: [Float; 3],
+    pub inv_direction: [Float; 3],
+}
+
+impl SlabRay {
+    pub fn new(r: &Ray) -> Self {
+        let origin = r.origin();
+        let direction = r.direction();
+        SlabRay {
+            origin: [origin[0], origin[1], origin[2]],
+            inv_direction: [1.0 / direction[0], 1.0 / direction[1], 1.0 / direction[2]],
+        }
+    }
+}
+
+// how `Aabb4` tests its boxes, SSE for f32 and AVX for f64 when the
+// processor has them, or the plain loop they are measured against
+#[derive(Copy, Clone)]
+pub enum SlabTest {
+    Scalar,
+    Simd,
+}
+
+// four boxes stored axis by axis so they can be tested against a ray at once,
+// unused lanes are inverted boxes that can never be hit
+#[derive(Copy, Clone)]
+#[repr(C, align(32))]
+pub struct Aabb4 {
+    pub min: [[Float; 4]; 3],
+    pub max: [[Float; 4]; 3],
+}
+
+impl Aabb4 {
+    pub fn empty() -> Self {
+        Aabb4 {
+            min: [[Float::MAX; 4]; 3],
+            max: [[-Float::MAX; 4]; 3],
+        }
+    }
+
+    pub fn set(&mut self, lane: usize, aabb: &Aabb) {
+        for axis in 0..3 {
+            self.min[axis][lane] = aabb.min[axis];
+            self.max[axis][lane] = aabb.max[axis];
+        }
+    }
+
+    pub fn hit_with(
+        &self,
+        test: SlabTest,
+        r: &SlabRay,
+        t_min: Float,
+        t_max: Float,
+    ) -> (u32, [Float; 4]) {
+        match test {
+            SlabTest::Scalar => self.hit_scalar(r, t_min, t_max),
+            SlabTest::Simd => self.hit(r, t_min, t_max),
+        }
+    }
+
+    // returns a bit mask of the boxes that were hit and the distance
+    // at which the ray enters each of them
+    pub fn hit(&self, r: &SlabRay, t_min: Float, t_max: Float) -> (u32, [Float; 4]) {
+        #[cfg(all(target_arch = "x86_64", not(feature = "f64")))]
+        {
+            // SSE is part of the x86_64 baseline so no runtime check is needed
+            unsafe { self.hit_sse(r, t_min, t_max) }
+        }
+        #[cfg(all(target_arch = "x86_64", feature = "f64"))]
+        {
+            if is_x86_feature_detected!("avx") {
+                unsafe { self.hit_avx(r, t_min, t_max) }
+            } else {
+                self.hit_scalar(r, t_min, t_max)
+            }
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            self.hit_scalar(r, t_min, t_max)
+        }
+    }
+
+    // the near plane of every box on an axis depends only on the sign
+    // of the ray direction, which is the same for all four lanes
+    fn slabs(&self, r: &SlabRay, axis: usize) -> (&[Float; 4], &[Float; 4]) {
+        if r.inv_direction[axis] >= 0.0 {
+            (&self.min[axis], &self.max[axis])
+        } else {
+            (&self.max[axis], &self.min[axis])
+        }
+    }
+
+    // a ray lying in the plane of a slab gives 0 * inf = NaN there, which
+    // `max` and `min` skip so the slab doesn't limit the ray, the SIMD
+    // versions pass the running interval second to do the same
+    pub fn hit_scalar(&self, r: &SlabRay, t_min: Float, t_max: Float) -> (u32, [Float; 4]) {
+        let mut t_near = [t_min; 4];
+        let mut t_far = [t_max; 4];
+        for axis in 0..3 {
+            let (near, far) = self.slabs(r, axis);
+            for lane in 0..4 {
+                let t0 = (near[lane] - r.origin[axis]) * r.inv_direction[axis];
+                let t1 = (far[lane] - r.origin[axis]) * r.inv_direction[axis];
+                t_near[lane] = t_near[lane].max(t0);
+                t_far[lane] = t_far[lane].min(t1);
+            }
+        }
+        let mut mask = 0;
+        for lane in 0..4 {
+            if t_near[lane] <= t_far[lane] {
+                mask |= 1 << lane;
+            }
+        }
+        (mask, t_near)
+    }
+
+    #[cfg(all(target_arch = "x86_64", not(feature = "f64")))]
+    unsafe fn hit_sse(&self, r: &SlabRay, t_min: Float, t_max: Float) -> (u32, [Float; 4]) {
+        use std::arch::x86_64::*;
+
+        let mut t_near = _mm_set1_ps(t_min);
+        let mut t_far = _mm_set1_ps(t_max);
+        for axis in 0..3 {
+            let (near, far) = self.slabs(r, axis);
+            let origin = _mm_set1_ps(r.origin[axis]);
+            let inv_direction = _mm_set1_ps(r.inv_direction[axis]);
+            let t0 = _mm_mul_ps(
+                _mm_sub_ps(_mm_load_ps(near.as_ptr()), origin),
+                inv_direction,
+            );
+            let t1 = _mm_mul_ps(_mm_sub_ps(_mm_load_ps(far.as_ptr()), origin), inv_direction);
+            t_near = _mm_max_ps(t0, t_near);
+            t_far = _mm_min_ps(t1, t_far);
+        }
+        let mask = _mm_movemask_ps(_mm_cmple_ps(t_near, t_far)) as u32;
+        let mut entry = [0.0; 4];
+        _mm_storeu_ps(entry.as_mut_ptr(), t_near);
+        (mask, entry)
+    }
+
+    #[cfg(all(target_arch = "x86_64", feature = "f64"))]
+    #[target_feature(enable = "avx")]
+    unsafe fn hit_avx(&self, r: &SlabRay, t_min: Float, t_max: Float) -> (u32, [Float; 4]) {
+        use std::arch::x86_64::*;
+
+        let mut t_near = _mm256_set1_pd(t_min);
+        let mut t_far = _mm256_set1_pd(t_max);
+        for axis in 0..3 {
+            let (near, far) = self.slabs(r, axis);
+            let origin = _mm256_set1_pd(r.origin[axis]);
+            let inv_direction = _mm256_set1_pd(r.inv_direction[axis]);
+            let t0 = _mm256_mul_pd(
+                _mm256_sub_pd(_mm256_load_pd(near.as_ptr()), origin),
+                inv_direction,
+            );
+            let t1 = _mm256_mul_pd(
+                _mm256_sub_pd(_mm256_load_pd(far.as_ptr()), origin),
+                inv_direction,
+            );
+            t_near = _mm256_max_pd(t0, t_near);
+            t_far = _mm256_min_pd(t1, t_far);
+        }
+        let mask = _mm256_movemask_pd(_mm256_cmp_pd(t_near, t_far, _CMP_LE_OQ)) as u32;
+        let mut entry = [0.0; 4];
+        _mm256_storeu_pd(entry.as_mut_ptr(), t_near);
+        (mask, entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // the SIMD version this build uses, SSE for f32 and AVX for f64, so
+    // running the tests in both builds compares all three
+    fn simd(boxes: &Aabb4, r: &SlabRay, t_min: Float, t_max: Float) -> (u32, [Float; 4]) {
+        #[cfg(all(target_arch = "x86_64", not(feature = "f64")))]
+        {
+            unsafe { boxes.hit_sse(r, t_min, t_max) }
+        }
+        #[cfg(all(target_arch = "x86_64", feature = "f64"))]
+        {
+            if is_x86_feature_detected!("avx") {
+                unsafe { boxes.hit_avx(r, t_min, t_max) }
+            } else {
+                boxes.hit_scalar(r, t_min, t_max)
+            }
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            boxes.hit_scalar(r, t_min, t_max)
+        }
+    }
+
+    fn assert_agree(boxes: &Aabb4, r: &Ray, t_min: Float, t_max: Float) {
+        let slab_ray = SlabRay::new(r);
+        let (scalar_mask, scalar_entry) = boxes.hit_scalar(&slab_ray, t_min, t_max);
+        let (simd_mask, simd_entry) = simd(boxes, &slab_ray, t_min, t_max);
+        assert_eq!(scalar_mask, simd_mask);
+        for lane in 0..4 {
+            if scalar_mask & (1 << lane) != 0 {
+                assert_eq!(scalar_entry[lane], simd_entry[lane]);
+            }
+        }
+    }
+
+    fn unit_boxes() -> Aabb4 {
+        let mut boxes = Aabb4::empty();
+        for lane in 0..3 {
+            let offset = 2.0 * lane as Float;
+            let aabb = Aabb::new(
+                Vector3::new(offset, 0.0, 0.0),
+                Vector3::new(offset + 1.0, 1.0, 1.0),
+            );
+            boxes.set(lane, &aabb);
+        }
+        boxes
+    }
+
+    #[test]
+    fn simd_matches_scalar_on_random_rays() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut point = || {
+            Vector3::new(
+                20.0 * rng.gen::<Float>() - 10.0,
+                20.0 * rng.gen::<Float>() - 10.0,
+                20.0 * rng.gen::<Float>() - 10.0,
+            )
+        };
+        for _ in 0..1000 {
+            let mut boxes = Aabb4::empty();
+            for lane in 0..4 {
+                let (a, b) = (point(), point());
+                let min = Vector3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+                let max = Vector3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+                boxes.set(lane, &Aabb::new(min, max));
+            }
+            let r = Ray::new(point(), point(), 0.0);
+            assert_agree(&boxes, &r, 0.0, Float::MAX);
+            assert_agree(&boxes, &r, 0.5, 3.0);
+        }
+    }
+
+    // rays parallel to an axis lying exactly in the plane of a slab give
+    // 0 * inf = NaN, both have to skip it the same way
+    #[test]
+    fn simd_matches_scalar_in_slab_planes() {
+        let boxes = unit_boxes();
+        let directions = [
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(-1.0, 0.0, 0.0),
+            Vector3::new(1.0, -0.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+        ];
+        let origins = [
+            Vector3::new(-1.0, 0.0, 0.5),
+            Vector3::new(-1.0, 1.0, 0.0),
+            Vector3::new(7.0, 0.5, 1.0),
+            Vector3::new(2.0, 0.0, -3.0),
+            Vector3::new(1.0, 1.0, -3.0),
+        ];
+        for &origin in &origins {
+            for &direction in &directions {
+                assert_agree(&boxes, &Ray::new(origin, direction, 0.0), 0.0, Float::MAX);
+            }
+        }
+        let r = Ray::new(
+            Vector3::new(-1.0, 0.0, 0.5),
+            Vector3::new(1.0, 0.0, 0.0),
+            0.0,
+        );
+        let (mask, entry) = boxes.hit_scalar(&SlabRay::new(&r), 0.0, Float::MAX);
+        // the ray grazes the bottom of all three boxes, the empty lane stays missed
+        assert_eq!(mask, 0b0111);
+        assert_eq!(entry[0], 1.0);
+    }
+
+    #[test]
+    fn empty_lanes_are_never_hit() {
+        let r = Ray::new(
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 1.0, 1.0),
+            0.0,
+        );
+        let (mask, _) = Aabb4::empty().hit_with(SlabTest::Simd, &SlabRay::new(&r), 0.0, Float::MAX);
+        assert_eq!(mask, 0);
+    }
+}
@@ -0,0 +1,185 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::aabb::SlabTest;
+use super::camera::Camera;
+use super::float::Float;
+use super::hittable::Hittable;
+use super::materials::lambertian::Lambertian;
+use super::objects::bvh4::Bvh4;
+use super::objects::bvh_tree::BvhTree;
+use super::objects::sphere::Sphere;
+use super::ray::Ray;
+use super::texture::solid;
+use super::vector3::{Color, Vector3};
+
+fn spheres(rng: &mut StdRng, count: usize) -> Vec<Box<dyn Hittable>> {
+    let mut list: Vec<Box<dyn Hittable>> = Vec::with_capacity(count);
+    for _ in 0..count {
+        list.push(Box::new(Sphere {
+            center: Vector3::new(
+                1000.0 * rng.gen::<Float>(),
+                1000.0 * rng.gen::<Float>(),
+                1000.0 * rng.gen::<Float>(),
+            ),
+            radius: 1.0 + 4.0 * rng.gen::<Float>(),
+            material: Arc::new(Lambertian {
+                albedo: solid(Color::new(0.5, 0.5, 0.5)),
+            }),
+        }));
+    }
+    list
+}
+
+fn trace(hierarchy: &dyn Hittable, rays: &[Ray]) -> (usize, Duration) {
+    let now = Instant::now();
+    let mut hits = 0;
+    for r in rays {
+        if hierarchy.hit(r, 0.0, Float::MAX).is_some() {
+            hits += 1;
+        }
+    }
+    (hits, now.elapsed())
+}
+
+// trace the same random rays through one Bvh4 testing its boxes with the
+// scalar loop and with SSE or AVX, so only the slab test differs, the
+// binary BvhTree is built differently and only shown for reference
+pub fn bvh() {
+    let object_count = 20_000;
+    let ray_count = 200_000;
+    let mut rng = StdRng::seed_from_u64(42);
+
+    let scalar = Bvh4::new(
+        spheres(&mut StdRng::seed_from_u64(7), object_count),
+        0.0,
+        1.0,
+    )
+    .with_slab_test(SlabTest::Scalar);
+    let simd = Bvh4::new(
+        spheres(&mut StdRng::seed_from_u64(7), object_count),
+        0.0,
+        1.0,
+    );
+    let binary = BvhTree::new(
+        spheres(&mut StdRng::seed_from_u64(7), object_count),
+        0.0,
+        1.0,
+    );
+
+    let rays: Vec<Ray> = (0..ray_count)
+        .map(|_| {
+            let origin = Vector3::new(
+                1000.0 * rng.gen::<Float>(),
+                1000.0 * rng.gen::<Float>(),
+                1000.0 * rng.gen::<Float>(),
+            );
+            let direction = Vector3::new(
+                rng.gen::<Float>() - 0.5,
+                rng.gen::<Float>() - 0.5,
+                rng.gen::<Float>() - 0.5,
+            );
+            Ray::new(origin, direction, 0.0)
+        })
+        .collect();
+
+    let (scalar_hits, scalar_time) = trace(&scalar, &rays);
+    let (simd_hits, simd_time) = trace(&simd, &rays);
+    let (binary_hits, binary_time) = trace(&binary, &rays);
+
+    println!("{} spheres, {} rays", object_count, ray_count);
+    println!(
+        "scalar: {} hits in {}ms",
+        scalar_hits,
+        scalar_time.as_millis()
+    );
+    println!("SIMD:   {} hits in {}ms", simd_hits, simd_time.as_millis());
+    println!(
+        "speedup: {:.2}x",
+        scalar_time.as_secs_f64() / simd_time.as_secs_f64()
+    );
+    println!(
+        "BvhTree: {} hits in {}ms",
+        binary_hits,
+        binary_time.as_millis()
+    );
+}
+
+// primary visibility of the sphere field through a camera, traced one ray
+// at a time and as packets of 8x8 pixel tiles
+pub fn packets() {
+    let object_count = 20_000;
+    let (width, height) = (512, 512);
+    let tile = 8;
+    let wide = Bvh4::new(
+        spheres(&mut StdRng::seed_from_u64(7), object_count),
+        0.0,
+        1.0,
+    );
+    let camera = Camera::new(
+        Vector3::new(-500.0, 500.0, -500.0),
+        Vector3::new(500.0, 500.0, 500.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        40.0,
+        1.0,
+        0.0,
+        10.0,
+        0.0,
+        1.0,
+    );
+
+    let mut tiles: Vec<Vec<Ray>> = Vec::new();
+    for tile_y in (0..height).step_by(tile) {
+        for tile_x in (0..width).step_by(tile) {
+            let mut rays = Vec::with_capacity(tile * tile);
+            for y in tile_y..tile_y + tile {
+                for x in tile_x..tile_x + tile {
+                    rays.push(camera.get_ray(
+                        (x as Float + 0.5) / width as Float,
+                        (y as Float + 0.5) / height as Float,
+                    ));
+                }
+            }
+            tiles.push(rays);
+        }
+    }
+    let rays: Vec<Ray> = tiles
+        .iter()
+        .flatten()
+        .map(|r| Ray::new(r.a, r.b, r.time))
+        .collect();
+
+    let (single_hits, single_time) = trace(&wide, &rays);
+    let now = Instant::now();
+    let mut packet_hits = 0;
+    for tile_rays in &tiles {
+        let mut t_max = vec![Float::MAX; tile_rays.len()];
+        packet_hits += wide
+            .hit_packet(tile_rays, 0.0, &mut t_max)
+            .iter()
+            .filter(|hit| hit.is_some())
+            .count();
+    }
+    let packet_time = now.elapsed();
+
+    println!(
+        "{} spheres, {}x{} primary rays",
+        object_count, width, height
+    );
+    println!(
+        "single: {} hits in {}ms",
+        single_hits,
+        single_time.as_millis()
+    );
+    println!(
+        "packet: {} hits in {}ms",
+        packet_hits,
+        packet_time.as_millis()
+    );
+    println!(
+        "speedup: {:.2}x",
+        single_time.as_secs_f64() / packet_time.as_secs_f64()
+    );
+}
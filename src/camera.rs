@@ -1,13 +1,14 @@
-use super::ray::Ray;
+use super::float::consts;
+use super::float::Float;
+use super::ray::{Differentials, Ray};
 use super::vector3::{cross, dot, unit_vector, Vector3};
 use rand::Rng;
-use std::f32::consts;
 
 fn random_in_unit_disk() -> Vector3 {
     let mut p: Vector3;
     let mut rng = rand::thread_rng();
     loop {
-        p = 2.0 * Vector3::new(rng.gen::<f32>(), rng.gen::<f32>(), 0.0)
+        p = 2.0 * Vector3::new(rng.gen::<Float>(), rng.gen::<Float>(), 0.0)
             - Vector3::new(1.0, 1.0, 0.0);
         if dot(p, p) < 1.0 {
             return p;
@@ -22,30 +23,32 @@ pub struct Camera {
     vertical: Vector3,
     u: Vector3,
     v: Vector3,
-    w: Vector3,
-    lens_radius: f32,
-    time0: f32,
-    time1: f32,
+    lens_radius: Float,
+    time0: Float,
+    time1: Float,
 }
 
 impl Camera {
     /**
      * vup: top to bottom in degrees
      */
+    // the lens and shutter each take a pair of parameters, grouping them
+    // would only move the argument list into a struct literal
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         lookfrom: Vector3,
         lookat: Vector3,
         vup: Vector3,
-        vfov: f32,
-        aspect: f32,
-        aperture: f32,
-        focus_dist: f32,
-        time0: f32,
-        time1: f32,
+        vfov: Float,
+        aspect: Float,
+        aperture: Float,
+        focus_dist: Float,
+        time0: Float,
+        time1: Float,
     ) -> Camera {
-        let theta: f32 = vfov * consts::PI / 180.0;
-        let half_height: f32 = (theta / 2.0).tan();
-        let half_width: f32 = aspect * half_height;
+        let theta: Float = vfov * consts::PI / 180.0;
+        let half_height: Float = (theta / 2.0).tan();
+        let half_width: Float = aspect * half_height;
         let w = unit_vector(lookfrom - lookat);
         let u = unit_vector(cross(vup, w));
         let v = cross(w, u);
@@ -61,22 +64,36 @@ impl Camera {
             vertical: 2.0 * half_height * focus_dist * v,
             u,
             v,
-            w,
             lens_radius: aperture / 2.0,
             time0,
             time1,
         }
     }
 
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
+    pub fn get_ray(&self, s: Float, t: Float) -> Ray {
         let mut rng = rand::thread_rng();
         let rd: Vector3 = self.lens_radius * random_in_unit_disk();
         let offset: Vector3 = self.u * rd.x() + self.v * rd.y();
-        let time: f32 = self.time0 + rng.gen::<f32>() * (self.time1 - self.time0);
-        Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
-            time,
-        )
+        let time: Float = self.time0 + rng.gen::<Float>() * (self.time1 - self.time0);
+        let origin = self.origin + offset;
+        Ray::new(origin, self.through(s, t) - origin, time)
+    }
+
+    // with rays from the same point on the lens through the points `ds`
+    // and `dt` away, the distances to the next pixels
+    pub fn get_ray_differential(&self, s: Float, t: Float, ds: Float, dt: Float) -> Ray {
+        let ray = self.get_ray(s, t);
+        let origin = ray.origin();
+        ray.with_differentials(Some(Differentials {
+            rx_origin: origin,
+            rx_direction: self.through(s + ds, t) - origin,
+            ry_origin: origin,
+            ry_direction: self.through(s, t + dt) - origin,
+        }))
+    }
+
+    // the point on the plane in focus
+    fn through(&self, s: Float, t: Float) -> Vector3 {
+        self.lower_left_corner + s * self.horizontal + t * self.vertical
     }
 }
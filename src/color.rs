@@ -0,0 +1,146 @@
+use std::sync::OnceLock;
+
+use super::vector3::Color;
+
+// the RGB primaries light is rendered in, colors in scenes are given in
+// it and images are converted into it when they are loaded
+#[derive(Copy, Clone)]
+pub enum ColorSpace {
+    // the primaries of sRGB and of most images and displays
+    Rec709,
+    // the wider gamut of ACES, mixing colors in it is closer to mixing spectra
+    AcesCg,
+}
+
+// linear Rec.709 to ACEScg with the D65 to D60 Bradford adaptation
+const REC709_TO_ACESCG: [[f32; 3]; 3] = [
+    [0.613_097_4, 0.339_523_1, 0.047_379_5],
+    [0.070_193_7, 0.916_353_9, 0.013_452_4],
+    [0.020_615_6, 0.109_569_8, 0.869_815_1],
+];
+
+const ACESCG_TO_REC709: [[f32; 3]; 3] = [
+    [1.705_051, -0.621_792_1, -0.083_259],
+    [-0.130_256_4, 1.140_804_8, -0.010_548_5],
+    [-0.024_003_4, -0.128_969, 1.152_972_4],
+];
+
+fn transform(m: &[[f32; 3]; 3], c: Color) -> Color {
+    let row = |r: &[f32; 3]| r[0] * c.r() + r[1] * c.g() + r[2] * c.b();
+    Color::new(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+impl ColorSpace {
+    // a color in linear Rec.709 in this space
+    pub fn convert_from_rec709(self, c: Color) -> Color {
+        match self {
+            ColorSpace::Rec709 => c,
+            ColorSpace::AcesCg => transform(&REC709_TO_ACESCG, c),
+        }
+    }
+
+    // a color in this space in linear Rec.709, for display
+    pub fn convert_to_rec709(self, c: Color) -> Color {
+        match self {
+            ColorSpace::Rec709 => c,
+            ColorSpace::AcesCg => transform(&ACESCG_TO_REC709, c),
+        }
+    }
+}
+
+static WORKING_SPACE: OnceLock<ColorSpace> = OnceLock::new();
+
+// picks the working space, only before anything is loaded or rendered
+pub fn set_working_space(space: ColorSpace) {
+    WORKING_SPACE
+        .set(space)
+        .unwrap_or_else(|_| panic!("the working color space is already in use"));
+}
+
+// linear Rec.709 unless picked otherwise
+pub fn working_space() -> ColorSpace {
+    *WORKING_SPACE.get_or_init(|| ColorSpace::Rec709)
+}
+
+// what the values stored in an image mean
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    // colors with the sRGB curve applied, like photos and painted albedo
+    Srgb,
+    // data used as it is, like normals, roughness or masks, which
+    // would be bent by decoding and tinted by the working space
+    Linear,
+}
+
+// the sRGB curve undone, for values in [0, 1]
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// the sRGB curve displays expect, for values in [0, 1]
+pub fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_round_trips() {
+        for i in 0..=1000 {
+            let x = i as f32 / 1000.0;
+            assert!((srgb_to_linear(linear_to_srgb(x)) - x).abs() < 1e-5);
+            assert!((linear_to_srgb(srgb_to_linear(x)) - x).abs() < 1e-5);
+        }
+        assert!(linear_to_srgb(1.0) > 0.9999 && srgb_to_linear(1.0) > 0.9999);
+    }
+
+    #[test]
+    fn srgb_is_linear_below_the_breakpoints_and_continuous_there() {
+        assert_eq!(srgb_to_linear(0.02), 0.02 / 12.92);
+        assert_eq!(linear_to_srgb(0.002), 0.002 * 12.92);
+        let eps = 1e-6;
+        assert!((srgb_to_linear(0.04045 - eps) - srgb_to_linear(0.04045 + eps)).abs() < 1e-5);
+        assert!(
+            (linear_to_srgb(0.003_130_8 - eps) - linear_to_srgb(0.003_130_8 + eps)).abs() < 1e-4
+        );
+        // the two breakpoints are the same point of the curve
+        assert!((srgb_to_linear(0.04045) - 0.003_130_8).abs() < 1e-6);
+    }
+
+    #[test]
+    fn matrices_invert_each_other() {
+        let product = |a: &[[f32; 3]; 3], b: &[[f32; 3]; 3]| {
+            let mut m = [[0.0; 3]; 3];
+            for (i, row) in m.iter_mut().enumerate() {
+                for (j, entry) in row.iter_mut().enumerate() {
+                    *entry = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+                }
+            }
+            m
+        };
+        for m in &[
+            product(&REC709_TO_ACESCG, &ACESCG_TO_REC709),
+            product(&ACESCG_TO_REC709, &REC709_TO_ACESCG),
+        ] {
+            for (i, row) in m.iter().enumerate() {
+                for (j, entry) in row.iter().enumerate() {
+                    let identity = if i == j { 1.0 } else { 0.0 };
+                    assert!((entry - identity).abs() < 1e-4, "{:?}", m);
+                }
+            }
+        }
+        // white stays white in both spaces
+        let white = ColorSpace::AcesCg.convert_from_rec709(Color::new(1.0, 1.0, 1.0));
+        assert!((white - Color::new(1.0, 1.0, 1.0)).length() < 1e-4);
+    }
+}
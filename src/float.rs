@@ -0,0 +1,18 @@
+// precision of the geometric core, the `f64` feature switches it to
+// double precision while colors keep using f32 either way
+#[cfg(not(feature = "f64"))]
+pub type Float = f32;
+#[cfg(feature = "f64")]
+pub type Float = f64;
+
+#[cfg(not(feature = "f64"))]
+pub use std::f32::consts;
+#[cfg(feature = "f64")]
+pub use std::f64::consts;
+
+// narrows a Float to the f32 colors are kept in, the cast is a no-op
+// in the default build so clippy's unnecessary_cast only applies there
+#[allow(clippy::unnecessary_cast)]
+pub fn to_f32(x: Float) -> f32 {
+    x as f32
+}
@@ -1,29 +1,367 @@
+use rand::Rng;
+
 use super::aabb::Aabb;
+use super::float::Float;
 use super::material::Material;
-use super::ray::Ray;
-use super::vector3::Vector3;
+use super::onb::ONB;
+use super::ray::{Differentials, Ray};
+use super::texture::Footprint;
+use super::utils::{next_float_down, next_float_up};
+use super::vector3::{cross, dot, unit_vector, Vector3};
 
+#[derive(Clone)]
 pub struct HitRecord {
-    pub u: f32,
-    pub v: f32,
-    pub t: f32,
+    pub u: Float,
+    pub v: Float,
+    pub t: Float,
     pub p: Vector3,
+    // the point in the space of the primitive, which transforms leave
+    // alone, so textures can stick to objects that are moved around
+    pub p_object: Vector3,
+    // conservative bound on the rounding error in each component of p
+    pub p_error: Vector3,
+    // geometric normal, always facing against the incoming ray
     pub normal: Vector3,
+    // normal used by the materials, on the same side as `normal`
+    pub shading_normal: Vector3,
+    // surface tangents along the u and v parameterization
+    pub dpdu: Vector3,
+    pub dpdv: Vector3,
+    // the tangents in the space of `p_object`, which transforms leave alone
+    pub dpdu_object: Vector3,
+    pub dpdv_object: Vector3,
+    // how the normal changes along them, zero on flat surfaces
+    pub dndu: Vector3,
+    pub dndv: Vector3,
+    // how far the hit point moves to the neighbouring pixels and what
+    // that is in texture coordinates, set from the differentials of the
+    // ray by the primitives before testing the opacity and by the integrator
+    pub dpdx: Vector3,
+    pub dpdy: Vector3,
+    pub footprint: Footprint,
+    // whether the ray hit the side the outward normal points to
+    pub front_face: bool,
+    // index of the top level object that was hit
+    pub object_id: usize,
+    // index of refraction of what surrounds the object, set by the
+    // integrator from the media the ray is in
+    pub eta_outside: Float,
+    // uniform in [0, 1), drawn for each hit so materials that pick a layer
+    // make the same pick in `is_opaque`, `scatter`, `pdf` and `eval`
+    pub sample: Float,
 }
 
 impl HitRecord {
-    pub fn new(u: f32, v: f32, t: f32, p: Vector3, normal: Vector3) -> HitRecord {
-        HitRecord { u, v, t, p, normal }
+    pub fn new(
+        u: Float,
+        v: Float,
+        t: Float,
+        p: Vector3,
+        outward_normal: Vector3,
+        dpdu: Vector3,
+        dpdv: Vector3,
+    ) -> HitRecord {
+        HitRecord {
+            u,
+            v,
+            t,
+            p,
+            p_object: p,
+            p_error: Vector3::new(0.0, 0.0, 0.0),
+            normal: outward_normal,
+            shading_normal: outward_normal,
+            dpdu,
+            dpdv,
+            dpdu_object: dpdu,
+            dpdv_object: dpdv,
+            dndu: Vector3::new(0.0, 0.0, 0.0),
+            dndv: Vector3::new(0.0, 0.0, 0.0),
+            dpdx: Vector3::new(0.0, 0.0, 0.0),
+            dpdy: Vector3::new(0.0, 0.0, 0.0),
+            footprint: Footprint::default(),
+            front_face: true,
+            object_id: 0,
+            eta_outside: 1.0,
+            sample: rand::thread_rng().gen(),
+        }
+    }
+
+    // a scattering event inside a volume, which has no surface to offset
+    // rays from, so the error stays zero and the frame just faces back
+    // along the ray for anything that wants one
+    pub fn in_medium(t: Float, p: Vector3, r: &Ray) -> HitRecord {
+        let normal = -unit_vector(r.direction());
+        let uvw = ONB::new(normal);
+        HitRecord::new(0.0, 0.0, t, p, normal, uvw.u(), uvw.v())
+    }
+
+    // whether the sample falls below the probability, with the hit for
+    // the choice and its sample stretched back over [0, 1) so nested
+    // picks stay independent of this one
+    pub fn pick(&self, probability: Float) -> (bool, HitRecord) {
+        let picked = self.sample < probability;
+        let sample = if picked {
+            self.sample / probability
+        } else {
+            (self.sample - probability) / (1.0 - probability)
+        };
+        let mut rec = self.clone();
+        rec.sample = sample.min(1.0 - Float::EPSILON);
+        (picked, rec)
+    }
+
+    // flip the normals to face the ray and remember which side was hit
+    pub fn set_face_normal(&mut self, r: &Ray) {
+        self.front_face = dot(r.direction(), self.normal) < 0.0;
+        if !self.front_face {
+            self.normal = -self.normal;
+            self.shading_normal = -self.shading_normal;
+            self.dndu = -self.dndu;
+            self.dndv = -self.dndv;
+        }
+    }
+
+    // start a new ray just outside the error bounds of the hit point,
+    // on the side of the surface the direction points to
+    pub fn spawn_ray(&self, direction: Vector3, time: Float) -> Ray {
+        let d: Float = dot(self.normal.abs(), self.p_error);
+        let mut offset: Vector3 = d * self.normal;
+        if dot(direction, self.normal) < 0.0 {
+            offset = -offset;
+        }
+        let mut origin: Vector3 = self.p + offset;
+        // round away from the surface so the offset survives the addition
+        for i in 0..3 {
+            if offset[i] > 0.0 {
+                origin[i] = next_float_up(origin[i]);
+            } else if offset[i] < 0.0 {
+                origin[i] = next_float_down(origin[i]);
+            }
+        }
+        Ray::new(origin, direction, time)
+    }
+
+    // where the rays through the neighbouring pixels meet the tangent plane
+    // and the texture coordinates there, a least squares fit of dpdx and
+    // dpdy to dpdu and dpdv in the two axes the normal is least along
+    pub fn set_footprint(&mut self, r: &Ray) {
+        let differentials = match &r.differentials {
+            Some(differentials) => differentials,
+            None => return,
+        };
+        let n = self.normal;
+        let plane = dot(n, self.p);
+        let tx = (plane - dot(n, differentials.rx_origin)) / dot(n, differentials.rx_direction);
+        let ty = (plane - dot(n, differentials.ry_origin)) / dot(n, differentials.ry_direction);
+        if !tx.is_finite() || !ty.is_finite() {
+            return;
+        }
+        self.dpdx = differentials.rx_origin + tx * differentials.rx_direction - self.p;
+        self.dpdy = differentials.ry_origin + ty * differentials.ry_direction - self.p;
+
+        let n = n.abs();
+        let (a, b) = if n.x() > n.y() && n.x() > n.z() {
+            (1, 2)
+        } else if n.y() > n.z() {
+            (0, 2)
+        } else {
+            (0, 1)
+        };
+        let determinant = self.dpdu[a] * self.dpdv[b] - self.dpdv[a] * self.dpdu[b];
+        if determinant.abs() < 1e-12 {
+            return;
+        }
+        let solve = |d: Vector3| {
+            let du = (self.dpdv[b] * d[a] - self.dpdv[a] * d[b]) / determinant;
+            let dv = (self.dpdu[a] * d[b] - self.dpdu[b] * d[a]) / determinant;
+            if du.is_finite() && dv.is_finite() {
+                (du, dv)
+            } else {
+                (0.0, 0.0)
+            }
+        };
+        let (dudx, dvdx) = solve(self.dpdx);
+        let (dudy, dvdy) = solve(self.dpdy);
+        self.footprint = Footprint {
+            dudx,
+            dvdx,
+            dudy,
+            dvdy,
+        };
+    }
+
+    // the differentials of a ray reflected or refracted at the hit, the
+    // ratio of the indices is recovered from snell's law so the materials
+    // don't have to pass it along
+    pub fn specular_differentials(&self, r: &Ray, scattered: &Ray) -> Option<Differentials> {
+        let differentials = r.differentials.as_ref()?;
+        let n = self.shading_normal;
+        let wo = -unit_vector(r.direction());
+        let wi = unit_vector(scattered.direction());
+        let cos_o = dot(wo, n);
+        let cos_i = dot(wi, n);
+        let sin_o = cross(wo, n).length();
+        let eta = if sin_o > 1e-4 {
+            cross(wi, n).length() / sin_o
+        } else {
+            1.0
+        };
+        let transfer = |direction: Vector3, du: Float, dv: Float| {
+            // change of the outgoing direction and the normal against the ray
+            let dwo = -unit_vector(direction) - wo;
+            let dn = du * self.dndu + dv * self.dndv;
+            let dcos_o = dot(dwo, n) + dot(wo, dn);
+            let dwi = if cos_o * cos_i > 0.0 {
+                -dwo + 2.0 * (dcos_o * n + cos_o * dn)
+            } else {
+                let mu = eta * cos_o - cos_i.abs();
+                let dmu = (eta - eta * eta * cos_o / cos_i.abs()) * dcos_o;
+                -eta * dwo + dmu * n + mu * dn
+            };
+            wi + dwi
+        };
+        let footprint = &self.footprint;
+        Some(Differentials {
+            rx_origin: self.p + self.dpdx,
+            rx_direction: transfer(differentials.rx_direction, footprint.dudx, footprint.dvdx),
+            ry_origin: self.p + self.dpdy,
+            ry_direction: transfer(differentials.ry_direction, footprint.dudy, footprint.dvdy),
+        })
     }
 }
 
 pub trait Hittable: Sync {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord, &Material)>;
-    fn bounding_box(&self, t0: f32, t1: f32) -> Option<Aabb>;
-    fn pdf_value(&self, o: Vector3, v: Vector3) -> f32 {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<(HitRecord, &dyn Material)>;
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb>;
+    // trace a group of rays at once, `t_max` holds the far limit of every
+    // ray and is lowered to the distance of each hit that is found
+    fn hit_packet(
+        &self,
+        rays: &[Ray],
+        t_min: Float,
+        t_max: &mut [Float],
+    ) -> Vec<Option<(HitRecord, &dyn Material)>> {
+        rays.iter()
+            .zip(t_max.iter_mut())
+            .map(|(r, t_max)| {
+                let hit = self.hit(r, t_min, *t_max);
+                if let Some((rec, _)) = &hit {
+                    *t_max = rec.t;
+                }
+                hit
+            })
+            .collect()
+    }
+    fn pdf_value(&self, _o: Vector3, _v: Vector3) -> Float {
         0.0
     }
-    fn random(&self, o: Vector3) -> Vector3 {
+    fn random(&self, _o: Vector3) -> Vector3 {
         Vector3::new(1.0, 0.0, 0.0)
     }
 }
+
+// hits the tests of materials and textures are evaluated at
+#[cfg(test)]
+pub mod fixtures {
+    use super::super::float::Float;
+    use super::super::ray::Ray;
+    use super::super::vector3::Vector3;
+    use super::HitRecord;
+
+    // at the origin facing +z, with u along x and v along y
+    pub fn flat(u: Float, v: Float) -> HitRecord {
+        let (p, normal) = (Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        let (dpdu, dpdv) = (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        HitRecord::new(u, v, 1.0, p, normal, dpdu, dpdv)
+    }
+
+    // the flat hit with the ray arriving from the direction
+    pub fn seen_from(wo: Vector3) -> (Ray, HitRecord) {
+        (Ray::new(wo, -wo, 0.0), flat(0.0, 0.0))
+    }
+
+    pub fn towards(direction: Vector3) -> Ray {
+        Ray::new(Vector3::new(0.0, 0.0, 0.0), direction, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(p: Vector3, normal: Vector3, p_error: Vector3) -> HitRecord {
+        let (dpdu, dpdv) = (Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        let mut rec = HitRecord::new(0.0, 0.0, 1.0, p, normal, dpdu, dpdv);
+        rec.p_error = p_error;
+        rec
+    }
+
+    #[test]
+    fn spawn_ray_clears_the_error_bounds() {
+        let p = Vector3::new(555.0, 200.3, 0.1);
+        let error = Vector3::new(1e-3, 1e-3, 1e-3);
+        let rec = record(p, Vector3::new(1.0, 0.0, 0.0), error);
+        let out = rec.spawn_ray(Vector3::new(1.0, 0.5, 0.0), 0.0);
+        assert!(out.origin().x() > p.x() + error.x());
+        let inside = rec.spawn_ray(Vector3::new(-1.0, 0.5, 0.0), 0.0);
+        assert!(inside.origin().x() < p.x() - error.x());
+        // only the normal direction moves
+        assert!(out.origin().y() == p.y() && out.origin().z() == p.z());
+    }
+
+    #[test]
+    fn spawn_ray_offset_along_slanted_normal() {
+        let p = Vector3::new(-3.0, 1000.0, 7.5);
+        let error = Vector3::new(2e-4, 5e-3, 1e-4);
+        let normal = unit_vector(Vector3::new(1.0, 2.0, -2.0));
+        let rec = record(p, normal, error);
+        let d = dot(normal.abs(), error);
+        let out = rec.spawn_ray(normal, 0.0);
+        assert!(dot(out.origin() - p, normal) >= d);
+        let inside = rec.spawn_ray(-normal, 0.0);
+        assert!(dot(inside.origin() - p, normal) <= -d);
+    }
+
+    #[test]
+    fn medium_hit_spawns_from_the_point() {
+        let p = Vector3::new(1.0, 2.0, 3.0);
+        let r = Ray::new(
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(0.0, 2.0, 0.0),
+            0.0,
+        );
+        let rec = HitRecord::in_medium(1.5, p, &r);
+        assert!(rec.front_face);
+        assert!(rec.p_error == Vector3::new(0.0, 0.0, 0.0));
+        assert!(rec.normal == Vector3::new(0.0, -1.0, 0.0));
+        assert!(dot(rec.dpdu, rec.normal).abs() < 1e-6 && dot(rec.dpdv, rec.normal).abs() < 1e-6);
+        for direction in &[Vector3::new(0.3, 1.0, 0.0), Vector3::new(0.3, -1.0, 0.2)] {
+            assert!(rec.spawn_ray(*direction, 0.0).origin() == p);
+        }
+    }
+
+    #[test]
+    fn pick_stretches_the_sample() {
+        let mut rec = record(
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.0, 0.0, 0.0),
+        );
+        rec.sample = 0.1;
+        let (picked, first) = rec.pick(0.4);
+        assert!(picked && (first.sample - 0.25).abs() < 1e-6);
+        rec.sample = 0.7;
+        let (picked, second) = rec.pick(0.4);
+        assert!(!picked && (second.sample - 0.5).abs() < 1e-6);
+        // never picked with no chance, always with certainty
+        assert!(!rec.pick(0.0).0 && rec.pick(1.0).0);
+    }
+
+    #[test]
+    fn spawn_ray_without_error_starts_at_the_point() {
+        let p = Vector3::new(1.0, 2.0, 3.0);
+        let rec = record(p, Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, 0.0));
+        let out = rec.spawn_ray(Vector3::new(0.0, 0.0, 1.0), 0.0);
+        assert!(out.origin() == p);
+    }
+}
@@ -0,0 +1,30 @@
+extern crate image;
+extern crate rand;
+
+pub mod aabb;
+pub mod bench;
+pub mod camera;
+pub mod color;
+pub mod float;
+pub mod hittable;
+pub mod material;
+pub mod materials;
+pub mod medium;
+pub mod microfacet;
+pub mod modifiers;
+pub mod objects;
+pub mod onb;
+pub mod pdf;
+pub mod perlin;
+pub mod post;
+pub mod ray;
+pub mod spectrum;
+pub mod texture;
+pub mod textures;
+pub mod thin_film;
+pub mod utils;
+pub mod vector3;
+
+// what a program needs to describe its own materials to the registry
+pub use material::Material;
+pub use materials::registry::{MaterialRegistry, Params};
@@ -3,136 +3,446 @@ extern crate image;
 extern crate num_cpus;
 extern crate rand;
 
-use image::GenericImageView;
 use rand::Rng;
+use std::sync::Arc;
 use std::time::Instant;
 
-mod aabb;
-mod camera;
-mod hittable;
-mod material;
-mod modifiers;
-mod objects;
-mod onb;
-mod pdf;
-mod perlin;
-mod ray;
-mod texture;
-mod utils;
-mod vector3;
+use raytracing_one_weekend::{
+    bench, camera, color, float, hittable, material, materials, medium, modifiers, objects, pdf,
+    perlin, post, ray, spectrum, texture, textures, thin_film, utils, vector3,
+};
 
 use camera::Camera;
-use hittable::Hittable;
-use material::Material;
+use color::{ColorSpace, Encoding};
+use float::{to_f32, Float};
+use hittable::{HitRecord, Hittable};
+use material::{ComplexIor, Material};
+use materials::cloth::Cloth;
+use materials::coated::Coated;
+use materials::cutout::Cutout;
+use materials::dielectric::{Dielectric, Dispersion};
+use materials::diffuse_light::DiffuseLight;
+use materials::diffuse_transmission::DiffuseTransmission;
+use materials::isotropic::Isotropic;
+use materials::lambertian::Lambertian;
+use materials::metal::Metal;
+use materials::mix::Mix;
+use materials::oren_nayar::OrenNayar;
+use materials::principled::Principled;
+use materials::registry::MaterialRegistry;
+use materials::subsurface::Subsurface;
+use medium::{Flight, MediumStack};
+use modifiers::bump_map::BumpMap;
 use modifiers::flip_normals::FlipNormals;
+use modifiers::normal_map::NormalMap;
 use modifiers::rotate::RotateY;
 use modifiers::translate::Translate;
-use objects::bvh_tree::BvhTree;
-use objects::constant_medium::ConstantMedium;
+use objects::bvh4::Bvh4;
 use objects::cube::Cube;
-use objects::moving_sphere::MovingSphere;
 use objects::object_list::ObjectList;
 use objects::plane::{XYRect, XZRect, YZRect};
 use objects::sphere::Sphere;
 use pdf::{HittablePDF, MixturePDF, PDF};
 use perlin::Perlin;
+use post::{PostProcess, ToneMapper};
 use ray::Ray;
-use texture::Texture;
-use utils::{clamp, de_nan, random_cosine_direction, random_on_unit_sphere, random_to_sphere};
-use vector3::Vector3;
+use spectrum::{white_response, Wavelengths};
+use texture::{constant, solid};
+use textures::cache;
+use textures::checker::CheckerTexture;
+use textures::image_texture::ImageTexture;
+use textures::mapping::{MappedTexture, Mapping, UvTransform};
+use textures::nodes::ColorRamp;
+use textures::noise::NoiseTexture;
+use thin_film::ThinFilm;
+use utils::{de_nan, random_on_unit_sphere};
+use vector3::{dot, Color, Vector3};
+
+// width and height in pixels of the tiles traced as ray packets
+const PACKET_TILE: usize = 8;
+// longest random walk through a scattering medium, in collisions
+const MAX_COLLISIONS: usize = 10000;
+
+fn cornell_box(aspect: Float) -> Scene {
+    let glass: Arc<dyn Material> = Arc::new(Dielectric {
+        ref_idx: 1.5,
+        roughness: 0.0,
+        absorption: Color::new(0.0, 0.0, 0.0),
+        priority: 0,
+        film: None,
+        dispersion: None,
+    });
+    let (object_list, mut lights, camera) = cornell_box_with_sphere(aspect, glass.clone());
+    // the glass sphere focuses the light into a caustic, which
+    // is only found by aiming at the sphere as well
+    lights.push(Box::new(Sphere {
+        center: Vector3::new(190.0, 90.0, 190.0),
+        radius: 90.0,
+        material: glass,
+    }));
+    (object_list, lights, camera)
+}
 
-fn random_scene() -> Vec<Box<Hittable>> {
-    let n: usize = 500;
-    let mut object_list: Vec<Box<Hittable>> = Vec::with_capacity(n + 1);
-    let light_material = Material::DiffuseLight {
-        emit: Texture::ConstantTexture {
-            color: Vector3::new(4.0, 4.0, 4.0),
-        },
-    };
+// the cornell box with its glass sphere frosted
+fn frosted_cornell_box(aspect: Float) -> Scene {
+    cornell_box_with_sphere(
+        aspect,
+        Arc::new(Dielectric {
+            ref_idx: 1.5,
+            roughness: 0.3,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            priority: 0,
+            film: None,
+            dispersion: None,
+        }),
+    )
+}
 
-    object_list.push(Box::new(Sphere {
-        center: Vector3::new(0.0, -1000.0, 0.0),
-        radius: 1000.0,
-        material: Material::Lambertian {
-            albedo: Texture::ConstantTexture {
-                color: Vector3::new(0.7, 0.7, 0.7),
-            },
-        },
-    }));
-    // object_list.push(Box::new(Sphere {
-    //     center: Vector3::new(0.0, 1.0, 0.0),
-    //     radius: 1.0,
-    //     material: Material::Lambertian {
-    //         albedo: Texture::NoiseTexture { noise: Perlin::new(), scale: 5.0 },
-    //     },
-    // }));
-    object_list.push(Box::new(Sphere {
-        center: Vector3::new(3.0, 1.0, 3.0),
-        radius: 1.0,
-        material: Material::DiffuseLight {
-            emit: Texture::ConstantTexture {
-                color: Vector3::new(4.0, 4.0, 4.0),
-            },
-        },
-    }));
-    let img = image::open("pug.jpg").unwrap();
-    let (nx, ny) = img.dimensions();
+// the cornell box with a sphere of tinted ice holding air bubbles, the
+// bubbles have a higher priority so they carve holes out of the ice
+fn ice_cornell_box(aspect: Float) -> Scene {
+    let (mut object_list, lights, camera) = cornell_box_with_sphere(
+        aspect,
+        Arc::new(Dielectric {
+            ref_idx: 1.31,
+            roughness: 0.0,
+            absorption: Color::new(0.006, 0.002, 0.001),
+            priority: 1,
+            film: None,
+            dispersion: None,
+        }),
+    );
+    let air = Arc::new(Dielectric {
+        ref_idx: 1.0,
+        roughness: 0.0,
+        absorption: Color::new(0.0, 0.0, 0.0),
+        priority: 2,
+        film: None,
+        dispersion: None,
+    });
+    let mut rng = rand::thread_rng();
+    for _ in 0..12 {
+        let offset = 55.0 * rng.gen::<Float>() * random_on_unit_sphere();
+        object_list.push(Box::new(Sphere {
+            center: Vector3::new(190.0, 90.0, 190.0) + offset,
+            radius: 6.0 + 12.0 * rng.gen::<Float>(),
+            material: air.clone(),
+        }));
+    }
+    (object_list, lights, camera)
+}
+
+// the cornell box with a clearcoated red plastic sphere
+fn principled_cornell_box(aspect: Float) -> Scene {
+    cornell_box_with_sphere(
+        aspect,
+        Arc::new(Principled {
+            roughness: constant(0.4),
+            clearcoat: constant(1.0),
+            ..Principled::new(solid(Color::new(0.7, 0.1, 0.1)))
+        }),
+    )
+}
+
+// the cornell box with a sphere of red paint under a clear varnish
+fn coated_cornell_box(aspect: Float) -> Scene {
+    cornell_box_with_sphere(
+        aspect,
+        Arc::new(Coated {
+            base: Arc::new(Lambertian {
+                albedo: solid(Color::new(0.6, 0.05, 0.05)),
+            }),
+            ref_idx: 1.5,
+            roughness: 0.0,
+            tint: Color::new(1.0, 1.0, 1.0),
+        }),
+    )
+}
+
+// the cornell box with a rough iron sphere streaked with rust
+fn rusty_cornell_box(aspect: Float) -> Scene {
+    cornell_box_with_sphere(
+        aspect,
+        Arc::new(Mix {
+            a: Arc::new(Metal {
+                ior: ComplexIor::IRON,
+                roughness_u: 0.2,
+                roughness_v: 0.2,
+                film: None,
+            }),
+            b: Arc::new(Lambertian {
+                albedo: solid(Color::new(0.45, 0.18, 0.06)),
+            }),
+            mask: Arc::new(NoiseTexture {
+                noise: Perlin::new(),
+                scale: 0.05,
+            }),
+        }),
+    )
+}
+
+// the cornell box with a sphere of candle wax, red light
+// travels the furthest through it before being absorbed
+fn subsurface_cornell_box(aspect: Float) -> Scene {
+    cornell_box_with_sphere(
+        aspect,
+        Arc::new(Subsurface {
+            albedo: Color::new(0.999, 0.995, 0.98),
+            mean_free_path: Color::new(8.0, 5.0, 3.5),
+            ref_idx: 1.45,
+            roughness: 0.0,
+            priority: 0,
+        }),
+    )
+}
+
+// the cornell box with a gold sphere hammered by a noise bump map
+fn bumpy_cornell_box(aspect: Float) -> Scene {
+    cornell_box_with_object(
+        aspect,
+        Box::new(BumpMap {
+            object: Box::new(Sphere {
+                center: Vector3::new(190.0, 90.0, 190.0),
+                radius: 90.0,
+                material: Arc::new(Metal {
+                    ior: ComplexIor::GOLD,
+                    roughness_u: 0.1,
+                    roughness_v: 0.1,
+                    film: None,
+                }),
+            }),
+            height: Arc::new(NoiseTexture {
+                noise: Perlin::new(),
+                scale: 0.05,
+            }),
+            scale: 0.5,
+        }),
+    )
+}
 
+// the cornell box with a soap bubble, the thickness of its film swirling
+// with noise, and a ball of anodized titanium
+fn bubble_cornell_box(aspect: Float) -> Scene {
+    let (mut object_list, lights, camera) = cornell_box_with_sphere(
+        aspect,
+        Arc::new(Dielectric {
+            ref_idx: 1.0,
+            roughness: 0.0,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            priority: 0,
+            film: Some(ThinFilm {
+                thickness: Arc::new(NoiseTexture {
+                    noise: Perlin::new(),
+                    scale: 0.02,
+                }),
+                scale: 800.0,
+                ref_idx: 1.33,
+            }),
+            dispersion: None,
+        }),
+    );
     object_list.push(Box::new(Sphere {
-        center: Vector3::new(0.0, 1.0, 0.0),
-        radius: 1.0,
-        material: Material::Lambertian {
-            albedo: Texture::ImageTexture { img },
-        },
+        center: Vector3::new(400.0, 60.0, 150.0),
+        radius: 60.0,
+        material: Arc::new(Metal {
+            ior: ComplexIor::from_reflectivity(
+                Color::new(0.54, 0.5, 0.45),
+                Color::new(0.6, 0.58, 0.55),
+            ),
+            roughness_u: 0.05,
+            roughness_v: 0.05,
+            film: Some(ThinFilm::uniform(60.0, 2.4)),
+        }),
     }));
+    (object_list, lights, camera)
+}
 
-    let light = Material::DiffuseLight {
-        emit: Texture::ConstantTexture {
-            color: Vector3::new(4.0, 4.0, 4.0),
-        },
-    };
+// the cornell box with a sphere of deep red velvet
+fn velvet_cornell_box(aspect: Float) -> Scene {
+    cornell_box_with_sphere(
+        aspect,
+        Arc::new(Cloth {
+            albedo: solid(Color::new(0.3, 0.02, 0.04)),
+            sheen: solid(Color::new(1.0, 0.6, 0.6)),
+            roughness: constant(0.4),
+        }),
+    )
+}
 
+// the cornell box with a polished marble ball, the veins are
+// a noise fed through a color ramp
+fn marble_cornell_box(aspect: Float) -> Scene {
+    let veins = ColorRamp::new(
+        Arc::new(NoiseTexture {
+            noise: Perlin::new(),
+            scale: 0.05,
+        }),
+        vec![
+            (0.0, Color::new(0.15, 0.17, 0.16)),
+            (0.12, Color::new(0.55, 0.57, 0.55)),
+            (0.3, Color::new(0.85, 0.84, 0.8)),
+            (1.0, Color::new(0.9, 0.89, 0.86)),
+        ],
+    )
+    .unwrap();
+    cornell_box_with_sphere(
+        aspect,
+        Arc::new(Principled {
+            roughness: constant(0.3),
+            clearcoat: constant(1.0),
+            ..Principled::new(Arc::new(veins))
+        }),
+    )
+}
+
+// the cornell box with a ball checkered by projecting
+// the checks along each axis and blending them
+fn checker_cornell_box(aspect: Float) -> Scene {
+    let checker = Arc::new(CheckerTexture {
+        even: solid(Color::new(0.8, 0.8, 0.8)),
+        odd: solid(Color::new(0.1, 0.2, 0.5)),
+        frequency: 1.0,
+    });
+    cornell_box_with_sphere(
+        aspect,
+        Arc::new(Lambertian {
+            albedo: Arc::new(MappedTexture {
+                texture: checker,
+                mapping: Mapping::Triplanar { sharpness: 4.0 },
+                transform: UvTransform {
+                    scale: (1.0 / 40.0, 1.0 / 40.0),
+                    rotation: 30.0,
+                    offset: (0.0, 0.0),
+                },
+            }),
+        }),
+    )
+}
+
+// the cornell box with a diamond ball, which splits the
+// light into colors when rendered with `--spectral`
+fn diamond_cornell_box(aspect: Float) -> Scene {
+    cornell_box_with_sphere(
+        aspect,
+        Arc::new(Dielectric {
+            ref_idx: 2.417,
+            roughness: 0.0,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            priority: 0,
+            film: None,
+            dispersion: Some(Dispersion::DIAMOND),
+        }),
+    )
+}
+
+// the cornell box seen through a wire fence cut out of a single rectangle
+fn fence_cornell_box(aspect: Float) -> Scene {
+    let (mut object_list, lights, camera) = cornell_box(aspect);
+    // diagonal wires, transparent everywhere else
+    let img = image::RgbaImage::from_fn(512, 512, |x, y| {
+        let wire = (x + y) % 64 < 6 || (x + 512 - y) % 64 < 6;
+        if wire {
+            image::Rgba([150, 150, 160, 255])
+        } else {
+            image::Rgba([0, 0, 0, 0])
+        }
+    });
+    let fence = Arc::new(ImageTexture::new(image::DynamicImage::ImageRgba8(img)));
     object_list.push(Box::new(XYRect {
-        x0: -2.0,
-        x1: 2.0,
-        y0: 1.0,
-        y1: 3.0,
-        k: -3.0,
-        material: light,
+        x0: 0.0,
+        x1: 555.0,
+        y0: 0.0,
+        y1: 555.0,
+        k: 100.0,
+        material: Arc::new(Cutout {
+            base: Arc::new(Lambertian {
+                albedo: fence.clone(),
+            }),
+            opacity: fence,
+            threshold: Some(0.5),
+        }),
     }));
+    (object_list, lights, camera)
+}
+
+// the cornell box with a rough clay sphere, lit through a sheet
+// of paper hung under the light
+fn paper_cornell_box(aspect: Float) -> Scene {
+    let (mut object_list, mut lights, camera) = cornell_box_with_sphere(
+        aspect,
+        Arc::new(OrenNayar {
+            albedo: solid(Color::new(0.6, 0.42, 0.3)),
+            sigma: 30.0,
+        }),
+    );
+    let paper = || XZRect {
+        x0: 163.0,
+        x1: 393.0,
+        z0: 177.0,
+        z1: 382.0,
+        k: 480.0,
+        material: Arc::new(DiffuseTransmission {
+            reflectance: constant(0.35),
+            transmittance: constant(0.55),
+        }),
+    };
+    object_list.push(Box::new(paper()));
+    // the light only reaches the box through the paper, so light sampling
+    // aims at the paper from below and at the light from the paper
+    lights.push(Box::new(paper()));
+    (object_list, lights, camera)
+}
 
-    object_list
+// the objects, the ones light sampling aims at and the camera
+type Scene = (Vec<Box<dyn Hittable>>, Vec<Box<dyn Hittable>>, Camera);
+type SceneBuilder = fn(Float) -> Scene;
+
+// the reference scenes `--scene <name>` picks from
+const SCENES: &[(&str, SceneBuilder)] = &[
+    ("cornell", cornell_box),
+    ("frosted", frosted_cornell_box),
+    ("ice", ice_cornell_box),
+    ("principled", principled_cornell_box),
+    ("coated", coated_cornell_box),
+    ("rusty", rusty_cornell_box),
+    ("subsurface", subsurface_cornell_box),
+    ("bumpy", bumpy_cornell_box),
+    ("bubble", bubble_cornell_box),
+    ("velvet", velvet_cornell_box),
+    ("marble", marble_cornell_box),
+    ("checker", checker_cornell_box),
+    ("diamond", diamond_cornell_box),
+    ("fence", fence_cornell_box),
+    ("paper", paper_cornell_box),
+];
+
+fn cornell_box_with_sphere(aspect: Float, sphere_material: Arc<dyn Material>) -> Scene {
+    cornell_box_with_object(
+        aspect,
+        Box::new(Sphere {
+            center: Vector3::new(190.0, 90.0, 190.0),
+            radius: 90.0,
+            material: sphere_material,
+        }),
+    )
 }
 
-fn cornell_box(aspect: f32) -> (Vec<Box<Hittable>>, Camera) {
+// the cornell box with the object in place of the sphere
+fn cornell_box_with_object(aspect: Float, object: Box<dyn Hittable>) -> Scene {
     let n: usize = 500;
-    let mut object_list: Vec<Box<Hittable>> = Vec::with_capacity(n + 1);
-    let red: Material = Material::Lambertian {
-        albedo: Texture::ConstantTexture {
-            color: Vector3::new(0.65, 0.05, 0.05),
-        },
-    };
-    let white: Material = Material::Lambertian {
-        albedo: Texture::ConstantTexture {
-            color: Vector3::new(0.73, 0.73, 0.73),
-        },
-    };
-    let green: Material = Material::Lambertian {
-        albedo: Texture::ConstantTexture {
-            color: Vector3::new(0.12, 0.45, 0.15),
-        },
-    };
-    let light: Material = Material::DiffuseLight {
-        emit: Texture::ConstantTexture {
-            color: Vector3::new(15.0, 15.0, 15.0),
-        },
-    };
-    let aluminium: Material = Material::Metal {
-        albedo: Texture::ConstantTexture {
-            color: Vector3::new(0.8, 0.85, 0.88),
-        },
-        fuzz: 0.0,
-    };
+    let mut object_list: Vec<Box<dyn Hittable>> = Vec::with_capacity(n + 1);
+    let red: Arc<dyn Material> = Arc::new(Lambertian {
+        albedo: solid(Color::new(0.65, 0.05, 0.05)),
+    });
+    let white: Arc<dyn Material> = Arc::new(Lambertian {
+        albedo: solid(Color::new(0.73, 0.73, 0.73)),
+    });
+    let green: Arc<dyn Material> = Arc::new(Lambertian {
+        albedo: solid(Color::new(0.12, 0.45, 0.15)),
+    });
+    let light: Arc<dyn Material> = Arc::new(DiffuseLight {
+        emit: solid(Color::new(15.0, 15.0, 15.0)),
+    });
 
     object_list.push(Box::new(FlipNormals::new(Box::new(YZRect {
         y0: 0.0,
@@ -150,14 +460,16 @@ fn cornell_box(aspect: f32) -> (Vec<Box<Hittable>>, Camera) {
         k: 0.0,
         material: red,
     }));
-    object_list.push(Box::new(FlipNormals::new(Box::new(XZRect {
+    let ceiling_light = || XZRect {
         x0: 213.0,
         x1: 343.0,
         z0: 227.0,
         z1: 332.0,
         k: 554.0,
-        material: light,
-    }))));
+        material: light.clone(),
+    };
+    object_list.push(Box::new(FlipNormals::new(Box::new(ceiling_light()))));
+    let lights: Vec<Box<dyn Hittable>> = vec![Box::new(ceiling_light())];
     object_list.push(Box::new(FlipNormals::new(Box::new(XZRect {
         x0: 0.0,
         x1: 555.0,
@@ -182,22 +494,7 @@ fn cornell_box(aspect: f32) -> (Vec<Box<Hittable>>, Camera) {
         k: 555.0,
         material: white.clone(),
     }))));
-    // object_list.push(Box::new(Translate {
-    //     object: Box::new(RotateY::new(
-    //         Box::new(Cube::new(
-    //             Vector3::new(0.0, 0.0, 0.0),
-    //             Vector3::new(165.0, 165.0, 165.0),
-    //             white,
-    //         )),
-    //         -18.0,
-    //     )),
-    //     offset: Vector3::new(130.0, 0.0, 65.0),
-    // }));
-    object_list.push(Box::new(Sphere {
-        center: Vector3::new(190.0, 90.0, 190.0),
-        radius: 90.0,
-        material: Material::Dielectric { ref_idx: 1.5 },
-    }));
+    object_list.push(object);
     object_list.push(Box::new(Translate {
         object: Box::new(RotateY::new(
             Box::new(Cube::new(
@@ -222,399 +519,283 @@ fn cornell_box(aspect: f32) -> (Vec<Box<Hittable>>, Camera) {
         1.0,
     );
 
-    (object_list, cornell_camera)
+    (object_list, lights, cornell_camera)
 }
 
-fn cornell_smoke() -> Vec<Box<Hittable>> {
-    let n: usize = 500;
-    let mut object_list: Vec<Box<Hittable>> = Vec::with_capacity(n + 1);
-    let red: Material = Material::Lambertian {
-        albedo: Texture::ConstantTexture {
-            color: Vector3::new(0.65, 0.05, 0.05),
-        },
-    };
-    let white: Material = Material::Lambertian {
-        albedo: Texture::ConstantTexture {
-            color: Vector3::new(0.73, 0.73, 0.73),
-        },
-    };
-    let green: Material = Material::Lambertian {
-        albedo: Texture::ConstantTexture {
-            color: Vector3::new(0.12, 0.45, 0.15),
-        },
-    };
-    let light: Material = Material::DiffuseLight {
-        emit: Texture::ConstantTexture {
-            color: Vector3::new(7.0, 7.0, 7.0),
-        },
-    };
-
-    object_list.push(Box::new(FlipNormals::new(Box::new(YZRect {
-        y0: 0.0,
-        y1: 555.0,
-        z0: 0.0,
-        z1: 555.0,
-        k: 555.0,
-        material: green,
-    }))));
-    object_list.push(Box::new(YZRect {
-        y0: 0.0,
-        y1: 555.0,
-        z0: 0.0,
-        z1: 555.0,
-        k: 0.0,
-        material: red,
-    }));
-    object_list.push(Box::new(XZRect {
-        x0: 113.0,
-        x1: 443.0,
-        z0: 127.0,
-        z1: 432.0,
-        k: 554.0,
-        material: light,
-    }));
-    object_list.push(Box::new(FlipNormals::new(Box::new(XZRect {
-        x0: 0.0,
-        x1: 555.0,
-        z0: 0.0,
-        z1: 555.0,
-        k: 555.0,
-        material: white.clone(),
-    }))));
-    object_list.push(Box::new(XZRect {
-        x0: 0.0,
-        x1: 555.0,
-        z0: 0.0,
-        z1: 555.0,
-        k: 0.0,
-        material: white.clone(),
-    }));
-    object_list.push(Box::new(FlipNormals::new(Box::new(XYRect {
-        x0: 0.0,
-        x1: 555.0,
-        y0: 0.0,
-        y1: 555.0,
-        k: 555.0,
-        material: white.clone(),
-    }))));
-    let box1: Translate = Translate {
-        object: Box::new(RotateY::new(
-            Box::new(Cube::new(
-                Vector3::new(0.0, 0.0, 0.0),
-                Vector3::new(165.0, 165.0, 165.0),
-                white.clone(),
-            )),
-            -18.0,
-        )),
-        offset: Vector3::new(130.0, 0.0, 65.0),
-    };
-    let box2: Translate = Translate {
-        object: Box::new(RotateY::new(
-            Box::new(Cube::new(
-                Vector3::new(0.0, 0.0, 0.0),
-                Vector3::new(165.0, 330.0, 165.0),
-                white.clone(),
-            )),
-            15.0,
-        )),
-        offset: Vector3::new(265.0, 0.0, 295.0),
-    };
-    object_list.push(Box::new(ConstantMedium::new(
-        Box::new(box1),
-        0.01,
-        Texture::ConstantTexture {
-            color: Vector3::new(1.0, 1.0, 1.0),
-        },
-    )));
-    object_list.push(Box::new(ConstantMedium::new(
-        Box::new(box2),
-        0.01,
-        Texture::ConstantTexture {
-            color: Vector3::new(0.0, 0.0, 0.0),
-        },
-    )));
-    object_list
+fn average(c: Color) -> f32 {
+    (c.r() + c.g() + c.b()) / 3.0
 }
 
-fn random_scene2() -> Vec<Box<Hittable>> {
-    let n: usize = 500;
-    let mut object_list: Vec<Box<Hittable>> = Vec::with_capacity(n + 1);
-    let mut rng = rand::thread_rng();
+fn color(
+    r: &Ray,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    depth: usize,
+    media: &MediumStack,
+) -> Color {
+    // spawned rays start outside the error bounds of the surface
+    // they leave, so there is no need to skip the first bit of the ray
+    shade(
+        r,
+        world.hit(r, 0.0, Float::MAX),
+        world,
+        lights,
+        depth,
+        media,
+    )
+}
 
-    for a in -10..10 {
-        for b in -10..10 {
-            let choose_mat: f32 = rng.gen::<f32>();
-            let center: Vector3 = Vector3::new(
-                a as f32 + 0.9 * rng.gen::<f32>(),
-                0.2,
-                b as f32 + 0.9 * rng.gen::<f32>(),
-            );
-            if (center - Vector3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                if choose_mat < 0.8 {
-                    object_list.push(Box::new(MovingSphere {
-                        center0: center,
-                        center1: center + Vector3::new(0.0, 0.5 * rng.gen::<f32>(), 0.0),
-                        time0: 0.0,
-                        time1: 1.0,
-                        radius: 0.2,
-                        material: Material::Lambertian {
-                            albedo: Texture::ConstantTexture {
-                                color: Vector3::new(
-                                    rng.gen::<f32>() * rng.gen::<f32>(),
-                                    rng.gen::<f32>() * rng.gen::<f32>(),
-                                    rng.gen::<f32>() * rng.gen::<f32>(),
-                                ),
-                            },
-                        },
-                    }));
-                } else if choose_mat < 0.95 {
-                    object_list.push(Box::new(Sphere {
-                        center,
-                        radius: 0.2,
-                        material: Material::Metal {
-                            albedo: Texture::ConstantTexture {
-                                color: Vector3::new(
-                                    0.5 * (1.0 + rng.gen::<f32>()),
-                                    0.5 * (1.0 + rng.gen::<f32>()),
-                                    0.5 * (1.0 + rng.gen::<f32>()),
-                                ),
-                            },
-                            fuzz: 0.5 * rng.gen::<f32>(),
-                        },
-                    }));
-                } else {
-                    object_list.push(Box::new(Sphere {
-                        center,
-                        radius: 0.2,
-                        material: Material::Dielectric { ref_idx: 1.5 },
-                    }));
+// color of a ray whose closest hit is already known,
+// lets primary rays be traced as packets
+fn shade<'a>(
+    r: &Ray,
+    hit: Option<(HitRecord, &'a dyn Material)>,
+    world: &'a dyn Hittable,
+    lights: &dyn Hittable,
+    depth: usize,
+    media: &MediumStack,
+) -> Color {
+    // on its way through the medium the ray is in light is absorbed, and in
+    // media that scatter the ray random walks until it reaches a surface, the
+    // whole walk samples distances with one channel and is weighted by the
+    // average density of the walk over all of them (spectral MIS)
+    let mut walked: Option<Ray> = None;
+    let mut hit = hit;
+    // in spectral mode colors of materials are RGB and get turned into
+    // the values at the wavelengths of the path
+    let wavelengths = r.wavelengths;
+    let space = color::working_space();
+    let spectral = move |color: Color| {
+        wavelengths.map_or(color, |w| w.upsample(space.convert_to_rec709(color)))
+    };
+    let mut contribution = Color::new(1.0, 1.0, 1.0);
+    let mut pdf = Color::new(1.0, 1.0, 1.0);
+    let channel = rand::thread_rng().gen_range(0, 3);
+    for collisions in 0.. {
+        let r = walked.as_ref().unwrap_or(r);
+        let distance = hit
+            .as_ref()
+            .map_or(Float::MAX, |(rec, _)| rec.t * r.direction().length());
+        match media.flight(distance, channel) {
+            Flight::Passed {
+                contribution: flight_contribution,
+                pdf: flight_pdf,
+            } => {
+                contribution *= flight_contribution;
+                pdf *= flight_pdf;
+                break;
+            }
+            Flight::Scattered {
+                distance,
+                contribution: flight_contribution,
+                pdf: flight_pdf,
+            } => {
+                if collisions == MAX_COLLISIONS {
+                    return Color::new(0.0, 0.0, 0.0);
+                }
+                let p = r.point_at_parameter(distance / r.direction().length());
+                let albedo = media.current().map_or(Color::new(0.0, 0.0, 0.0), |medium| {
+                    medium.scattering / (medium.absorption + medium.scattering)
+                });
+                let phase_function = Isotropic {
+                    texture: solid(albedo),
+                };
+                let rec = HitRecord::in_medium(distance / r.direction().length(), p, r);
+                let scatter_record = phase_function.scatter(r, &rec).unwrap();
+                contribution *= flight_contribution * scatter_record.attenuation;
+                pdf *= flight_pdf;
+                // only the ratio matters, keep both from underflowing on long walks
+                let scale = average(pdf);
+                contribution /= scale;
+                pdf /= scale;
+                // russian roulette keeps long walks from costing too much
+                let survival = average(contribution).min(1.0);
+                if rand::thread_rng().gen::<f32>() >= survival {
+                    return Color::new(0.0, 0.0, 0.0);
                 }
+                contribution /= survival;
+                let scattered = scatter_record
+                    .specular_ray
+                    .unwrap()
+                    .with_wavelengths(wavelengths);
+                hit = world.hit(&scattered, 0.0, Float::MAX);
+                walked = Some(scattered);
             }
         }
     }
-    object_list
-}
-
-fn final_scene() -> Vec<Box<Hittable>> {
-    let mut list: Vec<Box<Hittable>> = Vec::with_capacity(30);
-    let mut boxlist: Vec<Box<Hittable>> = Vec::with_capacity(10000);
-    let mut boxlist2: Vec<Box<Hittable>> = Vec::with_capacity(10000);
-    let mut rng = rand::thread_rng();
-    let nb: usize = 20;
-
-    let white: Material = Material::Lambertian {
-        albedo: Texture::ConstantTexture {
-            color: Vector3::new(0.73, 0.73, 0.73),
-        },
-    };
-    let ground: Material = Material::Lambertian {
-        albedo: Texture::ConstantTexture {
-            color: Vector3::new(0.48, 0.83, 0.53),
-        },
+    let transmittance = if average(pdf) > 0.0 {
+        contribution / average(pdf)
+    } else {
+        Color::new(0.0, 0.0, 0.0)
     };
-    for i in 0..nb {
-        for j in 0..nb {
-            let w: f32 = 100.0;
-            let x0: f32 = -1000.0 + i as f32 * w;
-            let z0: f32 = -1000.0 + j as f32 * w;
-            let y0: f32 = 0.0;
-            let x1: f32 = x0 + w;
-            let y1: f32 = 100.0 * (rng.gen::<f32>() + 0.01);
-            let z1: f32 = z0 + w;
-            boxlist.push(Box::new(Cube::new(
-                Vector3::new(x0, y0, z0),
-                Vector3::new(x1, y1, z1),
-                ground.clone(),
-            )))
-        }
-    }
-    list.push(Box::new(BvhTree::new(boxlist, 0.0, 1.0)));
-
-    let light: Material = Material::DiffuseLight {
-        emit: Texture::ConstantTexture {
-            color: Vector3::new(7.0, 7.0, 7.0),
-        },
-    };
-    list.push(Box::new(XZRect {
-        x0: 123.0,
-        x1: 423.0,
-        z0: 147.0,
-        z1: 412.0,
-        k: 554.0,
-        material: light,
-    }));
-
-    let center: Vector3 = Vector3::new(400.0, 400.0, 200.0);
-    list.push(Box::new(MovingSphere {
-        center0: center,
-        center1: center + Vector3::new(30.0, 0.0, 0.0),
-        time0: 0.0,
-        time1: 1.0,
-        radius: 50.0,
-        material: Material::Lambertian {
-            albedo: Texture::ConstantTexture {
-                color: Vector3::new(0.7, 0.3, 0.1),
-            },
-        },
-    }));
-
-    list.push(Box::new(Sphere {
-        center: Vector3::new(260.0, 150.0, 45.0),
-        radius: 50.0,
-        material: Material::Dielectric { ref_idx: 1.5 },
-    }));
-    list.push(Box::new(Sphere {
-        center: Vector3::new(0.0, 150.0, 145.0),
-        radius: 50.0,
-        material: Material::Metal {
-            albedo: Texture::ConstantTexture {
-                color: Vector3::new(0.8, 0.8, 0.9),
-            },
-            fuzz: 10.0,
-        },
-    }));
-    list.push(Box::new(Sphere {
-        center: Vector3::new(360.0, 150.0, 145.0),
-        radius: 70.0,
-        material: Material::Dielectric { ref_idx: 1.5 },
-    }));
-
-    list.push(Box::new(ConstantMedium::new(
-        Box::new(Sphere {
-            center: Vector3::new(360.0, 150.0, 145.0),
-            radius: 70.0,
-            material: Material::Dielectric { ref_idx: 1.5 },
-        }),
-        0.2,
-        Texture::ConstantTexture {
-            color: Vector3::new(0.2, 0.4, 0.9),
-        },
-    )));
-    list.push(Box::new(ConstantMedium::new(
-        Box::new(Sphere {
-            center: Vector3::new(0.0, 0.0, 0.0),
-            radius: 5000.0,
-            material: Material::Dielectric { ref_idx: 1.5 },
-        }),
-        0.0001,
-        Texture::ConstantTexture {
-            color: Vector3::new(1.0, 1.0, 1.0),
-        },
-    )));
-
-    let img = image::open("pug.jpg").unwrap();
-
-    list.push(Box::new(Sphere {
-        center: Vector3::new(400.0, 200.0, 400.0),
-        radius: 100.0,
-        material: Material::Lambertian {
-            albedo: Texture::ImageTexture { img },
-        },
-    }));
-
-    list.push(Box::new(Sphere {
-        center: Vector3::new(220.0, 280.0, 300.0),
-        radius: 80.0,
-        material: Material::Lambertian {
-            albedo: Texture::NoiseTexture {
-                noise: Perlin::new(),
-                scale: 0.1,
-            },
-        },
-    }));
-
-    let ns: usize = 1000;
-    for _i in 0..ns {
-        boxlist2.push(Box::new(Sphere {
-            center: Vector3::new(
-                165.0 * rng.gen::<f32>(),
-                165.0 * rng.gen::<f32>(),
-                165.0 * rng.gen::<f32>(),
-            ),
-            radius: 10.0,
-            material: white.clone(),
-        }))
-    }
-
-    list.push(Box::new(Translate {
-        object: Box::new(RotateY::new(
-            Box::new(BvhTree::new(boxlist2, 0.0, 1.0)),
-            15.0,
-        )),
-        offset: Vector3::new(-100.0, 270.0, 395.0),
-    }));
-
-    list
-}
+    let r = walked.as_ref().unwrap_or(r);
+
+    match hit {
+        Some((mut rec, material)) => {
+            rec.set_footprint(r);
+            let medium = material.medium().map(|medium| medium.at(wavelengths));
+            if let Some(medium) = &medium {
+                if depth < 50 && !media.is_interface(medium, rec.front_face) {
+                    // the boundary is inside a medium with a higher priority
+                    let inside = media.crossed(medium, rec.front_face);
+                    let mut through = rec
+                        .spawn_ray(r.direction(), r.time)
+                        .with_wavelengths(wavelengths);
+                    through.differentials = rec.specular_differentials(r, &through);
+                    return transmittance * color(&through, world, lights, depth + 1, &inside);
+                }
+            }
+            // surfaces without a medium are seen from the one the ray is in
+            rec.eta_outside = match &medium {
+                Some(medium) => media.outside_ref_idx(medium),
+                None => media.ref_idx(),
+            };
+            // rays refracted into or out of the medium continue in a different one
+            let media_after = |scattered: &Ray| match &medium {
+                Some(medium) if dot(scattered.direction(), rec.normal) < 0.0 => {
+                    media.crossed(medium, rec.front_face)
+                }
+                _ => media.clone(),
+            };
 
-fn color(r: &Ray, world: &ObjectList, depth: usize) -> Vector3 {
-    // some of the rays hit at 0.00000001 instead of 0.0
-    // so ignore those to remove noise
-    match world.hit(r, 0.001, std::f32::MAX) {
-        Some((rec, material)) => {
-            let emitted: Vector3 = material.emitted(r, &rec, rec.u, rec.v, &rec.p);
+            let emitted: Color = spectral(material.emitted(r, &rec, rec.u, rec.v, &rec.p));
             if depth < 50 {
                 match material.scatter(r, &rec) {
                     Some(scatter_record) => {
-                        //TODO: find a way to pass this in as an argument
-                        let light_shape: XZRect = XZRect {
-                            x0: 213.0,
-                            x1: 343.0,
-                            z0: 227.0,
-                            z1: 332.0,
-                            k: 554.0,
-                            material: Material::DiffuseLight {
-                                emit: Texture::ConstantTexture {
-                                    color: Vector3::new(15.0, 15.0, 15.0),
-                                },
-                            },
+                        // dispersion splits the wavelengths up, so the
+                        // path goes on with the hero wavelength alone
+                        let mut wavelengths = wavelengths;
+                        let dispersion = match &mut wavelengths {
+                            Some(w) if material.is_dispersive() => w.collapse(),
+                            _ => Color::new(1.0, 1.0, 1.0),
                         };
-                        let sphere_shape: Sphere = Sphere {
-                            center: Vector3::new(190.0, 90.0, 190.0),
-                            radius: 90.0,
-                            material: Material::Lambertian {
-                                albedo: Texture::ConstantTexture {
-                                    color: Vector3::new(0.7, 0.7, 0.7),
-                                },
-                            },
-                        };
-                        let object_list: ObjectList =
-                            ObjectList::new(vec![Box::new(light_shape), Box::new(sphere_shape)]);
-
                         if scatter_record.is_specular {
-                            return scatter_record.attenuation
-                                * color(&scatter_record.specular_ray.unwrap(), world, depth + 1);
+                            let mut specular_ray = scatter_record
+                                .specular_ray
+                                .unwrap()
+                                .with_wavelengths(wavelengths);
+                            specular_ray.differentials =
+                                rec.specular_differentials(r, &specular_ray);
+                            return transmittance
+                                * dispersion
+                                * spectral(scatter_record.attenuation)
+                                * color(
+                                    &specular_ray,
+                                    world,
+                                    lights,
+                                    depth + 1,
+                                    &media_after(&specular_ray),
+                                );
                         }
                         let plight: HittablePDF = HittablePDF {
                             o: rec.p,
-                            hittable: Box::new(object_list),
+                            hittable: lights,
                         };
                         let p: MixturePDF =
                             MixturePDF::new(Box::new(plight), scatter_record.pdf.unwrap());
-                        let scattered = Ray::new(rec.p, p.generate(), r.time);
-                        let pdf_val = p.value(scattered.direction());
-                        emitted
-                            + scatter_record.attenuation
-                                * material.scattering_pdf(r, &rec, &scattered)
-                                * color(&scattered, world, depth + 1)
-                                / pdf_val
+                        let scattered = rec
+                            .spawn_ray(p.generate(), r.time)
+                            .with_wavelengths(wavelengths);
+                        // half of the time the direction comes from the lights and
+                        // half of the time from the pdf the material sampled with
+                        let pdf_val = 0.5 * lights.pdf_value(rec.p, scattered.direction())
+                            + 0.5 * material.pdf(r, &rec, &scattered);
+                        if pdf_val <= 0.0 {
+                            return transmittance * emitted;
+                        }
+                        transmittance
+                            * (emitted
+                                + dispersion
+                                    * spectral(material.eval(r, &rec, &scattered))
+                                    * to_f32(1.0 / pdf_val)
+                                    * color(
+                                        &scattered,
+                                        world,
+                                        lights,
+                                        depth + 1,
+                                        &media_after(&scattered),
+                                    ))
                     }
-                    None => emitted,
+                    None => transmittance * emitted,
                 }
             } else {
-                Vector3::new(0.0, 0.0, 0.0)
+                Color::new(0.0, 0.0, 0.0)
             }
         }
-        None => {
-            // let unit_direction = unit_vector(r.direction());
-            // let t: f32 = 0.5 * (unit_direction.y() + 1.0);
-            // (1.0 - t) * Vector3::new(1.0, 1.0, 1.0) + t * Vector3::new(0.5, 0.7, 1.0)
-            Vector3::new(0.0, 0.0, 0.0)
-        }
+        None => Color::new(0.0, 0.0, 0.0),
     }
 }
 
 fn main() {
+    if std::env::args().any(|arg| arg == "--bench-bvh") {
+        bench::bvh();
+        return;
+    }
+    if std::env::args().any(|arg| arg == "--bench-packets") {
+        bench::packets();
+        return;
+    }
+
+    // `--color-space acescg` renders in the wider ACEScg gamut instead of
+    // linear Rec.709, colors in the scene are then given in it
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|arg| arg == "--color-space") {
+        color::set_working_space(match args.get(i + 1).map(|s| s.as_str()) {
+            Some("rec709") => ColorSpace::Rec709,
+            Some("acescg") => ColorSpace::AcesCg,
+            other => panic!("--color-space should be rec709 or acescg, not {:?}", other),
+        });
+    }
+    let space = color::working_space();
+    // `--exposure <EV>` brightens by stops, `--auto-exposure` exposes for
+    // middle gray first and `--tone-map <name>` keeps highlights from
+    // clipping, `--white <luminance>` is where extended Reinhard reaches 1
+    let value = |flag: &str| {
+        args.iter().position(|arg| arg == flag).map(|i| {
+            args.get(i + 1)
+                .unwrap_or_else(|| panic!("{} needs a value", flag))
+        })
+    };
+    let number = |flag: &str| {
+        value(flag).map(|v| {
+            v.parse::<f32>()
+                .unwrap_or_else(|_| panic!("{} should be a number, not {}", flag, v))
+        })
+    };
+    let post = PostProcess {
+        exposure: number("--exposure").unwrap_or(0.0),
+        auto_exposure: args.iter().any(|arg| arg == "--auto-exposure"),
+        tone_mapper: match value("--tone-map").map(|v| v.as_str()) {
+            None | Some("clamp") => ToneMapper::Clamp,
+            Some("reinhard") => ToneMapper::Reinhard,
+            Some("extended-reinhard") => ToneMapper::ExtendedReinhard {
+                white: number("--white"),
+            },
+            Some("hable") => ToneMapper::Hable,
+            Some("aces") => ToneMapper::Aces,
+            Some(other) => panic!(
+                "--tone-map should be clamp, reinhard, extended-reinhard, hable or aces, not {}",
+                other
+            ),
+        },
+    };
+
+    // trace the primary rays of each tile together
+    let packets = std::env::args().any(|arg| arg == "--packets");
+    let tile = if packets { PACKET_TILE } else { 1 };
+    // `--spectral` traces wavelengths instead of RGB, which is slower
+    // but lets dispersive dielectrics split light into colors
+    let spectral = std::env::args().any(|arg| arg == "--spectral");
+    let white = if spectral {
+        white_response()
+    } else {
+        Color::new(1.0, 1.0, 1.0)
+    };
+    let to_rgb = |r: &Ray, radiance: Color| match r.wavelengths {
+        Some(wavelengths) => space.convert_from_rec709(wavelengths.to_rgb(radiance, white)),
+        None => radiance,
+    };
+
     let cpu_num = num_cpus::get() - 1; // leave some for the rest of the processes
     let now = Instant::now();
     let width = 500;
@@ -624,37 +805,76 @@ fn main() {
     // use one less thread for exact division
     // the last one will have less pixels to calculate
     let thread_rows = height / cpu_num + 1;
-    let camera: &Camera = &Camera::new(
-        Vector3::new(11.0, 4.0, 5.0),
-        Vector3::new(0.0, 0.0, 0.0),
-        Vector3::new(0.0, 1.0, 0.0),
-        36.0,
-        width as f32 / height as f32,
-        0.0,
-        10.0,
-        0.0,
-        1.0,
-    );
-    let final_camera: &Camera = &Camera::new(
-        Vector3::new(478.0, 278.0, -600.0),
-        Vector3::new(278.0, 278.0, 0.0),
-        Vector3::new(0.0, 1.0, 0.0),
-        40.0,
-        width as f32 / height as f32,
-        0.0,
-        10.0,
-        0.0,
-        1.0,
-    );
 
-    let (scene, cornell_camera) = cornell_box(width as f32 / height as f32);
+    // `--sphere "<material>"` swaps the material of the cornell box sphere for
+    // one described like in a scene file, e.g. `metal ior=gold roughness=0.2`
+    // `--scene <name>` renders one of `SCENES` instead of the plain box
+    if value("--scene").is_some()
+        && (value("--sphere").is_some() || value("--normal-map").is_some())
+    {
+        panic!("--scene can't be combined with --sphere or --normal-map");
+    }
+    let sphere = args.iter().position(|arg| arg == "--sphere").map(|i| {
+        let description = args.get(i + 1).expect("--sphere needs a material");
+        MaterialRegistry::new()
+            .parse(description)
+            .unwrap_or_else(|err| panic!("bad sphere material: {}", err))
+    });
+    // `--normal-map <image>` wraps the sphere in a tangent space normal map
+    let normal_map = args.iter().position(|arg| arg == "--normal-map").map(|i| {
+        let path = args.get(i + 1).expect("--normal-map needs an image");
+        Arc::new(ImageTexture::shared(
+            cache::load(path, Encoding::Linear)
+                .unwrap_or_else(|err| panic!("bad normal map: {}", err)),
+        ))
+    });
+    let aspect = width as Float / height as Float;
+    let (scene, lights, cornell_camera) = match (sphere, normal_map) {
+        (sphere, Some(map)) => cornell_box_with_object(
+            aspect,
+            Box::new(NormalMap {
+                object: Box::new(Sphere {
+                    center: Vector3::new(190.0, 90.0, 190.0),
+                    radius: 90.0,
+                    material: sphere.unwrap_or_else(|| {
+                        Arc::new(Lambertian {
+                            albedo: constant(0.73),
+                        })
+                    }),
+                }),
+                map,
+            }),
+        ),
+        (Some(material), None) => cornell_box_with_sphere(aspect, material),
+        (None, None) => {
+            let name = value("--scene").map_or("cornell", |name| name.as_str());
+            match SCENES.iter().find(|(scene, _)| *scene == name) {
+                Some((_, build)) => build(aspect),
+                None => panic!(
+                    "unknown scene {}, one of {}",
+                    name,
+                    SCENES
+                        .iter()
+                        .map(|(scene, _)| *scene)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            }
+        }
+    };
     let camera = &cornell_camera;
-    // let balls = random_scene2();
-    // scene.push(Box::new(create_binary_tree(balls, 0.0, 1.0)));
-    let world = &ObjectList::new(scene);
-
-    let mut pixels = vec![Vector3::new(0.0, 0.0, 0.0); width * height];
-    let rows: Vec<&mut [Vector3]> = pixels.chunks_mut(thread_rows * width).collect();
+    // the samples of a pixel share it, so each filters textures over
+    // a smaller footprint than the pixel
+    let spacing = (1.0 / (smoothness as Float).sqrt()).max(0.125);
+    let ds = spacing / width as Float;
+    let dt = spacing / height as Float;
+    // packets only pay off with the SIMD traversal of a Bvh4 below them,
+    // single rays use the same one so only the traversal differs
+    let world = &Bvh4::new(scene, 0.0, 1.0);
+    let lights = &ObjectList::new(lights);
+
+    let mut pixels = vec![Color::new(0.0, 0.0, 0.0); width * height];
+    let rows: Vec<&mut [Color]> = pixels.chunks_mut(thread_rows * width).collect();
     let rows_length = rows.len();
 
     match crossbeam::scope(|spawner| {
@@ -668,39 +888,63 @@ fn main() {
                     max_thread_rows = height % thread_rows;
                 }
 
-                for y in 0..max_thread_rows {
-                    for x in 0..width {
-                        let mut col: Vector3 = Vector3::new(0.0, 0.0, 0.0);
-                        // rows and y need to be calculated from bottom up
-                        // but buffer needs to be written from top down
-                        // also subtract one because the for loop isn't inclusive
-                        // on the right hand side
-                        let inverted_y = thread_rows - y - 1;
-
-                        let inverted_row = ((cpu_num - i - 1) * thread_rows + inverted_y) as f32;
+                for tile_y in (0..max_thread_rows).step_by(tile) {
+                    for tile_x in (0..width).step_by(tile) {
+                        let mut tile_pixels: Vec<(usize, usize)> = Vec::with_capacity(tile * tile);
+                        for y in tile_y..(tile_y + tile).min(max_thread_rows) {
+                            for x in tile_x..(tile_x + tile).min(width) {
+                                tile_pixels.push((x, y));
+                            }
+                        }
+                        let mut cols = vec![Color::new(0.0, 0.0, 0.0); tile_pixels.len()];
                         let mut rng = rand::thread_rng();
 
                         // this shoots rays around the object
                         // edge using a random offset
                         // and computes a color average
                         for _ in 0..smoothness {
-                            let u = (x as f32 + rng.gen::<f32>()) / width as f32;
-                            let v = (inverted_row + rng.gen::<f32>()) / height as f32;
-                            let r = camera.get_ray(u, v);
-                            col += de_nan(color(&r, &world, 0));
+                            let rays: Vec<Ray> = tile_pixels
+                                .iter()
+                                .map(|&(x, y)| {
+                                    // rows and y need to be calculated from bottom up
+                                    // but buffer needs to be written from top down
+                                    // also subtract one because the for loop isn't inclusive
+                                    // on the right hand side
+                                    let inverted_y = thread_rows - y - 1;
+                                    let inverted_row =
+                                        ((cpu_num - i - 1) * thread_rows + inverted_y) as Float;
+                                    let u = (x as Float + rng.gen::<Float>()) / width as Float;
+                                    let v = (inverted_row + rng.gen::<Float>()) / height as Float;
+                                    let wavelengths = if spectral {
+                                        Some(Wavelengths::sample(rng.gen::<Float>()))
+                                    } else {
+                                        None
+                                    };
+                                    camera
+                                        .get_ray_differential(u, v, ds, dt)
+                                        .with_wavelengths(wavelengths)
+                                })
+                                .collect();
+                            if packets {
+                                let mut t_max = vec![Float::MAX; rays.len()];
+                                let hits = world.hit_packet(&rays, 0.0, &mut t_max);
+                                for ((col, r), hit) in cols.iter_mut().zip(&rays).zip(hits) {
+                                    let radiance =
+                                        shade(r, hit, world, lights, 0, &MediumStack::new());
+                                    *col += de_nan(to_rgb(r, radiance));
+                                }
+                            } else {
+                                for (col, r) in cols.iter_mut().zip(&rays) {
+                                    let radiance = color(r, world, lights, 0, &MediumStack::new());
+                                    *col += de_nan(to_rgb(r, radiance));
+                                }
+                            }
+                        }
+
+                        for (&(x, y), &col) in tile_pixels.iter().zip(&cols) {
+                            // save buffer values
+                            row[y * width + x] = col / smoothness as f32;
                         }
-                        col /= smoothness as f32;
-                        // remove the gamma of 2 from the color (raise to power of 1/2)
-                        col = Vector3::new(col[0].sqrt(), col[1].sqrt(), col[2].sqrt());
-                        let ir = clamp(255.99 * &col[0], 0.0, 255.99);
-                        let ig = clamp(255.99 * &col[1], 0.0, 255.99);
-                        let ib = clamp(255.99 * &col[2], 0.0, 255.99);
-
-                        // save buffer values
-                        let buffer_pos = y * width + x;
-                        row[buffer_pos][0] = ir;
-                        row[buffer_pos][1] = ig;
-                        row[buffer_pos][2] = ib;
                     }
                 }
             });
@@ -708,20 +952,13 @@ fn main() {
     }) {
         Ok(()) => println!(
             "Finished in {}m {}s {}ms",
-            (now.elapsed().as_secs() / 60) as u64,
+            now.elapsed().as_secs() / 60,
             now.elapsed().as_secs() % 60,
-            (now.elapsed().subsec_nanos() / 1_000_000) as u64
+            now.elapsed().subsec_millis()
         ),
         Err(err) => println!("{:?}", err),
     };
 
-    let mut buffer = vec![];
-
-    for pixel in pixels {
-        buffer.push(pixel[0] as u8);
-        buffer.push(pixel[1] as u8);
-        buffer.push(pixel[2] as u8);
-    }
-
+    let buffer = post.develop(&pixels, space);
     image::save_buffer("1.png", &buffer, width as u32, height as u32, image::RGB(8)).unwrap();
 }
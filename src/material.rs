@@ -1,22 +1,18 @@
+use super::float::{to_f32, Float};
 use super::hittable::HitRecord;
-use super::onb::ONB;
-use super::pdf::{CosinePDF, PDF};
+use super::medium::Medium;
+use super::pdf::PDF;
 use super::ray::Ray;
-use super::texture::Texture;
-use super::vector3::{dot, unit_vector, Vector3};
-use super::{random_cosine_direction, random_on_unit_sphere};
-use std::f32::consts;
+use super::vector3::{dot, unit_vector, Color, Vector3};
 
-use rand::Rng;
-
-fn reflect(v: Vector3, n: Vector3) -> Vector3 {
+pub fn reflect(v: Vector3, n: Vector3) -> Vector3 {
     v - 2.0 * dot(v, n) * n
 }
 
-fn refract(v: Vector3, n: Vector3, ni_over_nt: f32) -> Option<Vector3> {
+pub fn refract(v: Vector3, n: Vector3, ni_over_nt: Float) -> Option<Vector3> {
     let uv: Vector3 = unit_vector(v);
-    let dt: f32 = dot(uv, n);
-    let discriminant: f32 = 1.0 - ni_over_nt * ni_over_nt * (1.0 - dt * dt);
+    let dt: Float = dot(uv, n);
+    let discriminant: Float = 1.0 - ni_over_nt * ni_over_nt * (1.0 - dt * dt);
     if discriminant > 0.0 {
         Some(ni_over_nt * (uv - n * dt) - n * discriminant.sqrt())
     } else {
@@ -24,149 +20,180 @@ fn refract(v: Vector3, n: Vector3, ni_over_nt: f32) -> Option<Vector3> {
     }
 }
 
-// approximation for fresnel reflections
-fn schlick(cosine: f32, ref_idx: f32) -> f32 {
-    let mut r0: f32 = (1.0 - ref_idx) / (1.0 + ref_idx);
-    r0 = r0 * r0;
-    r0 + (1.0 - r0) * (1.0 - cosine).powf(5.0)
+// unpolarized fresnel reflectance of a dielectric interface, eta is the
+// index on the far side over the index on the side cosine is measured in
+pub fn fresnel_dielectric(cosine: Float, eta: Float) -> Float {
+    let (cos_i, eta) = if cosine < 0.0 {
+        (-cosine.max(-1.0), 1.0 / eta)
+    } else {
+        (cosine.min(1.0), eta)
+    };
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (r_parallel * r_parallel + r_perpendicular * r_perpendicular)
 }
 
-pub struct ScatterRecord {
-    pub specular_ray: Option<Ray>,
-    pub is_specular: bool,
-    pub attenuation: Vector3,
-    pub pdf: Option<Box<PDF>>,
+// fresnel reflectance of a conductor with complex index of refraction
+// eta + ik, seen from a dielectric with index 1
+pub fn fresnel_conductor(cosine: Float, ior: &ComplexIor) -> Color {
+    let cos2 = to_f32(cosine.clamp(0.0, 1.0));
+    let cos2 = cos2 * cos2;
+    let sin2 = 1.0 - cos2;
+    let mut reflectance = Color::new(0.0, 0.0, 0.0);
+    for i in 0..3 {
+        let eta2 = ior.eta[i] * ior.eta[i];
+        let k2 = ior.k[i] * ior.k[i];
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos2.sqrt() * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        reflectance[i] = 0.5 * (rp + rs);
+    }
+    reflectance
 }
 
-#[derive(Clone)]
-pub enum Material {
-    Lambertian { albedo: Texture },
-    Metal { albedo: Texture, fuzz: f32 },
-    Dielectric { ref_idx: f32 },
-    DiffuseLight { emit: Texture },
-    Isotropic { texture: Texture },
+// complex index of refraction sampled at roughly 650, 550 and 450nm
+#[derive(Copy, Clone)]
+pub struct ComplexIor {
+    pub eta: Color,
+    pub k: Color,
 }
 
-impl Material {
-    pub fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
-        match self {
-            Material::Lambertian { albedo } => {
-                let uvw: ONB = ONB::new(rec.normal);
-                let direction: Vector3 = uvw.local_vec(&random_cosine_direction());
-                let scattered: Ray = Ray::new(rec.p, unit_vector(direction), r_in.time);
-                let scattered_direction = scattered.direction();
-                Some(ScatterRecord {
-                    specular_ray: None,
-                    is_specular: false,
-                    attenuation: albedo.value(rec.u, rec.v, &rec.p),
-                    pdf: Some(Box::new(CosinePDF::new(rec.normal))),
-                })
-            }
-            Material::Metal { albedo, fuzz } => {
-                let mut fuzz = *fuzz;
-                if fuzz > 1.0 {
-                    fuzz = 1.0;
-                }
-                let reflected = reflect(unit_vector(r_in.direction()), rec.normal);
-                let scattered = Ray::new(rec.p, reflected + fuzz * random_on_unit_sphere(), 0.0);
-                if dot(scattered.direction(), rec.normal) > 0.0 {
-                    Some(ScatterRecord {
-                        attenuation: albedo.value(rec.u, rec.v, &rec.p),
-                        specular_ray: Some(scattered),
-                        is_specular: true,
-                        pdf: None,
-                    })
-                } else {
-                    None
-                }
-            }
-            Material::Dielectric { ref_idx } => {
-                let outward_normal: Vector3;
-                let reflected: Vector3 = reflect(r_in.direction(), rec.normal);
-                let ni_over_nt: f32;
-                let attenuation = Vector3::new(1.0, 1.0, 1.0);
-                let scattered: Ray;
-                let mut saved_refracted: Vector3 = Vector3::new(0.0, 0.0, 0.0);
-                let reflect_prob: f32;
-                let ray_angle = dot(r_in.direction(), rec.normal);
-                let random = rand::thread_rng().gen::<f32>();
-                let mut cosine: f32 = ray_angle / r_in.direction().length();
-
-                if ray_angle > 0.0 {
-                    outward_normal = -rec.normal;
-                    ni_over_nt = *ref_idx;
-                    cosine = ref_idx * cosine;
-                } else {
-                    outward_normal = rec.normal;
-                    ni_over_nt = 1.0 / ref_idx;
-                    cosine = -cosine;
-                }
-
-                match refract(r_in.direction(), outward_normal, ni_over_nt) {
-                    Some(refracted) => {
-                        reflect_prob = schlick(cosine, *ref_idx);
-                        saved_refracted = refracted;
-                    }
-                    None => {
-                        reflect_prob = 1.0;
-                    }
-                }
-
-                if random < reflect_prob {
-                    scattered = Ray::new(rec.p, reflected, 0.0);
-                } else {
-                    scattered = Ray::new(rec.p, saved_refracted, 0.0);
-                }
-
-                Some(ScatterRecord {
-                    attenuation,
-                    specular_ray: Some(scattered),
-                    is_specular: true,
-                    pdf: None,
-                })
-            }
-            Material::Isotropic { texture } => {
-                let target: Vector3 = rec.p + rec.normal + random_on_unit_sphere();
-                Some(ScatterRecord {
-                    attenuation: texture.value(rec.u, rec.v, &rec.p),
-                    specular_ray: Some(Ray::new(rec.p, random_on_unit_sphere(), r_in.time)),
-                    is_specular: true,
-                    pdf: None,
-                })
-            }
-            _ => None,
+impl ComplexIor {
+    pub const ALUMINIUM: ComplexIor = ComplexIor {
+        eta: Color {
+            e: [1.657, 0.880, 0.521],
+        },
+        k: Color {
+            e: [9.224, 6.270, 4.837],
+        },
+    };
+    pub const CHROMIUM: ComplexIor = ComplexIor {
+        eta: Color {
+            e: [3.105, 3.183, 2.251],
+        },
+        k: Color {
+            e: [3.330, 3.330, 3.040],
+        },
+    };
+    pub const COPPER: ComplexIor = ComplexIor {
+        eta: Color {
+            e: [0.200, 0.924, 1.102],
+        },
+        k: Color {
+            e: [3.912, 2.452, 2.142],
+        },
+    };
+    pub const GOLD: ComplexIor = ComplexIor {
+        eta: Color {
+            e: [0.143, 0.374, 1.442],
+        },
+        k: Color {
+            e: [3.983, 2.385, 1.603],
+        },
+    };
+    pub const IRON: ComplexIor = ComplexIor {
+        eta: Color {
+            e: [2.868, 2.916, 2.578],
+        },
+        k: Color {
+            e: [3.121, 2.931, 2.815],
+        },
+    };
+    pub const SILVER: ComplexIor = ComplexIor {
+        eta: Color {
+            e: [0.155, 0.117, 0.138],
+        },
+        k: Color {
+            e: [4.828, 3.122, 2.147],
+        },
+    };
+
+    // artist friendly parameterization (Gulbrandsen 2014), reflectivity is
+    // the color at normal incidence and edge tint the color at grazing angles
+    pub fn from_reflectivity(reflectivity: Color, edge_tint: Color) -> Self {
+        let mut eta = Color::new(0.0, 0.0, 0.0);
+        let mut k = Color::new(0.0, 0.0, 0.0);
+        for i in 0..3 {
+            let r = reflectivity[i].clamp(0.0, 0.99);
+            let g = edge_tint[i];
+            let n_min = (1.0 - r) / (1.0 + r);
+            let n_max = (1.0 + r.sqrt()) / (1.0 - r.sqrt());
+            let n = g * n_min + (1.0 - g) * n_max;
+            let k2 = ((n + 1.0) * (n + 1.0) * r - (n - 1.0) * (n - 1.0)) / (1.0 - r);
+            eta[i] = n;
+            k[i] = k2.max(0.0).sqrt();
         }
+        ComplexIor { eta, k }
     }
 
-    pub fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f32 {
-        match self {
-            Material::Lambertian { albedo } => {
-                let mut cosine: f32 = dot(rec.normal, unit_vector(scattered.direction()));
-                if cosine < 0.0 {
-                    cosine = 0.0;
-                }
-                cosine / consts::PI
-            }
-            // Material::Metal { albedo, fuzz } => {
-            // }
-            // Material::Dielectric { ref_idx } => {
-            // }
-            // Material::Isotropic { texture } => {
-            // }
-            _ => 0.0,
+    // a dielectric, which has no extinction
+    pub fn dielectric(ref_idx: Float) -> Self {
+        let eta = to_f32(ref_idx);
+        ComplexIor {
+            eta: Color::new(eta, eta, eta),
+            k: Color::new(0.0, 0.0, 0.0),
         }
     }
+}
+
+pub fn luminance(c: Color) -> f32 {
+    0.2126 * c.r() + 0.7152 * c.g() + 0.0722 * c.b()
+}
+
+// far side over near side, from the media the ray is in
+pub fn relative_ref_idx(ref_idx: Float, rec: &HitRecord) -> Float {
+    if rec.front_face {
+        ref_idx / rec.eta_outside
+    } else {
+        rec.eta_outside / ref_idx
+    }
+}
 
-    pub fn emitted(&self, r_in: &Ray, rec: &HitRecord, u: f32, v: f32, p: &Vector3) -> Vector3 {
-        match self {
-            Material::DiffuseLight { emit } => {
-                if dot(rec.normal, r_in.direction()) < 0.0 {
-                    emit.value(u, v, p)
-                } else {
-                    Vector3::new(0.0, 0.0, 0.0)
-                }
-            }
-            _ => Vector3::new(0.0, 0.0, 0.0),
-        }
+pub struct ScatterRecord {
+    pub specular_ray: Option<Ray>,
+    pub is_specular: bool,
+    pub attenuation: Color,
+    pub pdf: Option<Box<dyn PDF>>,
+}
+
+pub trait Material: Sync + Send {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Float {
+        0.0
+    }
+
+    // bsdf times the cosine of the scattered direction
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        None
+    }
+
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+
+    fn is_opaque(&self, _r_in: &Ray, _rec: &HitRecord) -> bool {
+        true
+    }
+
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord, _u: Float, _v: Float, _p: &Vector3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
     }
 }
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use super::super::float::consts;
+use super::super::float::{to_f32, Float};
+use super::super::hittable::HitRecord;
+use super::super::material::{luminance, Material, ScatterRecord};
+use super::super::microfacet::Charlie;
+use super::super::onb::ONB;
+use super::super::pdf::{CosinePDF, UniformPDF, WeightedPDF, PDF};
+use super::super::ray::Ray;
+use super::super::texture::Texture;
+use super::super::vector3::{dot, unit_vector, Color};
+
+// a diffuse base under the sheen of the fibers, which is brightest
+// at grazing angles
+pub struct Cloth {
+    pub albedo: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+}
+
+impl Cloth {
+    // cosine sampling for the base and uniform for the sheen, which
+    // is strongest close to the horizon where the former has none
+    fn sampling_pdf(&self, rec: &HitRecord) -> WeightedPDF {
+        let sheen = 0.5 * luminance(self.sheen.value(rec)).clamp(0.0, 1.0);
+        WeightedPDF::new(vec![
+            (
+                1.0 - sheen as Float,
+                Box::new(CosinePDF::new(rec.shading_normal)),
+            ),
+            (
+                sheen as Float,
+                Box::new(UniformPDF::new(rec.shading_normal)),
+            ),
+        ])
+    }
+}
+
+impl Material for Cloth {
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            specular_ray: None,
+            is_specular: false,
+            attenuation: Color::new(1.0, 1.0, 1.0),
+            pdf: Some(Box::new(self.sampling_pdf(rec))),
+        })
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        self.sampling_pdf(rec).value(scattered.direction())
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        if dot(scattered.direction(), rec.normal) <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let uvw = ONB::new(rec.shading_normal);
+        let wo = uvw.to_local(&-unit_vector(r_in.direction()));
+        let wi = uvw.to_local(&unit_vector(scattered.direction()));
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let distribution = Charlie::from_roughness(self.roughness.value(rec).r() as Float);
+        let h = unit_vector(wo + wi);
+        // D * G / (4 cos_o cos_i) times cos_i, the fibers have no fresnel term
+        let sheen_color = self.sheen.value(rec);
+        let sheen = distribution.d(h) * distribution.g(wo, wi) / (4.0 * wo.z());
+        // the base only gets the light the fibers don't reflect
+        let strength = sheen_color.r().max(sheen_color.g()).max(sheen_color.b()) as Float;
+        let scaling = (1.0 - strength * distribution.albedo(wo.z()))
+            .min(1.0 - strength * distribution.albedo(wi.z()))
+            .max(0.0);
+        let diffuse = scaling * wi.z() / consts::PI;
+        self.albedo.value(rec) * to_f32(diffuse) + sheen_color * to_f32(sheen)
+    }
+}
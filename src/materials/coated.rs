@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use super::super::float::{to_f32, Float};
+use super::super::hittable::HitRecord;
+use super::super::material::{
+    fresnel_dielectric, reflect, relative_ref_idx, Material, ScatterRecord,
+};
+use super::super::medium::Medium;
+use super::super::microfacet::TrowbridgeReitz;
+use super::super::onb::ONB;
+use super::super::pdf::{GgxPDF, PDF};
+use super::super::ray::Ray;
+use super::super::vector3::{dot, unit_vector, Color, Vector3};
+
+// a thin dielectric layer like varnish over any other material, the tint
+// is the color of the coat seen straight through once
+pub struct Coated {
+    pub base: Arc<dyn Material>,
+    pub ref_idx: Float,
+    pub roughness: Float,
+    pub tint: Color,
+}
+
+// which of the layers a hit scatters from, the coat with the probability of
+// its reflectance and the base otherwise, so the base doesn't have to be
+// weighted by the light the coat reflects
+enum Layer {
+    Coat,
+    Base,
+}
+
+impl Coated {
+    fn distribution(&self) -> TrowbridgeReitz {
+        TrowbridgeReitz::from_roughness(self.roughness, self.roughness)
+    }
+
+    // outgoing direction in the shading frame, None when it is on the side
+    // without the coat and the base alone is seen
+    fn frame(&self, r_in: &Ray, rec: &HitRecord) -> Option<(ONB, Vector3)> {
+        let uvw = ONB::from_tangent(rec.shading_normal, rec.dpdu);
+        let wo = uvw.to_local(&-unit_vector(r_in.direction()));
+        if !rec.front_face || wo.z() <= 0.0 {
+            None
+        } else {
+            Some((uvw, wo))
+        }
+    }
+
+    fn layer(&self, rec: &HitRecord, wo: Vector3) -> (Layer, HitRecord) {
+        let eta = relative_ref_idx(self.ref_idx, rec);
+        match rec.pick(fresnel_dielectric(wo.z(), eta)) {
+            (true, picked) => (Layer::Coat, picked),
+            (false, picked) => (Layer::Base, picked),
+        }
+    }
+
+    // the tint along the path through the layer, which follows the
+    // direction refracted into it
+    fn absorption(&self, rec: &HitRecord, cosine: Float) -> Color {
+        let eta = relative_ref_idx(self.ref_idx, rec);
+        let cosine = cosine.abs().min(1.0);
+        let cos_t = (1.0 - (1.0 - cosine * cosine) / (eta * eta))
+            .max(1.0e-4)
+            .sqrt();
+        let exponent = 1.0 / to_f32(cos_t);
+        Color::new(
+            self.tint.r().powf(exponent),
+            self.tint.g().powf(exponent),
+            self.tint.b().powf(exponent),
+        )
+    }
+
+    // fraction of the light leaving the base along a direction
+    // that makes it out through the coat
+    fn transmittance(&self, rec: &HitRecord, cosine: Float) -> Color {
+        let eta = relative_ref_idx(self.ref_idx, rec);
+        self.absorption(rec, cosine) * to_f32(1.0 - fresnel_dielectric(cosine.abs(), eta))
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let (uvw, wo) = match self.frame(r_in, rec) {
+            Some(frame) => frame,
+            None => return self.base.scatter(r_in, rec),
+        };
+        let (layer, picked) = self.layer(rec, wo);
+        let rec = &picked;
+        match layer {
+            Layer::Coat => {
+                let distribution = self.distribution();
+                if distribution.is_smooth() {
+                    let reflected = reflect(unit_vector(r_in.direction()), rec.shading_normal);
+                    return Some(ScatterRecord {
+                        attenuation: Color::new(1.0, 1.0, 1.0),
+                        specular_ray: Some(rec.spawn_ray(reflected, r_in.time)),
+                        is_specular: true,
+                        pdf: None,
+                    });
+                }
+                Some(ScatterRecord {
+                    attenuation: Color::new(1.0, 1.0, 1.0),
+                    specular_ray: None,
+                    is_specular: false,
+                    pdf: Some(Box::new(GgxPDF::new(uvw, wo, distribution))),
+                })
+            }
+            Layer::Base => {
+                // the light the coat reflects on the way in is what picking the
+                // base already left out, so only its tint is applied there
+                let mut scatter_record = self.base.scatter(r_in, rec)?;
+                if let Some(specular_ray) = &scatter_record.specular_ray {
+                    let cos_i = dot(unit_vector(specular_ray.direction()), rec.shading_normal);
+                    scatter_record.attenuation = scatter_record.attenuation
+                        * self.absorption(rec, wo.z())
+                        * self.transmittance(rec, cos_i);
+                }
+                Some(scatter_record)
+            }
+        }
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        let (uvw, wo) = match self.frame(r_in, rec) {
+            Some(frame) => frame,
+            None => return self.base.pdf(r_in, rec, scattered),
+        };
+        let (layer, picked) = self.layer(rec, wo);
+        let rec = &picked;
+        match layer {
+            Layer::Coat => GgxPDF::new(uvw, wo, self.distribution()).value(scattered.direction()),
+            Layer::Base => self.base.pdf(r_in, rec, scattered),
+        }
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let (uvw, wo) = match self.frame(r_in, rec) {
+            Some(frame) => frame,
+            None => return self.base.eval(r_in, rec, scattered),
+        };
+        let wi = uvw.to_local(&unit_vector(scattered.direction()));
+        let (layer, picked) = self.layer(rec, wo);
+        let rec = &picked;
+        match layer {
+            Layer::Coat => {
+                let distribution = self.distribution();
+                if distribution.is_smooth()
+                    || wi.z() <= 0.0
+                    || dot(scattered.direction(), rec.normal) <= 0.0
+                {
+                    return Color::new(0.0, 0.0, 0.0);
+                }
+                // D * G / (4 cos_o cos_i) times cos_i, the fresnel term of the
+                // microfacet over the reflectance the layer was picked with
+                let eta = relative_ref_idx(self.ref_idx, rec);
+                let h = unit_vector(wo + wi);
+                let specular = distribution.d(h) * distribution.g(wo, wi) / (4.0 * wo.z());
+                let fresnel = fresnel_dielectric(dot(wi, h), eta) / fresnel_dielectric(wo.z(), eta);
+                Color::new(1.0, 1.0, 1.0) * to_f32(specular * fresnel)
+            }
+            Layer::Base => {
+                self.base.eval(r_in, rec, scattered)
+                    * self.absorption(rec, wo.z())
+                    * self.transmittance(rec, wi.z())
+            }
+        }
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        self.base.medium()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+
+    fn is_opaque(&self, r_in: &Ray, rec: &HitRecord) -> bool {
+        self.base.is_opaque(r_in, rec)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord, u: Float, v: Float, p: &Vector3) -> Color {
+        let emitted = self.base.emitted(r_in, rec, u, v, p);
+        match self.frame(r_in, rec) {
+            Some((_, wo)) => emitted * self.transmittance(rec, wo.z()),
+            None => emitted,
+        }
+    }
+}
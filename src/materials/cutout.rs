@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use super::super::float::Float;
+use super::super::hittable::HitRecord;
+use super::super::material::{Material, ScatterRecord};
+use super::super::medium::Medium;
+use super::super::ray::Ray;
+use super::super::texture::Texture;
+use super::super::vector3::{Color, Vector3};
+
+// holes where the alpha of the opacity is below the threshold, without
+// one fractional alpha lets that fraction of the rays through
+pub struct Cutout {
+    pub base: Arc<dyn Material>,
+    pub opacity: Arc<dyn Texture>,
+    pub threshold: Option<f32>,
+}
+
+impl Cutout {
+    // whether the surface is there, with the hit the base sees, whose sample
+    // is left independent of the one that let the ray stop here
+    fn covered(&self, rec: &HitRecord) -> (bool, HitRecord) {
+        let alpha = self.opacity.alpha(rec);
+        match self.threshold {
+            Some(threshold) => (alpha >= threshold, rec.clone()),
+            None => rec.pick(alpha as Float),
+        }
+    }
+}
+
+impl Material for Cutout {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let (_, rec) = self.covered(rec);
+        self.base.scatter(r_in, &rec)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        let (_, rec) = self.covered(rec);
+        self.base.pdf(r_in, &rec, scattered)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let (_, rec) = self.covered(rec);
+        self.base.eval(r_in, &rec, scattered)
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        self.base.medium()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+
+    fn is_opaque(&self, r_in: &Ray, rec: &HitRecord) -> bool {
+        let (covered, rec) = self.covered(rec);
+        covered && self.base.is_opaque(r_in, &rec)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord, u: Float, v: Float, p: &Vector3) -> Color {
+        self.base.emitted(r_in, rec, u, v, p)
+    }
+}
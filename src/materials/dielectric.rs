@@ -0,0 +1,386 @@
+use rand::Rng;
+
+use super::super::float::{to_f32, Float};
+use super::super::hittable::HitRecord;
+use super::super::material::{
+    fresnel_dielectric, luminance, reflect, refract, relative_ref_idx, ComplexIor, Material,
+    ScatterRecord,
+};
+use super::super::medium::Medium;
+use super::super::microfacet::{dielectric_bsdf_cos, half_vector, TrowbridgeReitz};
+use super::super::onb::ONB;
+use super::super::pdf::{RoughDielectricPDF, PDF};
+use super::super::ray::Ray;
+use super::super::thin_film::ThinFilm;
+use super::super::vector3::{dot, unit_vector, Color, Vector3};
+
+// GGX glass when roughness is above 0, absorption is the Beer-Lambert
+// coefficient per unit of distance inside
+pub struct Dielectric {
+    pub ref_idx: Float,
+    pub roughness: Float,
+    pub absorption: Color,
+    pub priority: u32,
+    pub film: Option<ThinFilm>,
+    pub dispersion: Option<Dispersion>,
+}
+
+// index of refraction over the wavelength in micrometers
+#[derive(Copy, Clone)]
+pub enum Dispersion {
+    // a + b / lambda^2
+    Cauchy { a: Float, b: Float },
+    // n^2 = 1 + sum of b lambda^2 / (lambda^2 - c)
+    Sellmeier { b: [Float; 3], c: [Float; 3] },
+}
+
+impl Dispersion {
+    pub const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.039_612, 0.231_792_3, 1.010_47],
+        c: [0.006_000_699, 0.020_017_91, 103.560_6],
+    };
+    pub const DIAMOND: Dispersion = Dispersion::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030_625, 0.011_236, 0.0],
+    };
+
+    // index at a wavelength in nanometers
+    pub fn ref_idx(&self, lambda: Float) -> Float {
+        let lambda = lambda / 1000.0;
+        let lambda2 = lambda * lambda;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / lambda2,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: Float = (0..3).map(|i| b[i] * lambda2 / (lambda2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
+impl Dielectric {
+    fn distribution(&self) -> TrowbridgeReitz {
+        TrowbridgeReitz::from_roughness(self.roughness, self.roughness)
+    }
+
+    // index at the hero wavelength of spectral rays
+    fn ref_idx_at(&self, r_in: &Ray) -> Float {
+        match (&self.dispersion, &r_in.wavelengths) {
+            (Some(dispersion), Some(wavelengths)) => dispersion.ref_idx(wavelengths.hero()),
+            _ => self.ref_idx,
+        }
+    }
+
+    // reflectance with the film between the side the light comes from
+    // and the other one, ref_idx is the index at the hero wavelength
+    fn film_reflectance(
+        &self,
+        film: &ThinFilm,
+        cosine: Float,
+        ref_idx: Float,
+        rec: &HitRecord,
+    ) -> Color {
+        let (outside, inside) = if rec.front_face {
+            (rec.eta_outside, ref_idx)
+        } else {
+            (ref_idx, rec.eta_outside)
+        };
+        let substrate = ComplexIor::dielectric(inside);
+        film.reflectance(cosine, outside, &substrate, rec)
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let distribution = self.distribution();
+        if !distribution.is_smooth() {
+            let uvw = ONB::from_tangent(rec.shading_normal, rec.dpdu);
+            let wo = uvw.to_local(&-unit_vector(r_in.direction()));
+            if wo.z() <= 0.0 {
+                return None;
+            }
+            let eta = relative_ref_idx(self.ref_idx_at(r_in), rec);
+            return Some(ScatterRecord {
+                attenuation: Color::new(1.0, 1.0, 1.0),
+                specular_ray: None,
+                is_specular: false,
+                pdf: Some(Box::new(RoughDielectricPDF::new(
+                    uvw,
+                    wo,
+                    eta,
+                    distribution,
+                ))),
+            });
+        }
+
+        let reflected: Vector3 = reflect(r_in.direction(), rec.shading_normal);
+        let attenuation = Color::new(1.0, 1.0, 1.0);
+        let random = rand::thread_rng().gen::<Float>();
+        let cosine: Float = -dot(r_in.direction(), rec.shading_normal) / r_in.direction().length();
+        let eta = relative_ref_idx(self.ref_idx_at(r_in), rec);
+
+        let refracted = refract(r_in.direction(), rec.shading_normal, 1.0 / eta);
+        // with a film the reflectance differs per channel, so the choice
+        // is made with the average and the channels weighted after
+        let reflectance = match (&self.film, refracted) {
+            (Some(film), Some(_)) => {
+                self.film_reflectance(film, cosine, self.ref_idx_at(r_in), rec)
+            }
+            _ => attenuation,
+        };
+        let reflect_prob: Float = match refracted {
+            Some(_) if self.film.is_some() => luminance(reflectance) as Float,
+            Some(_) => fresnel_dielectric(cosine, eta),
+            None => 1.0,
+        };
+
+        let (scattered, attenuation) = match refracted {
+            Some(refracted) if random >= reflect_prob => {
+                let transmitted = match &self.film {
+                    Some(_) => (attenuation - reflectance) / to_f32(1.0 - reflect_prob),
+                    None => attenuation,
+                };
+                (rec.spawn_ray(refracted, r_in.time), transmitted)
+            }
+            _ => {
+                let reflected_color = match &self.film {
+                    Some(_) if reflect_prob < 1.0 => reflectance / to_f32(reflect_prob),
+                    _ => attenuation,
+                };
+                (rec.spawn_ray(reflected, r_in.time), reflected_color)
+            }
+        };
+
+        Some(ScatterRecord {
+            attenuation,
+            specular_ray: Some(scattered),
+            is_specular: true,
+            pdf: None,
+        })
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        let uvw = ONB::from_tangent(rec.shading_normal, rec.dpdu);
+        let wo = uvw.to_local(&-unit_vector(r_in.direction()));
+        let eta = relative_ref_idx(self.ref_idx_at(r_in), rec);
+        RoughDielectricPDF::new(uvw, wo, eta, self.distribution()).value(scattered.direction())
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let below = dot(scattered.direction(), rec.normal) <= 0.0;
+        let uvw = ONB::from_tangent(rec.shading_normal, rec.dpdu);
+        let wo = uvw.to_local(&-unit_vector(r_in.direction()));
+        let wi = uvw.to_local(&unit_vector(scattered.direction()));
+        // the shading and geometric normals have to agree on the side
+        if wo.z() <= 0.0 || (wi.z() <= 0.0) != below {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let eta = relative_ref_idx(self.ref_idx_at(r_in), rec);
+        let value = dielectric_bsdf_cos(wo, wi, eta, &self.distribution());
+        let film = match &self.film {
+            Some(film) => film,
+            None => return Color::new(1.0, 1.0, 1.0) * to_f32(value),
+        };
+        // swap the plain fresnel term for the one of the film
+        let h = match half_vector(wo, wi, eta) {
+            Some(h) => h,
+            None => return Color::new(0.0, 0.0, 0.0),
+        };
+        let cosine = dot(wo, h);
+        let fresnel = fresnel_dielectric(cosine, eta);
+        let reflectance = self.film_reflectance(film, cosine, self.ref_idx_at(r_in), rec);
+        if wi.z() > 0.0 && fresnel > 0.0 {
+            reflectance * to_f32(value / fresnel)
+        } else if wi.z() <= 0.0 && fresnel < 1.0 {
+            (Color::new(1.0, 1.0, 1.0) - reflectance) * to_f32(value / (1.0 - fresnel))
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.dispersion.is_some()
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        Some(Medium {
+            ref_idx: self.ref_idx,
+            absorption: self.absorption,
+            scattering: Color::new(0.0, 0.0, 0.0),
+            priority: self.priority,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::float::consts::PI;
+    use super::super::super::hittable::fixtures::{seen_from, towards};
+    use super::super::super::utils::random_on_unit_sphere;
+    use super::*;
+
+    fn frosted(roughness: Float) -> Dielectric {
+        Dielectric {
+            ref_idx: 1.5,
+            roughness,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            priority: 0,
+            film: None,
+            dispersion: None,
+        }
+    }
+
+    // a hit on the xy plane from above, where the shading frame is the
+    // world frame, and the ray arriving from wo
+    fn direction(cos_theta: Float, phi: Float) -> Vector3 {
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+
+    // the F, d and C Fraunhofer lines
+    const F_LINE: Float = 486.13;
+    const D_LINE: Float = 587.56;
+    const C_LINE: Float = 656.27;
+
+    #[test]
+    fn dispersion_at_the_fraunhofer_lines() {
+        let bk7 = Dispersion::BK7;
+        assert!((bk7.ref_idx(F_LINE) - 1.5224).abs() < 2e-4);
+        assert!((bk7.ref_idx(D_LINE) - 1.5168).abs() < 2e-4);
+        assert!((bk7.ref_idx(C_LINE) - 1.5143).abs() < 2e-4);
+        assert!((Dispersion::DIAMOND.ref_idx(D_LINE) - 2.4175).abs() < 1e-3);
+        // the two term fit of BK7
+        let cauchy = Dispersion::Cauchy {
+            a: 1.5046,
+            b: 0.00420,
+        };
+        assert!((cauchy.ref_idx(D_LINE) - 1.5168).abs() < 1e-3);
+        assert!(cauchy.ref_idx(F_LINE) > cauchy.ref_idx(C_LINE));
+    }
+
+    #[test]
+    fn dispersion_follows_the_hero_wavelength() {
+        use super::super::super::spectrum::Wavelengths;
+        let prism = Dielectric {
+            dispersion: Some(Dispersion::BK7),
+            ..frosted(0.0)
+        };
+        let r = Ray::new(
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            0.0,
+        );
+        assert_eq!(prism.ref_idx_at(&r), 1.5);
+        let mut wavelengths = Wavelengths::sample(0.0);
+        wavelengths.lambda[0] = F_LINE;
+        let r = r.with_wavelengths(Some(wavelengths));
+        assert_eq!(prism.ref_idx_at(&r), Dispersion::BK7.ref_idx(F_LINE));
+    }
+
+    // the bsdf without the cosine
+    fn bsdf(wo: Vector3, wi: Vector3, eta: Float, distribution: &TrowbridgeReitz) -> Float {
+        dielectric_bsdf_cos(wo, wi, eta, distribution) / wi.z().abs()
+    }
+
+    #[test]
+    fn rough_bsdf_is_reciprocal() {
+        let distribution = TrowbridgeReitz::from_roughness(0.5, 0.5);
+        let eta = 1.5;
+        for i in 0..50 {
+            let wo = direction(0.1 + 0.018 * i as Float, 0.3 * i as Float);
+            for j in 0..50 {
+                let wi = direction(-0.95 + 0.038 * j as Float, 1.0 + 0.7 * j as Float);
+                if wi.z().abs() < 0.05 {
+                    continue;
+                }
+                let forward = bsdf(wo, wi, eta, &distribution);
+                if wi.z() > 0.0 {
+                    let backward = bsdf(wi, wo, eta, &distribution);
+                    assert!((forward - backward).abs() <= 1e-3 * forward.max(1.0));
+                } else {
+                    // the same path from inside, radiance is scaled by the
+                    // square of the index on the side it goes to
+                    let backward = bsdf(-wi, -wo, 1.0 / eta, &distribution);
+                    let expected = forward * eta * eta;
+                    assert!(
+                        (expected - backward).abs() <= 1e-3 * expected.max(1.0),
+                        "{} against {}",
+                        expected,
+                        backward
+                    );
+                }
+            }
+        }
+    }
+
+    // sampling with the pdf and weighing by eval integrates eval over
+    // both sides, the same as a plain sum over the sphere
+    #[test]
+    fn rough_eval_and_pdf_agree() {
+        let glass = frosted(0.8);
+        for &cos_o in &[0.9, 0.4] {
+            let (r_in, rec) = seen_from(direction(cos_o, 0.5));
+            let pdf = glass.scatter(&r_in, &rec).unwrap().pdf.unwrap();
+            const SAMPLES: usize = 200_000;
+            let mut sampled = [0.0, 0.0];
+            let mut summed = [0.0, 0.0];
+            for _ in 0..SAMPLES {
+                let wi = pdf.generate();
+                let density = glass.pdf(&r_in, &rec, &towards(wi));
+                assert!((density - pdf.value(wi)).abs() <= 1e-4 * density.max(1.0));
+                let value = glass.eval(&r_in, &rec, &towards(wi)).r() as Float;
+                if value > 0.0 {
+                    assert!(density > 0.0);
+                    sampled[(wi.z() < 0.0) as usize] += value / density / SAMPLES as Float;
+                }
+
+                let wi = random_on_unit_sphere();
+                let value = glass.eval(&r_in, &rec, &towards(wi)).r() as Float;
+                summed[(wi.z() < 0.0) as usize] += value * 4.0 * PI / SAMPLES as Float;
+            }
+            for side in 0..2 {
+                assert!(
+                    (sampled[side] - summed[side]).abs() < 0.02 + 0.03 * summed[side],
+                    "side {}: sampled {} summed {}",
+                    side,
+                    sampled[side],
+                    summed[side]
+                );
+            }
+        }
+    }
+
+    // nothing is absorbed, so what is reflected and what is transmitted,
+    // without the compression of radiance on the far side, adds up to one
+    // up to the shadowing of the microfacets
+    #[test]
+    fn rough_white_furnace() {
+        for &(ref_idx, roughness) in &[(1.5, 0.3), (1.0 / 1.5, 0.3), (1.33, 0.5)] {
+            let glass = Dielectric {
+                ref_idx,
+                ..frosted(roughness)
+            };
+            let (r_in, rec) = seen_from(direction(0.7, 0.0));
+            let pdf = glass.scatter(&r_in, &rec).unwrap().pdf.unwrap();
+            const SAMPLES: usize = 100_000;
+            let mut energy = 0.0;
+            for _ in 0..SAMPLES {
+                let wi = pdf.generate();
+                let density = glass.pdf(&r_in, &rec, &towards(wi));
+                if density <= 0.0 {
+                    continue;
+                }
+                let value = glass.eval(&r_in, &rec, &towards(wi)).r() as Float;
+                let compression = if wi.z() < 0.0 { ref_idx * ref_idx } else { 1.0 };
+                energy += value * compression / density / SAMPLES as Float;
+            }
+            assert!(
+                energy > 0.93 && energy < 1.01,
+                "index {} roughness {}: {}",
+                ref_idx,
+                roughness,
+                energy
+            );
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use std::sync::Arc;
+
+use super::super::float::Float;
+use super::super::hittable::HitRecord;
+use super::super::material::Material;
+use super::super::ray::Ray;
+use super::super::texture::Texture;
+use super::super::vector3::{Color, Vector3};
+
+pub struct DiffuseLight {
+    pub emit: Arc<dyn Texture>,
+}
+
+impl Material for DiffuseLight {
+    fn emitted(&self, _r_in: &Ray, rec: &HitRecord, _u: Float, _v: Float, _p: &Vector3) -> Color {
+        if rec.front_face {
+            self.emit.value(rec)
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+}
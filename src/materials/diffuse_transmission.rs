@@ -0,0 +1,159 @@
+use std::sync::Arc;
+
+use super::super::float::consts;
+use super::super::float::{to_f32, Float};
+use super::super::hittable::HitRecord;
+use super::super::material::{luminance, Material, ScatterRecord};
+use super::super::pdf::{CosinePDF, WeightedPDF, PDF};
+use super::super::ray::Ray;
+use super::super::texture::Texture;
+use super::super::vector3::{dot, unit_vector, Color};
+
+// thin two-sided diffuser like paper, lampshades or leaves, light is scattered
+// diffusely back by the reflectance and through to the other side by the
+// transmittance, where they add up to more than one both are scaled down
+pub struct DiffuseTransmission {
+    pub reflectance: Arc<dyn Texture>,
+    pub transmittance: Arc<dyn Texture>,
+}
+
+impl DiffuseTransmission {
+    // the reflectance and transmittance at the hit, scattering no more than arrives
+    fn lobes(&self, rec: &HitRecord) -> (Color, Color) {
+        let mut reflectance = self.reflectance.value(rec);
+        let mut transmittance = self.transmittance.value(rec);
+        for i in 0..3 {
+            let total = reflectance[i] + transmittance[i];
+            if total > 1.0 {
+                reflectance[i] /= total;
+                transmittance[i] /= total;
+            }
+        }
+        (reflectance, transmittance)
+    }
+
+    // a cosine lobe on each side, picked in proportion to how much they scatter
+    fn sampling_pdf(&self, rec: &HitRecord) -> Option<WeightedPDF> {
+        let (reflectance, transmittance) = self.lobes(rec);
+        let reflected = luminance(reflectance) as Float;
+        let transmitted = luminance(transmittance) as Float;
+        if reflected + transmitted <= 0.0 {
+            return None;
+        }
+        Some(WeightedPDF::new(vec![
+            (reflected, Box::new(CosinePDF::new(rec.shading_normal))),
+            (transmitted, Box::new(CosinePDF::new(-rec.shading_normal))),
+        ]))
+    }
+}
+
+impl Material for DiffuseTransmission {
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.sampling_pdf(rec).map(|pdf| ScatterRecord {
+            specular_ray: None,
+            is_specular: false,
+            attenuation: self.lobes(rec).0,
+            pdf: Some(Box::new(pdf)),
+        })
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        self.sampling_pdf(rec)
+            .map_or(0.0, |pdf| pdf.value(scattered.direction()))
+    }
+
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        // which side the light comes from is up to the geometric normal
+        let direction = unit_vector(scattered.direction());
+        let cosine = dot(direction, rec.shading_normal);
+        let (reflectance, transmittance) = self.lobes(rec);
+        if dot(direction, rec.normal) > 0.0 {
+            reflectance * to_f32(cosine.max(0.0) / consts::PI)
+        } else {
+            transmittance * to_f32((-cosine).max(0.0) / consts::PI)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::hittable::fixtures::{seen_from, towards};
+    use super::super::super::texture::solid;
+    use super::super::super::utils::random_on_unit_sphere;
+    use super::super::super::vector3::Vector3;
+    use super::*;
+
+    fn paper(reflectance: Color, transmittance: Color) -> DiffuseTransmission {
+        DiffuseTransmission {
+            reflectance: solid(reflectance),
+            transmittance: solid(transmittance),
+        }
+    }
+
+    #[test]
+    fn scatter_pdf_matches_pdf_on_both_sides() {
+        let (reflectance, transmittance) = (Color::new(0.5, 0.4, 0.3), Color::new(0.2, 0.3, 0.1));
+        let material = paper(reflectance, transmittance);
+        let (r_in, rec) = seen_from(Vector3::new(0.3, -0.2, 1.0));
+        let pdf = material.scatter(&r_in, &rec).unwrap().pdf.unwrap();
+        const SAMPLES: usize = 20_000;
+        let mut below = 0;
+        for _ in 0..SAMPLES {
+            let sampled = pdf.generate();
+            below += (sampled.z() < 0.0) as usize;
+            for &direction in &[sampled, random_on_unit_sphere()] {
+                let value = material.pdf(&r_in, &rec, &towards(direction));
+                assert!((pdf.value(direction) - value).abs() < 1e-6);
+                // each side is a cosine lobe weighted by its share
+                let cosine = unit_vector(direction).z();
+                let share = if cosine > 0.0 {
+                    luminance(reflectance) / (luminance(reflectance) + luminance(transmittance))
+                } else {
+                    luminance(transmittance) / (luminance(reflectance) + luminance(transmittance))
+                };
+                let expected = share as Float * cosine.abs() / consts::PI;
+                assert!((value - expected).abs() < 1e-4);
+            }
+        }
+        let share = luminance(transmittance) / (luminance(reflectance) + luminance(transmittance));
+        let fraction = below as f32 / SAMPLES as f32;
+        assert!((fraction - share).abs() < 0.02, "{} below", fraction);
+    }
+
+    // what is reflected and transmitted together is at most what arrives
+    #[test]
+    fn scatters_no_more_than_arrives() {
+        let (r_in, rec) = seen_from(Vector3::new(0.3, -0.2, 1.0));
+        for &(reflectance, transmittance, expected) in &[
+            (
+                Color::new(0.6, 0.3, 0.1),
+                Color::new(0.4, 0.2, 0.0),
+                Color::new(1.0, 0.5, 0.1),
+            ),
+            (
+                Color::new(0.8, 1.0, 0.5),
+                Color::new(0.7, 0.5, 0.2),
+                Color::new(1.0, 1.0, 0.7),
+            ),
+        ] {
+            let material = paper(reflectance, transmittance);
+            // a sum over cells of equal solid angle
+            const STEPS: usize = 200;
+            let cell = to_f32(4.0 * consts::PI / (STEPS * STEPS) as Float);
+            let mut scattered = Color::new(0.0, 0.0, 0.0);
+            for i in 0..STEPS {
+                let z = -1.0 + 2.0 * (i as Float + 0.5) / STEPS as Float;
+                let r = (1.0 - z * z).sqrt();
+                for j in 0..STEPS {
+                    let phi = 2.0 * consts::PI * (j as Float + 0.5) / STEPS as Float;
+                    let direction = Vector3::new(r * phi.cos(), r * phi.sin(), z);
+                    scattered += material.eval(&r_in, &rec, &towards(direction)) * cell;
+                }
+            }
+            for i in 0..3 {
+                assert!(scattered[i] <= 1.0 + 1e-3);
+                assert!((scattered[i] - expected[i]).abs() < 1e-3);
+            }
+        }
+    }
+}
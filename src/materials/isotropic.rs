@@ -0,0 +1,23 @@
+use std::sync::Arc;
+
+use super::super::hittable::HitRecord;
+use super::super::material::{Material, ScatterRecord};
+use super::super::ray::Ray;
+use super::super::texture::Texture;
+use super::super::utils::random_on_unit_sphere;
+
+// phase function of participating media, scatters equally in all directions
+pub struct Isotropic {
+    pub texture: Arc<dyn Texture>,
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            attenuation: self.texture.value(rec),
+            specular_ray: Some(rec.spawn_ray(random_on_unit_sphere(), r_in.time)),
+            is_specular: true,
+            pdf: None,
+        })
+    }
+}
@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use super::super::float::consts;
+use super::super::float::{to_f32, Float};
+use super::super::hittable::HitRecord;
+use super::super::material::{Material, ScatterRecord};
+use super::super::pdf::CosinePDF;
+use super::super::ray::Ray;
+use super::super::texture::Texture;
+use super::super::vector3::{dot, unit_vector, Color};
+
+pub struct Lambertian {
+    pub albedo: Arc<dyn Texture>,
+}
+
+impl Material for Lambertian {
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            specular_ray: None,
+            is_specular: false,
+            attenuation: self.albedo.value(rec),
+            pdf: Some(Box::new(CosinePDF::new(rec.shading_normal))),
+        })
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        let mut cosine: Float = dot(rec.shading_normal, unit_vector(scattered.direction()));
+        if cosine < 0.0 {
+            cosine = 0.0;
+        }
+        cosine / consts::PI
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        // only transmissive materials let light in from below the geometric surface
+        if dot(scattered.direction(), rec.normal) <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.albedo.value(rec) * to_f32(self.pdf(r_in, rec, scattered))
+    }
+}
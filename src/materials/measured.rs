@@ -0,0 +1,287 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::sync::Arc;
+
+use super::super::float::consts;
+use super::super::float::{to_f32, Float};
+use super::super::hittable::HitRecord;
+use super::super::material::{Material, ScatterRecord};
+use super::super::onb::ONB;
+use super::super::pdf::{CosinePDF, HalfAngleTable, TabulatedPDF, WeightedPDF, PDF};
+use super::super::ray::Ray;
+use super::super::vector3::{dot, unit_vector, Color, Vector3};
+
+// resolution of the MERL tables in theta half, theta diff and phi diff
+const THETA_HALF: usize = 90;
+const THETA_DIFF: usize = 90;
+const PHI_DIFF: usize = 180;
+const SIZE: usize = THETA_HALF * THETA_DIFF * PHI_DIFF;
+
+// the channels are stored scaled by these
+const SCALE: [f64; 3] = [1.0 / 1500.0, 1.15 / 1500.0, 1.66 / 1500.0];
+
+// share of the samples drawn from the table, the rest are cosine
+// weighted to cover what the table misses
+const TABLE_WEIGHT: Float = 0.75;
+
+// a BRDF measured by Matusik et al. 2003 and stored in the `.binary` format
+// of the MERL database, tabulated over the half and difference angles of
+// Rusinkiewicz's parameterization
+pub struct Measured {
+    // every sample in rgb, negative where nothing was measured
+    samples: Vec<[f32; 3]>,
+    table: Arc<HalfAngleTable>,
+}
+
+fn read_i32(reader: &mut impl Read) -> io::Result<i32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(i32::from_le_bytes(bytes))
+}
+
+fn read_f64(reader: &mut impl Read) -> io::Result<f64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+// angle to the normal of the half vectors at a table index, the
+// table is denser close to the normal where highlights are sharp
+fn theta_half_at(index: Float) -> Float {
+    let t = index / THETA_HALF as Float;
+    t * t * consts::FRAC_PI_2
+}
+
+impl Measured {
+    pub fn load(path: &str) -> io::Result<Self> {
+        Measured::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let dimensions = [read_i32(reader)?, read_i32(reader)?, read_i32(reader)?];
+        if dimensions != [THETA_HALF as i32, THETA_DIFF as i32, PHI_DIFF as i32] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected MERL dimensions {:?}", dimensions),
+            ));
+        }
+        let mut samples = vec![[0.0; 3]; SIZE];
+        for (channel, scale) in SCALE.iter().enumerate() {
+            for sample in samples.iter_mut() {
+                sample[channel] = (read_f64(reader)? * scale) as f32;
+            }
+        }
+        let table = Arc::new(Measured::half_angle_table(&samples));
+        Ok(Measured { samples, table })
+    }
+
+    // probability of each band of theta half by the light the material
+    // reflects there, averaged over the difference angles
+    fn half_angle_table(samples: &[[f32; 3]]) -> HalfAngleTable {
+        let edges: Vec<Float> = (0..=THETA_HALF)
+            .map(|i| theta_half_at(i as Float).cos())
+            .collect();
+        let weights: Vec<Float> = samples
+            .chunks(THETA_DIFF * PHI_DIFF)
+            .zip(edges.windows(2))
+            .map(|(band, edge)| {
+                let sum: f32 = band
+                    .iter()
+                    .map(|sample| sample.iter().map(|c| c.max(0.0)).sum::<f32>())
+                    .sum();
+                sum as Float * (edge[0] - edge[1])
+            })
+            .collect();
+        let total: Float = weights.iter().sum();
+        let mut cdf = vec![0.0; THETA_HALF + 1];
+        for (i, weight) in weights.iter().enumerate() {
+            cdf[i + 1] = if total > 0.0 {
+                cdf[i] + weight / total
+            } else {
+                (i + 1) as Float / THETA_HALF as Float
+            };
+        }
+        cdf[THETA_HALF] = 1.0;
+        HalfAngleTable { edges, cdf }
+    }
+
+    fn sample(&self, theta_half: usize, theta_diff: usize, phi_diff: usize) -> Color {
+        let [r, g, b] = self.samples[phi_diff + PHI_DIFF * (theta_diff + THETA_DIFF * theta_half)];
+        Color::new(r.max(0.0), g.max(0.0), b.max(0.0))
+    }
+
+    // the BRDF between two directions in the local frame, interpolated
+    // between the eight samples around their angles
+    fn value(&self, wo: Vector3, wi: Vector3) -> Color {
+        let h = unit_vector(wo + wi);
+        let theta_half = h.z().clamp(-1.0, 1.0).acos();
+        let phi_half = h.y().atan2(h.x());
+        // wi in the frame where the half vector is the normal
+        let (sin_p, cos_p) = phi_half.sin_cos();
+        let (sin_t, cos_t) = theta_half.sin_cos();
+        let x = wi.x() * cos_p + wi.y() * sin_p;
+        let y = wi.y() * cos_p - wi.x() * sin_p;
+        let diff = Vector3::new(x * cos_t - wi.z() * sin_t, y, x * sin_t + wi.z() * cos_t);
+        let theta_diff = diff.z().clamp(-1.0, 1.0).acos();
+        // reciprocity makes phi diff and phi diff + pi the same
+        let mut phi_diff = diff.y().atan2(diff.x());
+        if phi_diff < 0.0 {
+            phi_diff += consts::PI;
+        }
+
+        let last = |n: usize| (n - 1) as Float;
+        let coordinates = [
+            ((theta_half / consts::FRAC_PI_2).sqrt() * THETA_HALF as Float).min(last(THETA_HALF)),
+            (theta_diff / consts::FRAC_PI_2 * THETA_DIFF as Float).min(last(THETA_DIFF)),
+            phi_diff / consts::PI * PHI_DIFF as Float,
+        ];
+        let lower = coordinates.map(|c| c.floor());
+        let t = [0, 1, 2].map(|i| to_f32(coordinates[i] - lower[i]));
+        let lower = lower.map(|c| c as usize);
+        let mut value = Color::new(0.0, 0.0, 0.0);
+        for corner in 0..8 {
+            let pick = |i: usize| corner >> i & 1 == 1;
+            let weight = (0..3)
+                .map(|i| if pick(i) { t[i] } else { 1.0 - t[i] })
+                .product::<f32>();
+            if weight == 0.0 {
+                continue;
+            }
+            let theta_half = (lower[0] + pick(0) as usize).min(THETA_HALF - 1);
+            let theta_diff = (lower[1] + pick(1) as usize).min(THETA_DIFF - 1);
+            // phi diff wraps around
+            let phi_diff = (lower[2] + pick(2) as usize) % PHI_DIFF;
+            value += self.sample(theta_half, theta_diff, phi_diff) * weight;
+        }
+        value
+    }
+
+    fn sampling_pdf(&self, r_in: &Ray, rec: &HitRecord) -> WeightedPDF {
+        let uvw = ONB::new(rec.shading_normal);
+        let wo = uvw.to_local(&-unit_vector(r_in.direction()));
+        WeightedPDF::new(vec![
+            (
+                TABLE_WEIGHT,
+                Box::new(TabulatedPDF::new(uvw, wo, self.table.clone())),
+            ),
+            (
+                1.0 - TABLE_WEIGHT,
+                Box::new(CosinePDF::new(rec.shading_normal)),
+            ),
+        ])
+    }
+}
+
+impl Material for Measured {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        if dot(r_in.direction(), rec.shading_normal) >= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord {
+            specular_ray: None,
+            is_specular: false,
+            attenuation: Color::new(1.0, 1.0, 1.0),
+            pdf: Some(Box::new(self.sampling_pdf(r_in, rec))),
+        })
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        self.sampling_pdf(r_in, rec).value(scattered.direction())
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        if dot(scattered.direction(), rec.normal) <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let uvw = ONB::new(rec.shading_normal);
+        let wo = uvw.to_local(&-unit_vector(r_in.direction()));
+        let wi = uvw.to_local(&unit_vector(scattered.direction()));
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.value(wo, wi) * to_f32(wi.z())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a MERL file whose stored value is `stored(channel, theta half,
+    // theta diff, phi diff)`
+    fn merl(stored: impl Fn(usize, usize, usize, usize) -> f64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for n in &[THETA_HALF, THETA_DIFF, PHI_DIFF] {
+            bytes.extend_from_slice(&(*n as i32).to_le_bytes());
+        }
+        for channel in 0..3 {
+            for theta_half in 0..THETA_HALF {
+                for theta_diff in 0..THETA_DIFF {
+                    for phi_diff in 0..PHI_DIFF {
+                        let value = stored(channel, theta_half, theta_diff, phi_diff);
+                        bytes.extend_from_slice(&value.to_le_bytes());
+                    }
+                }
+            }
+        }
+        bytes
+    }
+
+    fn near(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn channels_are_scaled() {
+        let measured = Measured::read(&mut &merl(|_, _, _, _| 1500.0)[..]).unwrap();
+        let c = measured.sample(12, 34, 56);
+        assert!(near(c.r(), 1.0) && near(c.g(), 1.15) && near(c.b(), 1.66));
+        let wi = unit_vector(Vector3::new(0.3, -0.2, 0.8));
+        let c = measured.value(Vector3::new(0.0, 0.0, 1.0), wi);
+        assert!(near(c.r(), 1.0) && near(c.g(), 1.15) && near(c.b(), 1.66));
+    }
+
+    // each channel stores one of the indices, so the interpolated value is
+    // where the directions land in the table
+    fn indices() -> Measured {
+        let bytes = merl(|channel, theta_half, theta_diff, phi_diff| {
+            [theta_half, theta_diff, phi_diff][channel] as f64 / SCALE[channel]
+        });
+        Measured::read(&mut &bytes[..]).unwrap()
+    }
+
+    #[test]
+    fn theta_half_is_indexed_by_its_square_root() {
+        let measured = indices();
+        let theta = consts::FRAC_PI_2 * (30.0 / 90.0) * (30.0 / 90.0);
+        let w = Vector3::new(theta.sin(), 0.0, theta.cos());
+        let c = measured.value(w, w);
+        // the acos of theta diff is only good to a few hundredths near 0 in f32
+        assert!(near(c.r(), 30.0) && c.g() < 0.05);
+    }
+
+    #[test]
+    fn difference_angles_are_indexed_linearly() {
+        let measured = indices();
+        let theta = consts::FRAC_PI_4;
+        let phi = consts::PI / 3.0;
+        let wi = Vector3::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        );
+        let wo = Vector3::new(-wi.x(), -wi.y(), wi.z());
+        let c = measured.value(wo, wi);
+        assert!(near(c.r(), 0.0) && near(c.g(), 45.0) && near(c.b(), 60.0));
+        // reciprocity folds phi diff into [0, pi)
+        let c = measured.value(wi, wo);
+        assert!(near(c.g(), 45.0) && near(c.b(), 60.0));
+    }
+
+    #[test]
+    fn other_dimensions_are_rejected() {
+        let mut bytes = merl(|_, _, _, _| 1.0);
+        bytes[0] = 89;
+        assert!(Measured::read(&mut &bytes[..]).is_err());
+    }
+}
@@ -0,0 +1,81 @@
+use super::super::float::{to_f32, Float};
+use super::super::hittable::HitRecord;
+use super::super::material::{fresnel_conductor, reflect, ComplexIor, Material, ScatterRecord};
+use super::super::microfacet::TrowbridgeReitz;
+use super::super::onb::ONB;
+use super::super::pdf::{GgxPDF, PDF};
+use super::super::ray::Ray;
+use super::super::thin_film::ThinFilm;
+use super::super::vector3::{dot, unit_vector, Color};
+
+// GGX microfacet conductor, roughness_u and roughness_v follow
+// the dpdu and dpdv tangents and are equal for isotropic metals, a film
+// makes it iridescent like anodized titanium or heat tinted steel
+pub struct Metal {
+    pub ior: ComplexIor,
+    pub roughness_u: Float,
+    pub roughness_v: Float,
+    pub film: Option<ThinFilm>,
+}
+
+impl Metal {
+    fn distribution(&self) -> TrowbridgeReitz {
+        TrowbridgeReitz::from_roughness(self.roughness_u, self.roughness_v)
+    }
+
+    fn fresnel(&self, cosine: Float, rec: &HitRecord) -> Color {
+        match &self.film {
+            Some(film) => film.reflectance(cosine, rec.eta_outside, &self.ior, rec),
+            None => fresnel_conductor(cosine, &self.ior),
+        }
+    }
+}
+
+impl Material for Metal {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let distribution = self.distribution();
+        let uvw = ONB::from_tangent(rec.shading_normal, rec.dpdu);
+        let wo = uvw.to_local(&-unit_vector(r_in.direction()));
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        if distribution.is_smooth() {
+            let reflected = reflect(unit_vector(r_in.direction()), rec.shading_normal);
+            return Some(ScatterRecord {
+                attenuation: self.fresnel(wo.z(), rec),
+                specular_ray: Some(rec.spawn_ray(reflected, r_in.time)),
+                is_specular: true,
+                pdf: None,
+            });
+        }
+        Some(ScatterRecord {
+            attenuation: Color::new(1.0, 1.0, 1.0),
+            specular_ray: None,
+            is_specular: false,
+            pdf: Some(Box::new(GgxPDF::new(uvw, wo, distribution))),
+        })
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        let uvw = ONB::from_tangent(rec.shading_normal, rec.dpdu);
+        let wo = uvw.to_local(&-unit_vector(r_in.direction()));
+        GgxPDF::new(uvw, wo, self.distribution()).value(scattered.direction())
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        if dot(scattered.direction(), rec.normal) <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let uvw = ONB::from_tangent(rec.shading_normal, rec.dpdu);
+        let wo = uvw.to_local(&-unit_vector(r_in.direction()));
+        let wi = uvw.to_local(&unit_vector(scattered.direction()));
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let distribution = self.distribution();
+        let h = unit_vector(wo + wi);
+        // D * G * F / (4 cos_o cos_i), times cos_i
+        let specular = distribution.d(h) * distribution.g(wo, wi) / (4.0 * wo.z());
+        self.fresnel(dot(wi, h), rec) * to_f32(specular)
+    }
+}
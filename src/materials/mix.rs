@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use super::super::float::Float;
+use super::super::hittable::HitRecord;
+use super::super::material::{Material, ScatterRecord};
+use super::super::ray::Ray;
+use super::super::texture::Texture;
+use super::super::vector3::{Color, Vector3};
+
+// the red channel of the mask is how much of `b` shows through `a`,
+// each hit picks one of them so the blend is only exact on average
+pub struct Mix {
+    pub a: Arc<dyn Material>,
+    pub b: Arc<dyn Material>,
+    pub mask: Arc<dyn Texture>,
+}
+
+impl Mix {
+    fn weight(&self, rec: &HitRecord) -> f32 {
+        self.mask.value(rec).r().clamp(0.0, 1.0)
+    }
+
+    fn pick(&self, rec: &HitRecord) -> (&dyn Material, HitRecord) {
+        match rec.pick(self.weight(rec) as Float) {
+            (true, picked) => (&*self.b, picked),
+            (false, picked) => (&*self.a, picked),
+        }
+    }
+}
+
+impl Material for Mix {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let (material, rec) = self.pick(rec);
+        material.scatter(r_in, &rec)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        let (material, rec) = self.pick(rec);
+        material.pdf(r_in, &rec, scattered)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let (material, rec) = self.pick(rec);
+        material.eval(r_in, &rec, scattered)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.a.is_dispersive() || self.b.is_dispersive()
+    }
+
+    fn is_opaque(&self, r_in: &Ray, rec: &HitRecord) -> bool {
+        let (material, rec) = self.pick(rec);
+        material.is_opaque(r_in, &rec)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord, u: Float, v: Float, p: &Vector3) -> Color {
+        let weight = self.weight(rec);
+        self.a.emitted(r_in, rec, u, v, p) * (1.0 - weight)
+            + self.b.emitted(r_in, rec, u, v, p) * weight
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::hittable::fixtures::{seen_from, towards};
+    use super::super::super::texture::{constant, solid};
+    use super::super::lambertian::Lambertian;
+    use super::*;
+
+    #[test]
+    fn the_sample_of_the_hit_picks_the_material() {
+        let lambertian = |albedo: Color| -> Arc<dyn Material> {
+            Arc::new(Lambertian {
+                albedo: solid(albedo),
+            })
+        };
+        let mix = Mix {
+            a: lambertian(Color::new(1.0, 0.0, 0.0)),
+            b: lambertian(Color::new(0.0, 0.0, 1.0)),
+            mask: constant(0.3),
+        };
+        let (r_in, mut rec) = seen_from(Vector3::new(0.0, 0.0, 1.0));
+        let scattered = towards(Vector3::new(0.0, 0.0, 1.0));
+        for &(sample, b) in &[(0.1, true), (0.29, true), (0.31, false), (0.9, false)] {
+            rec.sample = sample;
+            // every call on the same hit agrees
+            for _ in 0..10 {
+                let value = mix.eval(&r_in, &rec, &scattered);
+                assert_eq!(value.b() > 0.0, b);
+                assert_eq!(value.r() > 0.0, !b);
+            }
+        }
+    }
+}
@@ -0,0 +1,15 @@
+pub mod cloth;
+pub mod coated;
+pub mod cutout;
+pub mod dielectric;
+pub mod diffuse_light;
+pub mod diffuse_transmission;
+pub mod isotropic;
+pub mod lambertian;
+pub mod measured;
+pub mod metal;
+pub mod mix;
+pub mod oren_nayar;
+pub mod principled;
+pub mod registry;
+pub mod subsurface;
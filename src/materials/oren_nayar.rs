@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use super::super::float::consts;
+use super::super::float::{to_f32, Float};
+use super::super::hittable::HitRecord;
+use super::super::material::{Material, ScatterRecord};
+use super::super::onb::ONB;
+use super::super::pdf::{CosinePDF, PDF};
+use super::super::ray::Ray;
+use super::super::texture::Texture;
+use super::super::vector3::{dot, unit_vector, Color};
+
+// rough diffuse surface made of lambertian microfacets (Oren and Nayar 1994),
+// sigma is the standard deviation of the facet angles in degrees, 0 is the
+// same as `Lambertian` and clay or concrete are around 20 to 30
+pub struct OrenNayar {
+    pub albedo: Arc<dyn Texture>,
+    pub sigma: Float,
+}
+
+impl OrenNayar {
+    // the A and B terms of the qualitative model
+    fn coefficients(&self) -> (Float, Float) {
+        let sigma = self.sigma.to_radians();
+        let sigma2 = sigma * sigma;
+        (
+            1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+            0.45 * sigma2 / (sigma2 + 0.09),
+        )
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            specular_ray: None,
+            is_specular: false,
+            attenuation: self.albedo.value(rec),
+            pdf: Some(Box::new(CosinePDF::new(rec.shading_normal))),
+        })
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        CosinePDF::new(rec.shading_normal).value(scattered.direction())
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        if dot(scattered.direction(), rec.normal) <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let uvw = ONB::from_tangent(rec.shading_normal, rec.dpdu);
+        let wo = uvw.to_local(&-unit_vector(r_in.direction()));
+        let wi = uvw.to_local(&unit_vector(scattered.direction()));
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let (a, b) = self.coefficients();
+        let sin_o = (1.0 - wo.z() * wo.z()).max(0.0).sqrt();
+        let sin_i = (1.0 - wi.z() * wi.z()).max(0.0).sqrt();
+        // cosine of the azimuth between the directions
+        let mut max_cos = 0.0;
+        if sin_o > 1.0e-4 && sin_i > 1.0e-4 {
+            let cos_phi = (wo.x() * wi.x() + wo.y() * wi.y()) / (sin_o * sin_i);
+            max_cos = cos_phi.max(0.0);
+        }
+        // sin(alpha) * tan(beta), alpha the larger of the two angles
+        let (sin_alpha, tan_beta) = if wi.z() > wo.z() {
+            (sin_o, sin_i / wi.z())
+        } else {
+            (sin_i, sin_o / wo.z())
+        };
+        let factor = (a + b * max_cos * sin_alpha * tan_beta) * wi.z() / consts::PI;
+        self.albedo.value(rec) * to_f32(factor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::hittable::fixtures::{seen_from, towards};
+    use super::super::super::texture::solid;
+    use super::super::super::utils::random_on_unit_sphere;
+    use super::super::super::vector3::Vector3;
+    use super::super::lambertian::Lambertian;
+    use super::*;
+
+    fn clay(sigma: Float) -> OrenNayar {
+        OrenNayar {
+            albedo: solid(Color::new(0.8, 0.5, 0.3)),
+            sigma,
+        }
+    }
+
+    #[test]
+    fn smooth_is_lambertian() {
+        let rough = clay(0.0);
+        let lambertian = Lambertian {
+            albedo: solid(Color::new(0.8, 0.5, 0.3)),
+        };
+        for _ in 0..1000 {
+            let (r_in, rec) = seen_from(random_on_unit_sphere() + Vector3::new(0.0, 0.0, 1.0));
+            let scattered = towards(random_on_unit_sphere());
+            let a = rough.eval(&r_in, &rec, &scattered);
+            let b = lambertian.eval(&r_in, &rec, &scattered);
+            assert!((a - b).length() < 1e-6);
+            let pdf_a = rough.pdf(&r_in, &rec, &scattered);
+            assert!((pdf_a - lambertian.pdf(&r_in, &rec, &scattered)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn scatter_pdf_matches_pdf() {
+        let material = clay(25.0);
+        let (r_in, rec) = seen_from(Vector3::new(0.5, 0.2, 0.6));
+        let pdf = material.scatter(&r_in, &rec).unwrap().pdf.unwrap();
+        for _ in 0..1000 {
+            for &direction in &[pdf.generate(), random_on_unit_sphere()] {
+                let value = material.pdf(&r_in, &rec, &towards(direction));
+                assert!((pdf.value(direction) - value).abs() < 1e-6);
+            }
+        }
+    }
+
+    // the rough facets shadow each other, so less than the albedo comes back
+    #[test]
+    fn rough_reflects_less_than_the_albedo() {
+        const STEPS: usize = 200;
+        for &cosine in &[0.95 as Float, 0.5, 0.1] {
+            let (r_in, rec) = seen_from(Vector3::new((1.0 - cosine * cosine).sqrt(), 0.0, cosine));
+            let cell = to_f32(2.0 * consts::PI / (STEPS * STEPS) as Float);
+            let mut reflected = Color::new(0.0, 0.0, 0.0);
+            for i in 0..STEPS {
+                let z = (i as Float + 0.5) / STEPS as Float;
+                let r = (1.0 - z * z).sqrt();
+                for j in 0..STEPS {
+                    let phi = 2.0 * consts::PI * (j as Float + 0.5) / STEPS as Float;
+                    let direction = Vector3::new(r * phi.cos(), r * phi.sin(), z);
+                    reflected += clay(30.0).eval(&r_in, &rec, &towards(direction)) * cell;
+                }
+            }
+            assert!(
+                reflected.r() <= 0.8 && reflected.r() > 0.5,
+                "{}",
+                reflected.r()
+            );
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use std::sync::Arc;
+
+use super::super::float::consts;
+use super::super::float::{to_f32, Float};
+use super::super::hittable::HitRecord;
+use super::super::material::{luminance, relative_ref_idx, Material, ScatterRecord};
+use super::super::medium::Medium;
+use super::super::microfacet::{dielectric_bsdf_cos, Gtr1, TrowbridgeReitz};
+use super::super::onb::ONB;
+use super::super::pdf::{CosinePDF, GgxPDF, Gtr1PDF, RoughDielectricPDF, WeightedPDF, PDF};
+use super::super::ray::Ray;
+use super::super::texture::{self, Texture};
+use super::super::vector3::{dot, unit_vector, Color, Vector3};
+
+// Disney principled BSDF (Burley 2012 and 2015), every parameter but the index
+// of refraction comes from a texture and scalar ones use its red channel
+#[derive(Clone)]
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    // reflectance at normal incidence, 0.5 is 4%
+    pub specular: Arc<dyn Texture>,
+    pub specular_tint: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    pub sheen_tint: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_gloss: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub ref_idx: Float,
+    pub priority: u32,
+}
+
+struct Parameters {
+    base_color: Color,
+    metallic: f32,
+    roughness: Float,
+    specular: f32,
+    specular_tint: f32,
+    sheen: f32,
+    sheen_tint: f32,
+    clearcoat: f32,
+    clearcoat_gloss: Float,
+    transmission: f32,
+}
+
+struct Lobes {
+    diffuse: f32,
+    specular: f32,
+    clearcoat: f32,
+    transmission: f32,
+}
+
+impl Parameters {
+    fn lobes(&self) -> Lobes {
+        let dielectric = 1.0 - self.metallic;
+        let transmission = dielectric * self.transmission;
+        Lobes {
+            diffuse: dielectric * (1.0 - self.transmission),
+            // the transmission lobe has its own fresnel reflection
+            specular: 1.0 - transmission,
+            clearcoat: 0.25 * self.clearcoat,
+            transmission,
+        }
+    }
+
+    fn specular_distribution(&self) -> TrowbridgeReitz {
+        TrowbridgeReitz::from_roughness(self.roughness, self.roughness)
+    }
+
+    fn clearcoat_distribution(&self) -> Gtr1 {
+        Gtr1 {
+            alpha: 0.1 + (0.001 - 0.1) * self.clearcoat_gloss,
+        }
+    }
+
+    // base color normalized to unit luminance
+    fn tint(&self) -> Color {
+        let luminance = luminance(self.base_color);
+        if luminance > 0.0 {
+            self.base_color / luminance
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        }
+    }
+}
+
+fn mix(a: Color, b: Color, t: f32) -> Color {
+    (1.0 - t) * a + t * b
+}
+
+// (1 - cos)^5 from Schlick's fresnel approximation
+fn schlick_weight(cosine: Float) -> f32 {
+    let m = to_f32((1.0 - cosine).clamp(0.0, 1.0));
+    let m2 = m * m;
+    m2 * m2 * m
+}
+
+// separable Smith shadowing of the fixed width GGX the clearcoat uses
+fn smith_g1(cosine: Float, alpha: Float) -> Float {
+    let a2 = alpha * alpha;
+    let cos2 = cosine * cosine;
+    2.0 * cosine / (cosine + (a2 + cos2 - a2 * cos2).sqrt())
+}
+
+impl Principled {
+    pub fn new(base_color: Arc<dyn Texture>) -> Self {
+        Principled {
+            base_color,
+            metallic: texture::constant(0.0),
+            roughness: texture::constant(0.5),
+            specular: texture::constant(0.5),
+            specular_tint: texture::constant(0.0),
+            sheen: texture::constant(0.0),
+            sheen_tint: texture::constant(0.5),
+            clearcoat: texture::constant(0.0),
+            clearcoat_gloss: texture::constant(1.0),
+            transmission: texture::constant(0.0),
+            ref_idx: 1.5,
+            priority: 0,
+        }
+    }
+
+    fn parameters(&self, rec: &HitRecord) -> Parameters {
+        let scalar = |texture: &Arc<dyn Texture>| texture.value(rec).r().clamp(0.0, 1.0);
+        Parameters {
+            base_color: self.base_color.value(rec),
+            metallic: scalar(&self.metallic),
+            // perfectly smooth lobes would need delta distributions
+            roughness: (scalar(&self.roughness) as Float).max(0.03),
+            specular: scalar(&self.specular),
+            specular_tint: scalar(&self.specular_tint),
+            sheen: scalar(&self.sheen),
+            sheen_tint: scalar(&self.sheen_tint),
+            clearcoat: scalar(&self.clearcoat),
+            clearcoat_gloss: scalar(&self.clearcoat_gloss) as Float,
+            transmission: scalar(&self.transmission),
+        }
+    }
+
+    fn frame(rec: &HitRecord) -> ONB {
+        ONB::from_tangent(rec.shading_normal, rec.dpdu)
+    }
+
+    // one pdf per lobe, weighted by how much the lobe contributes
+    fn sampling_pdf(&self, r_in: &Ray, rec: &HitRecord) -> Option<WeightedPDF> {
+        let wo = Principled::frame(rec).to_local(&-unit_vector(r_in.direction()));
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let parameters = self.parameters(rec);
+        let lobes = parameters.lobes();
+        let eta = relative_ref_idx(self.ref_idx, rec);
+        Some(WeightedPDF::new(vec![
+            (
+                lobes.diffuse as Float,
+                Box::new(CosinePDF::new(rec.shading_normal)),
+            ),
+            (
+                lobes.specular as Float,
+                Box::new(GgxPDF::new(
+                    Principled::frame(rec),
+                    wo,
+                    parameters.specular_distribution(),
+                )),
+            ),
+            (
+                lobes.clearcoat as Float,
+                Box::new(Gtr1PDF::new(
+                    Principled::frame(rec),
+                    wo,
+                    parameters.clearcoat_distribution(),
+                )),
+            ),
+            (
+                lobes.transmission as Float,
+                Box::new(RoughDielectricPDF::new(
+                    Principled::frame(rec),
+                    wo,
+                    eta,
+                    parameters.specular_distribution(),
+                )),
+            ),
+        ]))
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.sampling_pdf(r_in, rec).map(|pdf| ScatterRecord {
+            specular_ray: None,
+            is_specular: false,
+            attenuation: Color::new(1.0, 1.0, 1.0),
+            pdf: Some(Box::new(pdf)),
+        })
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        self.sampling_pdf(r_in, rec)
+            .map_or(0.0, |pdf| pdf.value(scattered.direction()))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let uvw = Principled::frame(rec);
+        let wo: Vector3 = uvw.to_local(&-unit_vector(r_in.direction()));
+        let wi: Vector3 = uvw.to_local(&unit_vector(scattered.direction()));
+        // the shading and geometric normals have to agree on the side
+        let below = dot(scattered.direction(), rec.normal) <= 0.0;
+        if wo.z() <= 0.0 || (wi.z() <= 0.0) != below {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let parameters = self.parameters(rec);
+        let lobes = parameters.lobes();
+        let white = Color::new(1.0, 1.0, 1.0);
+        let mut value = Color::new(0.0, 0.0, 0.0);
+
+        if lobes.transmission > 0.0 {
+            let eta = relative_ref_idx(self.ref_idx, rec);
+            let transmission =
+                to_f32(dielectric_bsdf_cos(wo, wi, eta, &parameters.specular_distribution()));
+            // refracted light takes the base color
+            let tint = if wi.z() < 0.0 {
+                parameters.base_color
+            } else {
+                white
+            };
+            value += tint * (lobes.transmission * transmission);
+        }
+        if wi.z() <= 0.0 {
+            return value;
+        }
+
+        let h = unit_vector(wo + wi);
+        let cos_d = dot(wi, h);
+
+        // diffuse with retro-reflection at grazing angles, plus sheen
+        let fd90 = 0.5 + 2.0 * to_f32(cos_d * cos_d * parameters.roughness);
+        let fd = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z()))
+            * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z()));
+        let sheen = mix(white, parameters.tint(), parameters.sheen_tint)
+            * (parameters.sheen * schlick_weight(cos_d));
+        value += (parameters.base_color * (fd / to_f32(consts::PI)) + sheen)
+            * (lobes.diffuse * to_f32(wi.z()));
+
+        // metals reflect their base color, dielectrics a mostly white 0-8%
+        let f0 = mix(
+            mix(white, parameters.tint(), parameters.specular_tint) * (0.08 * parameters.specular),
+            parameters.base_color,
+            parameters.metallic,
+        );
+        let fresnel = f0 + (white - f0) * schlick_weight(cos_d);
+        let distribution = parameters.specular_distribution();
+        let specular = distribution.d(h) * distribution.g(wo, wi) / (4.0 * wo.z());
+        value += fresnel * (lobes.specular * to_f32(specular));
+
+        // clearcoat with a fixed index of 1.5
+        let clearcoat = parameters.clearcoat_distribution().d(h)
+            * smith_g1(wo.z(), 0.25)
+            * smith_g1(wi.z(), 0.25)
+            / (4.0 * wo.z());
+        let fresnel = 0.04 + 0.96 * schlick_weight(cos_d);
+        value += white * (lobes.clearcoat * fresnel * to_f32(clearcoat));
+
+        value
+    }
+
+    // only when light can get inside, an opaque object has no medium so
+    // inside a dielectric with a higher priority it still gets hit
+    fn medium(&self) -> Option<Medium> {
+        let opaque = self.transmission.uniform().is_some_and(|t| t.r() <= 0.0)
+            || self.metallic.uniform().is_some_and(|m| m.r() >= 1.0);
+        if opaque {
+            return None;
+        }
+        Some(Medium {
+            ref_idx: self.ref_idx,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            scattering: Color::new(0.0, 0.0, 0.0),
+            priority: self.priority,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::perlin::Perlin;
+    use super::super::super::textures::noise::NoiseTexture;
+    use super::*;
+
+    fn plastic() -> Principled {
+        Principled::new(texture::solid(Color::new(0.8, 0.2, 0.2)))
+    }
+
+    #[test]
+    fn opaque_has_no_medium() {
+        assert!(plastic().medium().is_none());
+        let metal = Principled {
+            metallic: texture::constant(1.0),
+            transmission: texture::constant(1.0),
+            ..plastic()
+        };
+        assert!(metal.medium().is_none());
+    }
+
+    #[test]
+    fn transmissive_has_a_medium_with_its_priority() {
+        let glass = Principled {
+            transmission: texture::constant(1.0),
+            ref_idx: 1.33,
+            priority: 3,
+            ..plastic()
+        };
+        let medium = glass.medium().expect("light gets inside");
+        assert_eq!(medium.priority, 3);
+        assert_eq!(medium.ref_idx, 1.33);
+    }
+
+    // without a hit a textured transmission might be anything
+    #[test]
+    fn textured_transmission_has_a_medium() {
+        let patchy = Principled {
+            transmission: Arc::new(NoiseTexture {
+                noise: Perlin::new(),
+                scale: 1.0,
+            }),
+            ..plastic()
+        };
+        assert!(patchy.medium().is_some());
+    }
+}
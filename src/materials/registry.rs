@@ -0,0 +1,548 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::super::color::Encoding;
+use super::super::float::{to_f32, Float};
+use super::super::material::{ComplexIor, Material};
+use super::super::texture::{self, Texture};
+use super::super::textures::cache;
+use super::super::textures::checker::{CheckerTexture, SolidCheckerTexture};
+use super::super::textures::image_texture::ImageTexture;
+use super::super::textures::nodes::{
+    AddTexture, ChannelTexture, InvertTexture, MixTexture, MultiplyTexture, ScaleTexture,
+};
+use super::super::thin_film::ThinFilm;
+use super::super::vector3::Color;
+use super::cloth::Cloth;
+use super::coated::Coated;
+use super::cutout::Cutout;
+use super::dielectric::{Dielectric, Dispersion};
+use super::diffuse_light::DiffuseLight;
+use super::diffuse_transmission::DiffuseTransmission;
+use super::isotropic::Isotropic;
+use super::lambertian::Lambertian;
+use super::measured::Measured;
+use super::metal::Metal;
+use super::mix::Mix;
+use super::oren_nayar::OrenNayar;
+use super::principled::Principled;
+use super::subsurface::Subsurface;
+
+// `metal ior=gold roughness=0.2`, nested materials go in brackets like
+// `coated base=[lambertian]` and nested textures in parentheses
+pub struct Params {
+    values: HashMap<String, String>,
+    materials: HashMap<String, Arc<dyn Material>>,
+}
+
+impl Params {
+    pub fn new() -> Self {
+        Params {
+            values: HashMap::new(),
+            materials: HashMap::new(),
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.values.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn set_material(&mut self, key: &str, material: Arc<dyn Material>) {
+        self.materials.insert(key.to_string(), material);
+    }
+
+    pub fn material(&self, key: &str) -> Result<Arc<dyn Material>, String> {
+        self.materials
+            .get(key)
+            .cloned()
+            .ok_or_else(|| format!("{} should be a material like [lambertian]", key))
+    }
+
+    pub fn word(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(|value| value.as_str())
+    }
+
+    pub fn float(&self, key: &str, default: Float) -> Result<Float, String> {
+        match self.word(key) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("{} should be a number, not {}", key, value)),
+            None => Ok(default),
+        }
+    }
+
+    pub fn int(&self, key: &str, default: u32) -> Result<u32, String> {
+        match self.word(key) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("{} should be an integer, not {}", key, value)),
+            None => Ok(default),
+        }
+    }
+
+    // a single number is a gray, otherwise three comma separated channels
+    pub fn color(&self, key: &str, default: Color) -> Result<Color, String> {
+        let value = match self.word(key) {
+            Some(value) => value,
+            None => return Ok(default),
+        };
+        let channels: Result<Vec<f32>, _> = value.split(',').map(|c| c.trim().parse()).collect();
+        match channels.as_ref().map(|c| c.as_slice()) {
+            Ok([gray]) => Ok(Color::new(*gray, *gray, *gray)),
+            Ok([r, g, b]) => Ok(Color::new(*r, *g, *b)),
+            _ => Err(format!("{} should be a color, not {}", key, value)),
+        }
+    }
+
+    // a color, a texture node, or the path of an sRGB image
+    pub fn texture(&self, key: &str, default: Color) -> Result<Arc<dyn Texture>, String> {
+        self.encoded_texture(key, default, Encoding::Srgb)
+    }
+
+    // like `texture` for linear data such as roughness
+    pub fn data_texture(&self, key: &str, default: Color) -> Result<Arc<dyn Texture>, String> {
+        self.encoded_texture(key, default, Encoding::Linear)
+    }
+
+    fn encoded_texture(
+        &self,
+        key: &str,
+        default: Color,
+        encoding: Encoding,
+    ) -> Result<Arc<dyn Texture>, String> {
+        let color = self.color(key, default);
+        match self.word(key) {
+            Some(node) if node.starts_with('(') && node.ends_with(')') => {
+                texture_node(&node[1..node.len() - 1], encoding)
+            }
+            Some(path) if color.is_err() => match cache::load(path, encoding) {
+                Ok(image) => Ok(Arc::new(ImageTexture::shared(image))),
+                Err(err) => Err(format!(
+                    "{} should be a color or an image, not {} ({})",
+                    key, path, err
+                )),
+            },
+            _ => Ok(texture::solid(color?)),
+        }
+    }
+
+    // film thickness in nanometers
+    pub fn film(&self) -> Result<Option<ThinFilm>, String> {
+        if self.word("film_thickness").is_none() {
+            return Ok(None);
+        }
+        Ok(Some(ThinFilm::uniform(
+            self.float("film_thickness", 0.0)?,
+            self.float("film_ref_idx", 1.33)?,
+        )))
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params::new()
+    }
+}
+
+type Constructor = Box<dyn Fn(&Params) -> Result<Arc<dyn Material>, String> + Sync + Send>;
+
+pub struct MaterialRegistry {
+    constructors: HashMap<String, Constructor>,
+}
+
+impl MaterialRegistry {
+    pub fn new() -> Self {
+        let mut registry = MaterialRegistry {
+            constructors: HashMap::new(),
+        };
+        registry.register("lambertian", |params| {
+            Ok(Arc::new(Lambertian {
+                albedo: params.texture("albedo", Color::new(0.5, 0.5, 0.5))?,
+            }))
+        });
+        registry.register("oren_nayar", |params| {
+            Ok(Arc::new(OrenNayar {
+                albedo: params.texture("albedo", Color::new(0.5, 0.5, 0.5))?,
+                sigma: params.float("sigma", 20.0)?,
+            }))
+        });
+        registry.register("cloth", |params| {
+            Ok(Arc::new(Cloth {
+                albedo: params.texture("albedo", Color::new(0.5, 0.5, 0.5))?,
+                sheen: params.texture("sheen", Color::new(1.0, 1.0, 1.0))?,
+                roughness: params.data_texture("roughness", Color::new(0.5, 0.5, 0.5))?,
+            }))
+        });
+        registry.register("diffuse_transmission", |params| {
+            Ok(Arc::new(DiffuseTransmission {
+                reflectance: params.texture("reflectance", Color::new(0.25, 0.25, 0.25))?,
+                transmittance: params.texture("transmittance", Color::new(0.25, 0.25, 0.25))?,
+            }))
+        });
+        registry.register("measured", |params| {
+            let path = params.word("file").ok_or("measured needs a file")?;
+            let measured =
+                Measured::load(path).map_err(|err| format!("can't load {}: {}", path, err))?;
+            Ok(Arc::new(measured))
+        });
+        registry.register("metal", |params| {
+            let ior = match params.word("ior") {
+                Some("aluminium") => ComplexIor::ALUMINIUM,
+                Some("chromium") => ComplexIor::CHROMIUM,
+                Some("copper") => ComplexIor::COPPER,
+                Some("gold") => ComplexIor::GOLD,
+                Some("iron") => ComplexIor::IRON,
+                Some("silver") => ComplexIor::SILVER,
+                Some(other) => return Err(format!("unknown metal {}", other)),
+                None => {
+                    let reflectivity = params.color("reflectivity", Color::new(0.9, 0.9, 0.9))?;
+                    let edge_tint = params.color("edge_tint", reflectivity)?;
+                    ComplexIor::from_reflectivity(reflectivity, edge_tint)
+                }
+            };
+            let roughness = params.float("roughness", 0.0)?;
+            Ok(Arc::new(Metal {
+                ior,
+                roughness_u: params.float("roughness_u", roughness)?,
+                roughness_v: params.float("roughness_v", roughness)?,
+                film: params.film()?,
+            }))
+        });
+        registry.register("dielectric", |params| {
+            let ref_idx = params.float("ref_idx", 1.5)?;
+            // a named glass, or cauchy_b for an index that is
+            // ref_idx at the sodium line and rises towards blue
+            let dispersion = match (params.word("dispersion"), params.word("cauchy_b")) {
+                (Some("bk7"), _) => Some(Dispersion::BK7),
+                (Some("diamond"), _) => Some(Dispersion::DIAMOND),
+                (Some(other), _) => return Err(format!("unknown dispersion {}", other)),
+                (None, Some(_)) => {
+                    let b = params.float("cauchy_b", 0.0)?;
+                    Some(Dispersion::Cauchy {
+                        a: ref_idx - b / (0.5893 * 0.5893),
+                        b,
+                    })
+                }
+                (None, None) => None,
+            };
+            Ok(Arc::new(Dielectric {
+                ref_idx,
+                roughness: params.float("roughness", 0.0)?,
+                absorption: params.color("absorption", Color::new(0.0, 0.0, 0.0))?,
+                priority: params.int("priority", 0)?,
+                film: params.film()?,
+                dispersion,
+            }))
+        });
+        registry.register("principled", |params| {
+            let defaults =
+                Principled::new(params.texture("base_color", Color::new(0.8, 0.8, 0.8))?);
+            let scalar = |key: &str, default: &Arc<dyn Texture>| match params.word(key) {
+                Some(_) => params.data_texture(key, Color::new(0.0, 0.0, 0.0)),
+                None => Ok(default.clone()),
+            };
+            Ok(Arc::new(Principled {
+                metallic: scalar("metallic", &defaults.metallic)?,
+                roughness: scalar("roughness", &defaults.roughness)?,
+                specular: scalar("specular", &defaults.specular)?,
+                specular_tint: scalar("specular_tint", &defaults.specular_tint)?,
+                sheen: scalar("sheen", &defaults.sheen)?,
+                sheen_tint: scalar("sheen_tint", &defaults.sheen_tint)?,
+                clearcoat: scalar("clearcoat", &defaults.clearcoat)?,
+                clearcoat_gloss: scalar("clearcoat_gloss", &defaults.clearcoat_gloss)?,
+                transmission: scalar("transmission", &defaults.transmission)?,
+                ref_idx: params.float("ref_idx", defaults.ref_idx)?,
+                priority: params.int("priority", defaults.priority)?,
+                ..defaults
+            }))
+        });
+        registry.register("subsurface", |params| {
+            let mean_free_path = params.color("mean_free_path", Color::new(1.0, 1.0, 1.0))?;
+            let channels = [mean_free_path.r(), mean_free_path.g(), mean_free_path.b()];
+            if !channels.iter().all(|d| *d > 0.0 && d.is_finite()) {
+                return Err(format!(
+                    "mean_free_path should be positive, not {}",
+                    params.word("mean_free_path").unwrap_or("")
+                ));
+            }
+            Ok(Arc::new(Subsurface {
+                albedo: params.color("albedo", Color::new(0.9, 0.9, 0.9))?,
+                mean_free_path,
+                ref_idx: params.float("ref_idx", 1.4)?,
+                roughness: params.float("roughness", 0.0)?,
+                priority: params.int("priority", 0)?,
+            }))
+        });
+        registry.register("mix", |params| {
+            Ok(Arc::new(Mix {
+                a: params.material("a")?,
+                b: params.material("b")?,
+                mask: params.data_texture("mask", Color::new(0.5, 0.5, 0.5))?,
+            }))
+        });
+        registry.register("coated", |params| {
+            Ok(Arc::new(Coated {
+                base: params.material("base")?,
+                ref_idx: params.float("ref_idx", 1.5)?,
+                roughness: params.float("roughness", 0.0)?,
+                tint: params.color("tint", Color::new(1.0, 1.0, 1.0))?,
+            }))
+        });
+        registry.register("cutout", |params| {
+            let threshold = match params.word("threshold") {
+                Some(_) => Some(to_f32(params.float("threshold", 0.0)?)),
+                None => None,
+            };
+            Ok(Arc::new(Cutout {
+                base: params.material("base")?,
+                opacity: params.texture("opacity", Color::new(1.0, 1.0, 1.0))?,
+                threshold,
+            }))
+        });
+        registry.register("diffuse_light", |params| {
+            Ok(Arc::new(DiffuseLight {
+                emit: params.texture("emit", Color::new(1.0, 1.0, 1.0))?,
+            }))
+        });
+        registry.register("isotropic", |params| {
+            Ok(Arc::new(Isotropic {
+                texture: params.texture("albedo", Color::new(0.5, 0.5, 0.5))?,
+            }))
+        });
+        registry
+    }
+
+    pub fn register<F>(&mut self, name: &str, constructor: F)
+    where
+        F: Fn(&Params) -> Result<Arc<dyn Material>, String> + Sync + Send + 'static,
+    {
+        self.constructors
+            .insert(name.to_string(), Box::new(constructor));
+    }
+
+    pub fn create(&self, name: &str, params: &Params) -> Result<Arc<dyn Material>, String> {
+        match self.constructors.get(name) {
+            Some(constructor) => constructor(params),
+            None => Err(format!("unknown material {}", name)),
+        }
+    }
+
+    pub fn parse(&self, description: &str) -> Result<Arc<dyn Material>, String> {
+        let (name, pairs) = split_description(description)?;
+        let mut params = Params::new();
+        for (key, value) in pairs {
+            if value.starts_with('[') && value.ends_with(']') {
+                params.set_material(key, self.parse(&value[1..value.len() - 1])?);
+            } else {
+                params.set(key, value);
+            }
+        }
+        self.create(name, &params)
+    }
+}
+
+impl Default for MaterialRegistry {
+    fn default() -> Self {
+        MaterialRegistry::new()
+    }
+}
+
+fn texture_node(description: &str, encoding: Encoding) -> Result<Arc<dyn Texture>, String> {
+    let (name, pairs) = split_description(description)?;
+    let mut params = Params::new();
+    for (key, value) in pairs {
+        params.set(key, value);
+    }
+    let input = |key: &str| match params.word(key) {
+        Some(_) => params.encoded_texture(key, Color::new(0.0, 0.0, 0.0), encoding),
+        None => Err(format!("{} needs {}", name, key)),
+    };
+    Ok(match name {
+        "scale" => Arc::new(ScaleTexture {
+            input: input("input")?,
+            factor: to_f32(params.float("factor", 1.0)?),
+        }),
+        "add" => Arc::new(AddTexture {
+            a: input("a")?,
+            b: input("b")?,
+        }),
+        "multiply" => Arc::new(MultiplyTexture {
+            a: input("a")?,
+            b: input("b")?,
+        }),
+        "mix" => Arc::new(MixTexture {
+            a: input("a")?,
+            b: input("b")?,
+            mask: params.data_texture("mask", Color::new(0.5, 0.5, 0.5))?,
+        }),
+        "checker" => Arc::new(CheckerTexture {
+            even: input("even")?,
+            odd: input("odd")?,
+            frequency: params.float("frequency", 1.0)?,
+        }),
+        "solid_checker" => Arc::new(SolidCheckerTexture {
+            even: input("even")?,
+            odd: input("odd")?,
+            frequency: params.float("frequency", 1.0)?,
+        }),
+        "invert" => Arc::new(InvertTexture {
+            input: input("input")?,
+        }),
+        "channel" => Arc::new(ChannelTexture {
+            input: input("input")?,
+            channel: match params.word("channel") {
+                Some("r") => 0,
+                Some("g") => 1,
+                Some("b") => 2,
+                Some("a") => 3,
+                other => {
+                    return Err(format!(
+                        "channel should be r, g, b or a, not {}",
+                        other.unwrap_or("nothing")
+                    ))
+                }
+            },
+        }),
+        other => return Err(format!("unknown texture {}", other)),
+    })
+}
+
+type Pairs<'a> = Vec<(&'a str, &'a str)>;
+
+fn split_description(description: &str) -> Result<(&str, Pairs<'_>), String> {
+    let words = split_words(description)?;
+    let mut words = words.into_iter();
+    let name = words.next().ok_or("empty description")?;
+    let pairs = words
+        .map(|word| {
+            let mut pair = word.splitn(2, '=');
+            match (pair.next(), pair.next()) {
+                (Some(key), Some(value)) => Ok((key, value)),
+                _ => Err(format!("expected key=value, not {}", word)),
+            }
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((name, pairs))
+}
+
+// split at whitespace outside of brackets and parentheses
+fn split_words(description: &str) -> Result<Vec<&str>, String> {
+    let mut words = Vec::new();
+    let mut open = Vec::new();
+    let mut start = None;
+    for (i, c) in description.char_indices() {
+        match c {
+            '[' | '(' => open.push(c),
+            ']' | ')' => match (open.pop(), c) {
+                (Some('['), ']') | (Some('('), ')') => {}
+                _ => return Err(format!("unmatched {} in {}", c, description)),
+            },
+            _ => {}
+        }
+        if c.is_whitespace() && open.is_empty() {
+            if let Some(begin) = start.take() {
+                words.push(&description[begin..i]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(c) = open.pop() {
+        return Err(format!("unmatched {} in {}", c, description));
+    }
+    if let Some(begin) = start {
+        words.push(&description[begin..]);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::hittable::fixtures::flat;
+    use super::*;
+
+    #[test]
+    fn words_keep_brackets_together() {
+        let words = split_words("coated  base=[mix a=[metal] b=[lambertian]] tint=1").unwrap();
+        assert_eq!(
+            words,
+            vec!["coated", "base=[mix a=[metal] b=[lambertian]]", "tint=1"]
+        );
+        assert!(split_words("coated base=[lambertian").is_err());
+        assert!(split_words("coated base=lambertian]").is_err());
+        assert!(split_words("lambertian albedo=(invert input=[metal)]").is_err());
+    }
+
+    fn texture(value: &str) -> Result<Arc<dyn Texture>, String> {
+        let mut params = Params::new();
+        params.set("albedo", value);
+        params.texture("albedo", Color::new(0.0, 0.0, 0.0))
+    }
+
+    #[test]
+    fn texture_nodes_nest_in_parentheses() {
+        let sum = texture("(add a=0.25 b=(scale input=0.5,0.25,1 factor=2))").unwrap();
+        assert!(sum.value(&flat(0.5, 0.5)) == Color::new(1.25, 0.75, 2.25));
+        let blend = texture("(mix a=(invert input=1) b=(multiply a=0.5 b=0.5) mask=0.5)").unwrap();
+        assert!(blend.value(&flat(0.5, 0.5)) == Color::new(0.125, 0.125, 0.125));
+        let green = texture("(channel input=0.1,0.2,0.3 channel=g)").unwrap();
+        assert!(green.value(&flat(0.5, 0.5)) == Color::new(0.2, 0.2, 0.2));
+        let checks = texture("(solid_checker even=0 odd=(checker even=1 odd=0.5) frequency=2)");
+        assert!(checks.unwrap().value(&flat(0.5, 0.5)) == Color::new(0.0, 0.0, 0.0));
+        let material = MaterialRegistry::new().parse("lambertian albedo=(invert input=0.2)");
+        assert!(material.is_ok());
+    }
+
+    #[test]
+    fn texture_nodes_need_their_inputs() {
+        assert!(texture("(scale factor=2)").is_err());
+        assert!(texture("(channel input=0.5 channel=x)").is_err());
+        assert!(texture("(blur input=0.5)").is_err());
+    }
+
+    #[test]
+    fn composites_wrap_the_materials_in_brackets() {
+        let registry = MaterialRegistry::new();
+        let coated = registry
+            .parse("coated base=[cutout base=[dielectric priority=2] threshold=0.5]")
+            .unwrap();
+        assert_eq!(coated.medium().expect("the glass inside").priority, 2);
+        let mix = registry
+            .parse("mix a=[metal ior=gold] b=[lambertian albedo=0.2] mask=0.3")
+            .unwrap();
+        assert!(mix.medium().is_none());
+        assert!(registry.parse("coated ref_idx=1.4").is_err());
+        assert!(registry.parse("mix a=[metal] b=[glass]").is_err());
+    }
+
+    #[test]
+    fn subsurface_needs_a_positive_mean_free_path() {
+        let registry = MaterialRegistry::new();
+        assert!(registry
+            .parse("subsurface mean_free_path=0.1,0.2,0.4")
+            .is_ok());
+        assert!(registry.parse("subsurface mean_free_path=0").is_err());
+        assert!(registry
+            .parse("subsurface mean_free_path=1,-0.5,1")
+            .is_err());
+        assert!(registry.parse("subsurface mean_free_path=inf").is_err());
+        // so wax poured into a glass can fill it where they overlap
+        let wax = registry.parse("subsurface priority=3").unwrap();
+        assert_eq!(wax.medium().unwrap().priority, 3);
+    }
+
+    #[test]
+    fn registered_materials_nest_in_composites() {
+        let mut registry = MaterialRegistry::new();
+        registry.register("water", |params| {
+            let mut glass = Params::new();
+            glass.set("ref_idx", "1.33");
+            glass.set("priority", &params.int("priority", 1)?.to_string());
+            MaterialRegistry::new().create("dielectric", &glass)
+        });
+        let coated = registry.parse("coated base=[water priority=4]").unwrap();
+        let medium = coated.medium().unwrap();
+        assert_eq!(medium.priority, 4);
+        assert_eq!(medium.ref_idx, 1.33);
+    }
+}
@@ -0,0 +1,86 @@
+use super::super::float::Float;
+use super::super::hittable::HitRecord;
+use super::super::material::{Material, ScatterRecord};
+use super::super::medium::Medium;
+use super::super::ray::Ray;
+use super::super::vector3::Color;
+use super::dielectric::Dielectric;
+
+// random walk subsurface scattering inside a closed object, mean free
+// path is the average distance between collisions for each channel
+pub struct Subsurface {
+    pub albedo: Color,
+    pub mean_free_path: Color,
+    pub ref_idx: Float,
+    pub roughness: Float,
+    pub priority: u32,
+}
+
+// paths shorter than this are taken as this, a path of zero would make
+// the medium infinitely dense and its coefficients infinite or NaN
+const MIN_MEAN_FREE_PATH: f32 = 1e-4;
+
+impl Subsurface {
+    fn interface(&self) -> Dielectric {
+        Dielectric {
+            ref_idx: self.ref_idx,
+            roughness: self.roughness,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            priority: self.priority,
+            film: None,
+            dispersion: None,
+        }
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.interface().scatter(r_in, rec)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        self.interface().pdf(r_in, rec, scattered)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        self.interface().eval(r_in, rec, scattered)
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        let path = |d: f32| d.max(MIN_MEAN_FREE_PATH);
+        let extinction = Color::new(
+            1.0 / path(self.mean_free_path.r()),
+            1.0 / path(self.mean_free_path.g()),
+            1.0 / path(self.mean_free_path.b()),
+        );
+        let scattering = self.albedo * extinction;
+        Some(Medium {
+            ref_idx: self.ref_idx,
+            absorption: extinction - scattering,
+            scattering,
+            priority: self.priority,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_mean_free_path_is_dense_but_finite() {
+        let wax = Subsurface {
+            albedo: Color::new(0.9, 0.9, 0.9),
+            mean_free_path: Color::new(0.0, -1.0, 0.5),
+            ref_idx: 1.4,
+            roughness: 0.0,
+            priority: 0,
+        };
+        let medium = wax.medium().unwrap();
+        let extinction = medium.absorption + medium.scattering;
+        assert!(extinction.r().is_finite() && extinction.g().is_finite());
+        assert!(extinction.r() == 1.0 / MIN_MEAN_FREE_PATH);
+        assert!(extinction.g() == 1.0 / MIN_MEAN_FREE_PATH);
+        assert!((extinction.b() - 2.0).abs() < 1e-6);
+    }
+}
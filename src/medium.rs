@@ -0,0 +1,287 @@
+use super::float::{to_f32, Float};
+use super::spectrum::Wavelengths;
+use super::vector3::Color;
+
+use rand::Rng;
+
+// the inside of a dielectric object, where objects overlap the one with
+// the highest priority is the medium that is really there
+#[derive(Copy, Clone, PartialEq)]
+pub struct Medium {
+    pub ref_idx: Float,
+    // absorption coefficient per unit of distance for each channel
+    pub absorption: Color,
+    // scattering coefficient, media that scatter are walked through
+    // instead of only absorbing what crosses them
+    pub scattering: Color,
+    pub priority: u32,
+}
+
+impl Medium {
+    pub fn scatters(&self) -> bool {
+        self.scattering.squared_length() > 0.0
+    }
+
+    // the medium with its coefficients at the wavelengths of a spectral path
+    pub fn at(&self, wavelengths: Option<Wavelengths>) -> Medium {
+        match wavelengths {
+            Some(wavelengths) => Medium {
+                absorption: wavelengths.upsample(self.absorption),
+                scattering: wavelengths.upsample(self.scattering),
+                ..*self
+            },
+            None => *self,
+        }
+    }
+
+    // fraction of light left after travelling a distance through the medium
+    fn transmittance(&self, distance: Float) -> Color {
+        let extinction = self.absorption + self.scattering;
+        let distance = to_f32(distance);
+        Color::new(
+            (-extinction[0] * distance).exp(),
+            (-extinction[1] * distance).exp(),
+            (-extinction[2] * distance).exp(),
+        )
+    }
+}
+
+// distance to the next collision in a medium of the given density,
+// exponentially distributed
+pub fn free_flight(density: Float) -> Float {
+    -(1.0 / density) * rand::thread_rng().gen::<Float>().ln()
+}
+
+// what happened to a ray on its way to the next surface, with what the medium
+// let through and the density of that happening when the distance was
+// sampled with each of the channels
+pub enum Flight {
+    // it made it there
+    Passed {
+        contribution: Color,
+        pdf: Color,
+    },
+    // it collided with the medium after the distance, the contribution
+    // still has to be multiplied by the scattering albedo
+    Scattered {
+        distance: Float,
+        contribution: Color,
+        pdf: Color,
+    },
+}
+
+// the medium with the highest priority, the latest one on ties
+fn highest<'a>(media: impl Iterator<Item = &'a Medium>) -> Option<&'a Medium> {
+    media.fold(None, |highest: Option<&Medium>, medium| match highest {
+        Some(highest) if highest.priority > medium.priority => Some(highest),
+        _ => Some(medium),
+    })
+}
+
+// the media a ray is currently inside of, in the order they were entered
+#[derive(Clone)]
+pub struct MediumStack {
+    media: Vec<Medium>,
+}
+
+impl MediumStack {
+    // rays start out in vacuum
+    pub fn new() -> Self {
+        MediumStack { media: Vec::new() }
+    }
+
+    // the medium the ray is travelling through
+    pub fn current(&self) -> Option<&Medium> {
+        highest(self.media.iter())
+    }
+
+    // whether the boundary of the medium is a real interface or lies inside
+    // a medium with a higher priority and has to be ignored
+    pub fn is_interface(&self, medium: &Medium, entering: bool) -> bool {
+        let others = if entering {
+            self.media.iter().collect()
+        } else {
+            self.without(medium)
+        };
+        others.iter().all(|other| other.priority <= medium.priority)
+    }
+
+    // index of refraction of the medium the ray is travelling through
+    pub fn ref_idx(&self) -> Float {
+        self.current().map_or(1.0, |medium| medium.ref_idx)
+    }
+
+    // index of refraction on the other side of the medium's boundary
+    pub fn outside_ref_idx(&self, medium: &Medium) -> Float {
+        highest(self.without(medium).into_iter()).map_or(1.0, |other| other.ref_idx)
+    }
+
+    // the stack after crossing the boundary of the medium
+    pub fn crossed(&self, medium: &Medium, entering: bool) -> MediumStack {
+        if entering {
+            let mut media = self.media.clone();
+            media.push(*medium);
+            MediumStack { media }
+        } else {
+            MediumStack {
+                media: self.without(medium).into_iter().cloned().collect(),
+            }
+        }
+    }
+
+    // sample how far a ray gets through the current medium towards a surface
+    // at the distance, colored media sample the free flight of one channel
+    pub fn flight(&self, distance: Float, channel: usize) -> Flight {
+        let medium = match self.current() {
+            Some(medium) => medium,
+            None => {
+                return Flight::Passed {
+                    contribution: Color::new(1.0, 1.0, 1.0),
+                    pdf: Color::new(1.0, 1.0, 1.0),
+                }
+            }
+        };
+        if !medium.scatters() {
+            return Flight::Passed {
+                contribution: medium.transmittance(distance),
+                pdf: Color::new(1.0, 1.0, 1.0),
+            };
+        }
+        let extinction = medium.absorption + medium.scattering;
+        let collision = free_flight(extinction[channel] as Float);
+        if collision < distance {
+            let transmittance = medium.transmittance(collision);
+            Flight::Scattered {
+                distance: collision,
+                contribution: extinction * transmittance,
+                pdf: extinction * transmittance,
+            }
+        } else {
+            let transmittance = medium.transmittance(distance);
+            Flight::Passed {
+                contribution: transmittance,
+                pdf: transmittance,
+            }
+        }
+    }
+
+    // every medium except the last entered one equal to `medium`
+    fn without(&self, medium: &Medium) -> Vec<&Medium> {
+        let last = self.media.iter().rposition(|other| other == medium);
+        self.media
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| Some(i) != last)
+            .map(|(_, other)| other)
+            .collect()
+    }
+}
+
+impl Default for MediumStack {
+    fn default() -> Self {
+        MediumStack::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::hittable::fixtures::flat;
+    use super::super::material::relative_ref_idx;
+    use super::*;
+
+    fn medium(ref_idx: Float, absorption: f32, priority: u32) -> Medium {
+        Medium {
+            ref_idx,
+            absorption: Color::new(absorption, 2.0 * absorption, 3.0 * absorption),
+            scattering: Color::new(0.0, 0.0, 0.0),
+            priority,
+        }
+    }
+
+    // cross the boundary of the medium like the integrator does, and return
+    // the stack after it with the relative index there, None if ignored
+    fn cross(media: &MediumStack, medium: &Medium, entering: bool) -> (MediumStack, Option<Float>) {
+        let after = media.crossed(medium, entering);
+        if !media.is_interface(medium, entering) {
+            return (after, None);
+        }
+        let mut rec = flat(0.0, 0.0);
+        rec.front_face = entering;
+        rec.eta_outside = media.outside_ref_idx(medium);
+        (after, Some(relative_ref_idx(medium.ref_idx, &rec)))
+    }
+
+    fn close(a: Option<Float>, b: Float) -> bool {
+        a.is_some_and(|a| (a - b).abs() < 1e-6)
+    }
+
+    fn passed(media: &MediumStack, distance: Float) -> Color {
+        match media.flight(distance, 0) {
+            Flight::Passed { contribution, .. } => contribution,
+            Flight::Scattered { .. } => panic!("absorbing media don't scatter"),
+        }
+    }
+
+    // the liquid overlaps the walls a little so there is no gap of air,
+    // and the glass wins where they overlap
+    #[test]
+    fn liquid_in_glass() {
+        let glass = medium(1.5, 0.0, 2);
+        let water = medium(1.33, 0.1, 1);
+        let (media, eta) = cross(&MediumStack::new(), &glass, true);
+        assert!(close(eta, 1.5));
+        let (media, eta) = cross(&media, &water, true);
+        assert!(eta.is_none());
+        assert!(media.current() == Some(&glass));
+        // surfaces without a medium inside the wall see the glass
+        assert_eq!(media.ref_idx(), 1.5);
+        // the inner wall of the glass is where the water really starts
+        let (media, eta) = cross(&media, &glass, false);
+        assert!(close(eta, 1.33 / 1.5));
+        assert!(media.current() == Some(&water));
+        assert_eq!(media.ref_idx(), 1.33);
+
+        let transmittance = passed(&media, 2.0);
+        for i in 0..3 {
+            let expected = (-0.1 * (i + 1) as f32 * 2.0).exp();
+            assert!((transmittance[i] - expected).abs() < 1e-6);
+        }
+        let (media, eta) = cross(&media, &water, false);
+        assert!(close(eta, 1.0 / 1.33));
+        assert!(media.current().is_none());
+        assert_eq!(media.ref_idx(), 1.0);
+    }
+
+    #[test]
+    fn air_bubble_in_ice() {
+        let ice = medium(1.31, 0.05, 1);
+        let air = medium(1.0, 0.0, 2);
+        let (media, _) = cross(&MediumStack::new(), &ice, true);
+        let (media, eta) = cross(&media, &air, true);
+        assert!(close(eta, 1.0 / 1.31));
+        // nothing is absorbed inside the bubble
+        assert!(passed(&media, 10.0) == Color::new(1.0, 1.0, 1.0));
+        let (media, eta) = cross(&media, &air, false);
+        assert!(close(eta, 1.31));
+        assert!(media.current() == Some(&ice));
+        let transmittance = passed(&media, 4.0);
+        assert!((transmittance[2] - (-0.15f32 * 4.0).exp()).abs() < 1e-6);
+    }
+
+    // on ties both boundaries count and the last one entered fills the overlap
+    #[test]
+    fn equal_priorities() {
+        let crown = medium(1.5, 0.0, 0);
+        let flint = medium(1.7, 0.2, 0);
+        let (media, _) = cross(&MediumStack::new(), &crown, true);
+        let (media, eta) = cross(&media, &flint, true);
+        assert!(close(eta, 1.7 / 1.5));
+        assert!(media.current() == Some(&flint));
+        let (media, eta) = cross(&media, &crown, false);
+        assert!(close(eta, 1.7 / 1.5));
+        assert!(media.current() == Some(&flint));
+        let (media, eta) = cross(&media, &flint, false);
+        assert!(close(eta, 1.0 / 1.7));
+        assert!(media.current().is_none());
+    }
+}
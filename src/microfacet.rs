@@ -0,0 +1,326 @@
+use std::sync::OnceLock;
+
+use super::float::consts;
+use super::float::Float;
+use super::material::fresnel_dielectric;
+use super::vector3::{cross, dot, unit_vector, Vector3};
+
+// all directions are in the local shading frame, with z along the normal
+// and x along the first tangent
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals, with
+// separate roughness along the two tangents for anisotropic surfaces
+#[derive(Copy, Clone)]
+pub struct TrowbridgeReitz {
+    pub alpha_x: Float,
+    pub alpha_y: Float,
+}
+
+impl TrowbridgeReitz {
+    // perceptually linear roughness in [0, 1] to the distribution width
+    pub fn from_roughness(roughness_x: Float, roughness_y: Float) -> Self {
+        TrowbridgeReitz {
+            alpha_x: (roughness_x * roughness_x).max(1.0e-4),
+            alpha_y: (roughness_y * roughness_y).max(1.0e-4),
+        }
+    }
+
+    // below this the surface is treated as a perfect mirror
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1.0e-3
+    }
+
+    // density of microfacet normals
+    pub fn d(&self, h: Vector3) -> Float {
+        if h.z() <= 0.0 {
+            return 0.0;
+        }
+        let cos2 = h.z() * h.z();
+        let e = (h.x() * h.x() / (self.alpha_x * self.alpha_x)
+            + h.y() * h.y() / (self.alpha_y * self.alpha_y))
+            / cos2;
+        1.0 / (consts::PI * self.alpha_x * self.alpha_y * cos2 * cos2 * (1.0 + e) * (1.0 + e))
+    }
+
+    // Smith auxiliary function, projected area of the back facing microfacets
+    fn lambda(&self, w: Vector3) -> Float {
+        let cos2 = w.z() * w.z();
+        if cos2 == 0.0 {
+            return Float::MAX;
+        }
+        let alpha2_tan2 = (self.alpha_x * self.alpha_x * w.x() * w.x()
+            + self.alpha_y * self.alpha_y * w.y() * w.y())
+            / cos2;
+        0.5 * ((1.0 + alpha2_tan2).sqrt() - 1.0)
+    }
+
+    // fraction of microfacets visible from one direction
+    pub fn g1(&self, w: Vector3) -> Float {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // fraction visible from both directions
+    pub fn g(&self, wo: Vector3, wi: Vector3) -> Float {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // density of the normals sampled by `sample_visible`
+    pub fn visible_pdf(&self, wo: Vector3, h: Vector3) -> Float {
+        let cosine = wo.x() * h.x() + wo.y() * h.y() + wo.z() * h.z();
+        if wo.z() <= 0.0 || cosine <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * cosine * self.d(h) / wo.z()
+    }
+
+    // sample a microfacet normal visible from wo (Heitz 2018), wo must
+    // be above the surface and u1, u2 are uniform in [0, 1)
+    pub fn sample_visible(&self, wo: Vector3, u1: Float, u2: Float) -> Vector3 {
+        // stretch the view direction so the distribution becomes a hemisphere
+        let vh = unit_vector(Vector3::new(
+            self.alpha_x * wo.x(),
+            self.alpha_y * wo.y(),
+            wo.z(),
+        ));
+        let length_squared = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if length_squared > 0.0 {
+            Vector3::new(-vh.y(), vh.x(), 0.0) / length_squared.sqrt()
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = cross(vh, t1);
+
+        // uniform point on the disk, squashed onto the visible half
+        let r = u1.sqrt();
+        let phi = 2.0 * consts::PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        // unstretch back to the ellipsoid
+        unit_vector(Vector3::new(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            nh.z().max(1.0e-6),
+        ))
+    }
+}
+
+// microfacet normal that scatters wo into wi, eta is the index on the far
+// side of the surface relative to the side wo is on, which has wo.z > 0
+pub fn half_vector(wo: Vector3, wi: Vector3, eta: Float) -> Option<Vector3> {
+    let etap = if wi.z() > 0.0 { 1.0 } else { eta };
+    let h = wo + etap * wi;
+    if h.squared_length() == 0.0 {
+        return None;
+    }
+    let mut h = unit_vector(h);
+    if h.z() < 0.0 {
+        h = -h;
+    }
+    // microfacets seen from behind by either direction can't connect them
+    if dot(h, wi) * wi.z() <= 0.0 || dot(h, wo) * wo.z() <= 0.0 {
+        return None;
+    }
+    Some(h)
+}
+
+// refraction of wo through the microfacet h, None on total internal reflection
+pub fn refract(wo: Vector3, h: Vector3, eta: Float) -> Option<Vector3> {
+    let cos_i = dot(wo, h);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-wo / eta + (cos_i / eta - cos_t) * h)
+}
+
+// generalized Trowbridge-Reitz with gamma 1, the long tailed distribution
+// the Disney BRDF uses for its clearcoat lobe
+#[derive(Copy, Clone)]
+pub struct Gtr1 {
+    pub alpha: Float,
+}
+
+impl Gtr1 {
+    pub fn d(&self, h: Vector3) -> Float {
+        if h.z() <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        (a2 - 1.0) / (consts::PI * a2.ln() * (1.0 + (a2 - 1.0) * h.z() * h.z()))
+    }
+
+    // sample a normal proportional to d(h) * cos(h)
+    pub fn sample(&self, u1: Float, u2: Float) -> Vector3 {
+        let a2 = self.alpha * self.alpha;
+        let cos_theta = ((1.0 - a2.powf(1.0 - u1)) / (1.0 - a2)).max(0.0).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * consts::PI * u2;
+        Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+}
+
+// inverted gaussian like distribution of the fibers of cloth, which faces
+// away from the normal and shines at grazing angles (Estevez and Kulla 2017)
+#[derive(Copy, Clone)]
+pub struct Charlie {
+    pub alpha: Float,
+}
+
+impl Charlie {
+    pub fn from_roughness(roughness: Float) -> Self {
+        Charlie {
+            alpha: (roughness * roughness).clamp(ALBEDO_MIN_ALPHA, 1.0),
+        }
+    }
+
+    pub fn d(&self, h: Vector3) -> Float {
+        if h.z() <= 0.0 {
+            return 0.0;
+        }
+        let inv_alpha = 1.0 / self.alpha;
+        let sin_theta = (1.0 - h.z() * h.z()).max(0.0).sqrt();
+        (2.0 + inv_alpha) * sin_theta.powf(inv_alpha) / (2.0 * consts::PI)
+    }
+
+    // the fit of the paper to the shadowing of the fibers
+    fn l(&self, x: Float) -> Float {
+        let r = (1.0 - self.alpha) * (1.0 - self.alpha);
+        let lerp = |a: Float, b: Float| a + (b - a) * r;
+        let a = lerp(25.3245, 21.5473);
+        let b = lerp(3.32435, 3.82987);
+        let c = lerp(0.16801, 0.19823);
+        let d = lerp(-1.27393, -1.97760);
+        let e = lerp(-4.85967, -4.32054);
+        a / (1.0 + b * x.powf(c)) + d * x + e
+    }
+
+    fn lambda(&self, cosine: Float) -> Float {
+        let lambda = if cosine < 0.5 {
+            self.l(cosine).exp()
+        } else {
+            (2.0 * self.l(0.5) - self.l(1.0 - cosine)).exp()
+        };
+        // softened towards the terminator, where the fit lets too much through
+        let t = 1.0 - cosine;
+        lambda.powf(1.0 + 2.0 * t * t * t * t * t * t * t * t)
+    }
+
+    pub fn g(&self, wo: Vector3, wi: Vector3) -> Float {
+        1.0 / (1.0 + self.lambda(wo.z()) + self.lambda(wi.z()))
+    }
+
+    // fraction of the light arriving with the cosine that the fibers
+    // reflect, looked up in a table built once over cosine and alpha
+    pub fn albedo(&self, cosine: Float) -> Float {
+        let table = CHARLIE_ALBEDO.get_or_init(|| {
+            let mut table = Vec::with_capacity(ALBEDO_SIZE * ALBEDO_SIZE);
+            for i in 0..ALBEDO_SIZE {
+                let alpha = Charlie {
+                    alpha: ALBEDO_MIN_ALPHA + (1.0 - ALBEDO_MIN_ALPHA) * node(i),
+                };
+                for j in 0..ALBEDO_SIZE {
+                    table.push(alpha.integrated_albedo(node(j)));
+                }
+            }
+            table
+        });
+        // bilinear between the nodes around alpha and cosine
+        let position = |x: Float| {
+            let x = x.clamp(0.0, 1.0) * (ALBEDO_SIZE - 1) as Float;
+            let i = (x as usize).min(ALBEDO_SIZE - 2);
+            (i, x - i as Float)
+        };
+        let (i, s) = position((self.alpha - ALBEDO_MIN_ALPHA) / (1.0 - ALBEDO_MIN_ALPHA));
+        let (j, t) = position(cosine);
+        let at = |i: usize, j: usize| table[i * ALBEDO_SIZE + j];
+        let row = |i: usize| at(i, j) * (1.0 - t) + at(i, j + 1) * t;
+        row(i) * (1.0 - s) + row(i + 1) * s
+    }
+
+    // the albedo integrated numerically over the hemisphere
+    fn integrated_albedo(&self, cosine: Float) -> Float {
+        const STEPS: usize = 32;
+        let cosine = cosine.max(1.0e-3);
+        let wo = Vector3::new((1.0 - cosine * cosine).sqrt(), 0.0, cosine);
+        let mut sum = 0.0;
+        for i in 0..STEPS {
+            let cos_i = (i as Float + 0.5) / STEPS as Float;
+            let sin_i = (1.0 - cos_i * cos_i).sqrt();
+            for j in 0..STEPS {
+                // the lobe is symmetric about the plane of wo
+                let phi = consts::PI * (j as Float + 0.5) / STEPS as Float;
+                let wi = Vector3::new(sin_i * phi.cos(), sin_i * phi.sin(), cos_i);
+                let h = unit_vector(wo + wi);
+                sum += self.d(h) * self.g(wo, wi) / (4.0 * cosine);
+            }
+        }
+        sum * 2.0 * consts::PI / (STEPS * STEPS) as Float
+    }
+}
+
+// the smallest alpha `from_roughness` gives and the nodes of the albedo
+// table along alpha and the cosine, both spread over [0, 1]
+const ALBEDO_MIN_ALPHA: Float = 0.07;
+const ALBEDO_SIZE: usize = 64;
+
+fn node(i: usize) -> Float {
+    i as Float / (ALBEDO_SIZE - 1) as Float
+}
+
+static CHARLIE_ALBEDO: OnceLock<Vec<Float>> = OnceLock::new();
+
+// bsdf times cosine of a rough dielectric interface for light arriving
+// along wi, reflected or refracted towards wo (Walter et al. 2007)
+pub fn dielectric_bsdf_cos(
+    wo: Vector3,
+    wi: Vector3,
+    eta: Float,
+    distribution: &TrowbridgeReitz,
+) -> Float {
+    let h = match half_vector(wo, wi, eta) {
+        Some(h) => h,
+        None => return 0.0,
+    };
+    let reflectance = fresnel_dielectric(dot(wo, h), eta);
+    let dg = distribution.d(h) * distribution.g(wo, wi);
+    if wi.z() > 0.0 {
+        dg * reflectance / (4.0 * wo.z())
+    } else {
+        // radiance is compressed into the smaller solid angle
+        // on the denser side, hence the division by eta squared
+        let denom = dot(wi, h) + dot(wo, h) / eta;
+        (1.0 - reflectance) * dg * (dot(wi, h) * dot(wo, h)).abs()
+            / (denom * denom * wo.z())
+            / (eta * eta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charlie_albedo_table_matches_the_integral() {
+        let mut worst: Float = 0.0;
+        for i in 0..=20 {
+            let charlie = Charlie::from_roughness(0.2 + 0.8 * i as Float / 20.0);
+            for j in 0..=20 {
+                let cosine = 0.05 + 0.95 * j as Float / 20.0;
+                let error = (charlie.albedo(cosine) - charlie.integrated_albedo(cosine)).abs();
+                worst = worst.max(error);
+            }
+        }
+        assert!(worst < 0.005, "worst error {}", worst);
+    }
+
+    #[test]
+    fn charlie_albedo_table_hits_its_nodes() {
+        let charlie = Charlie { alpha: 1.0 };
+        let expected = charlie.integrated_albedo(node(7));
+        assert!((charlie.albedo(node(7)) - expected).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+use super::super::aabb::Aabb;
+use super::super::float::Float;
+use super::super::hittable::{HitRecord, Hittable};
+use super::super::material::Material;
+use super::super::ray::Ray;
+use super::super::texture::Texture;
+use super::super::vector3::{cross, dot, unit_vector, Vector3};
+
+// step in u and v for the finite differences of the height
+const DELTA: Float = 0.0005;
+
+// bumps the shading normal of an object as if the surface was displaced along
+// it by the red channel of the height texture times the scale, the geometry
+// and its normal stay where they are
+pub struct BumpMap {
+    pub object: Box<dyn Hittable>,
+    pub height: Arc<dyn Texture>,
+    pub scale: Float,
+}
+
+impl BumpMap {
+    // the displacement a step of du and dv away from the hit
+    fn displacement(&self, rec: &HitRecord, du: Float, dv: Float) -> Float {
+        let mut moved = rec.clone();
+        moved.u += du;
+        moved.v += dv;
+        moved.p += du * rec.dpdu + dv * rec.dpdv;
+        moved.p_object += du * rec.dpdu_object + dv * rec.dpdv_object;
+        self.scale * self.height.value(&moved).r() as Float
+    }
+
+    fn bump(&self, rec: &mut HitRecord) {
+        let n = rec.shading_normal;
+        let displacement = self.displacement(rec, 0.0, 0.0);
+        let u_displacement = self.displacement(rec, DELTA, 0.0);
+        let v_displacement = self.displacement(rec, 0.0, DELTA);
+        // tangents of the displaced surface, ignoring the change of the normal
+        let dpdu = rec.dpdu + (u_displacement - displacement) / DELTA * n;
+        let dpdv = rec.dpdv + (v_displacement - displacement) / DELTA * n;
+        let bumped = cross(dpdu, dpdv);
+        if bumped.squared_length() == 0.0 {
+            return;
+        }
+        let mut bumped = unit_vector(bumped);
+        if dot(bumped, n) < 0.0 {
+            bumped = -bumped;
+        }
+        rec.shading_normal = bumped;
+        rec.dpdu = dpdu;
+        rec.dpdv = dpdv;
+    }
+}
+
+impl Hittable for BumpMap {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<(HitRecord, &dyn Material)> {
+        self.object.hit(r, t_min, t_max).map(|(mut rec, mat)| {
+            self.bump(&mut rec);
+            (rec, mat)
+        })
+    }
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
+        self.object.bounding_box(t0, t1)
+    }
+    fn pdf_value(&self, o: Vector3, v: Vector3) -> Float {
+        self.object.pdf_value(o, v)
+    }
+    fn random(&self, o: Vector3) -> Vector3 {
+        self.object.random(o)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::float::to_f32;
+    use super::super::super::hittable::HitRecord;
+    use super::super::super::materials::lambertian::Lambertian;
+    use super::super::super::objects::plane::XYRect;
+    use super::super::super::texture::{constant, solid};
+    use super::super::super::vector3::Color;
+    use super::super::rotate::RotateY;
+    use super::*;
+
+    // the height rises with u, or with x on the object for `Solid`
+    struct Ramp;
+    struct Solid;
+
+    impl Texture for Ramp {
+        fn value(&self, rec: &HitRecord) -> Color {
+            let u = to_f32(rec.u);
+            Color::new(u, u, u)
+        }
+    }
+
+    impl Texture for Solid {
+        fn value(&self, rec: &HitRecord) -> Color {
+            let x = to_f32(rec.p_object.x());
+            Color::new(x, x, x)
+        }
+    }
+
+    // two units along x, so u changes half as fast as x
+    fn rect() -> Box<dyn Hittable> {
+        Box::new(XYRect {
+            x0: 0.0,
+            x1: 2.0,
+            y0: 0.0,
+            y1: 1.0,
+            k: 0.0,
+            material: Arc::new(Lambertian {
+                albedo: solid(Color::new(0.5, 0.5, 0.5)),
+            }),
+        })
+    }
+
+    fn bumped(object: Box<dyn Hittable>, height: Arc<dyn Texture>, r: &Ray) -> HitRecord {
+        let bump = BumpMap {
+            object,
+            height,
+            scale: 0.5,
+        };
+        bump.hit(r, 0.001, Float::MAX)
+            .expect("the ray hits the rect")
+            .0
+    }
+
+    fn down() -> Ray {
+        Ray::new(
+            Vector3::new(0.7, 0.5, 1.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            0.0,
+        )
+    }
+
+    #[test]
+    fn constant_height_keeps_the_normal() {
+        let rec = bumped(rect(), constant(0.7), &down());
+        assert!((rec.shading_normal - rec.normal).length() < 1e-6);
+    }
+
+    #[test]
+    fn ramp_in_u_tilts_the_normal() {
+        let rec = bumped(rect(), Arc::new(Ramp), &down());
+        // the height changes by 0.5 over the 2 units of u, and the normal
+        // leans away from where it rises
+        let tilt = dot(rec.shading_normal, rec.normal).acos();
+        assert!((tilt - (0.25 as Float).atan()).abs() < 1e-4);
+        assert!(rec.shading_normal.x() < 0.0 && rec.shading_normal.y().abs() < 1e-6);
+    }
+
+    #[test]
+    fn solid_heights_step_in_object_space() {
+        // turned a quarter the rect lies at x = 0 with z from -2 to 0
+        let turned = Box::new(RotateY::new(rect(), 90.0));
+        let r = Ray::new(
+            Vector3::new(1.0, 0.5, -0.7),
+            Vector3::new(-1.0, 0.0, 0.0),
+            0.0,
+        );
+        let rec = bumped(turned, Arc::new(Solid), &r);
+        // the height changes by 0.5 for each unit along the rect
+        let tilt = dot(rec.shading_normal, rec.normal).acos();
+        assert!((tilt - (0.5 as Float).atan()).abs() < 1e-4);
+    }
+}
@@ -1,28 +1,29 @@
-use super::super::hittable::{Hittable, HitRecord};
-use super::super::material::Material;
 use super::super::aabb::Aabb;
+use super::super::float::Float;
+use super::super::hittable::{HitRecord, Hittable};
+use super::super::material::Material;
 use super::super::ray::Ray;
 
 pub struct FlipNormals {
-    object: Box<Hittable>
+    object: Box<dyn Hittable>,
 }
 
 impl FlipNormals {
-    pub fn new(object: Box<Hittable>) -> Self {
+    pub fn new(object: Box<dyn Hittable>) -> Self {
         FlipNormals { object }
     }
 }
 
 impl Hittable for FlipNormals {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord, &Material)> {
-        match self.object.hit(r, t_min, t_max) {
-            Some((rec, mat)) => {
-                Some((HitRecord::new(rec.u, rec.v, rec.t, rec.p, -rec.normal), mat))
-            }
-            None => None,
-        }
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<(HitRecord, &dyn Material)> {
+        // the normals already face the ray, so flipping the outward
+        // direction only swaps which side counts as the front
+        self.object.hit(r, t_min, t_max).map(|(mut rec, mat)| {
+            rec.front_face = !rec.front_face;
+            (rec, mat)
+        })
     }
-    fn bounding_box(&self, t0: f32, t1: f32) -> Option<Aabb> {
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
         self.object.bounding_box(t0, t1)
     }
-}
\ No newline at end of file
+}
@@ -1,3 +1,5 @@
+pub mod bump_map;
 pub mod flip_normals;
+pub mod normal_map;
 pub mod rotate;
-pub mod translate;
\ No newline at end of file
+pub mod translate;
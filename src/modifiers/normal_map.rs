@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use super::super::aabb::Aabb;
+use super::super::float::Float;
+use super::super::hittable::{HitRecord, Hittable};
+use super::super::material::Material;
+use super::super::onb::ONB;
+use super::super::ray::Ray;
+use super::super::texture::Texture;
+use super::super::vector3::{dot, unit_vector, Vector3};
+
+// replaces the shading normal of an object with one read from a tangent space
+// normal map, usually an `ImageTexture`, where red, green and blue in [0, 1]
+// are the normal along dpdu, the bitangent and the surface normal in [-1, 1]
+pub struct NormalMap {
+    pub object: Box<dyn Hittable>,
+    pub map: Arc<dyn Texture>,
+}
+
+impl NormalMap {
+    fn perturb(&self, rec: &mut HitRecord) {
+        let texel = self.map.value(rec).to_vector();
+        let local = 2.0 * texel - Vector3::new(1.0, 1.0, 1.0);
+        if local.squared_length() == 0.0 {
+            return;
+        }
+        let uvw = ONB::from_tangent(rec.shading_normal, rec.dpdu);
+        let mut normal = unit_vector(uvw.local_vec(&local));
+        // a normal pointing into the surface would let light through it
+        if dot(normal, rec.normal) <= 0.0 {
+            normal = rec.shading_normal;
+        }
+        rec.shading_normal = normal;
+        // keep the tangent, materials orthogonalize it against the normal
+    }
+}
+
+impl Hittable for NormalMap {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<(HitRecord, &dyn Material)> {
+        self.object.hit(r, t_min, t_max).map(|(mut rec, mat)| {
+            self.perturb(&mut rec);
+            (rec, mat)
+        })
+    }
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
+        self.object.bounding_box(t0, t1)
+    }
+    fn pdf_value(&self, o: Vector3, v: Vector3) -> Float {
+        self.object.pdf_value(o, v)
+    }
+    fn random(&self, o: Vector3) -> Vector3 {
+        self.object.random(o)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::materials::lambertian::Lambertian;
+    use super::super::super::objects::plane::XYRect;
+    use super::super::super::texture::solid;
+    use super::super::super::vector3::Color;
+    use super::*;
+
+    fn mapped(texel: Color) -> HitRecord {
+        let rect = XYRect {
+            x0: 0.0,
+            x1: 1.0,
+            y0: 0.0,
+            y1: 1.0,
+            k: 0.0,
+            material: Arc::new(Lambertian {
+                albedo: solid(Color::new(0.5, 0.5, 0.5)),
+            }),
+        };
+        let normal_map = NormalMap {
+            object: Box::new(rect),
+            map: solid(texel),
+        };
+        let r = Ray::new(
+            Vector3::new(0.5, 0.5, 1.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            0.0,
+        );
+        normal_map.hit(&r, 0.001, Float::MAX).unwrap().0
+    }
+
+    #[test]
+    fn flat_texel_keeps_the_normal() {
+        let rec = mapped(Color::new(0.5, 0.5, 1.0));
+        assert!((rec.shading_normal - rec.normal).length() < 1e-6);
+    }
+
+    #[test]
+    fn texel_tilts_the_normal_along_the_tangent() {
+        // (1, 0, 1) in the tangent frame, 45 degrees towards dpdu
+        let rec = mapped(Color::new(1.0, 0.5, 1.0));
+        let expected = unit_vector(Vector3::new(1.0, 0.0, 1.0));
+        assert!((rec.shading_normal - expected).length() < 1e-5);
+        // pointing into the surface it is ignored
+        let rec = mapped(Color::new(0.5, 0.5, 0.0));
+        assert!((rec.shading_normal - rec.normal).length() < 1e-6);
+    }
+}
@@ -1,51 +1,50 @@
-use std::f32::consts::PI;
+use super::super::float::consts::PI;
+use super::super::float::Float;
 
 use super::super::aabb::Aabb;
 use super::super::hittable::{HitRecord, Hittable};
 use super::super::material::Material;
-use super::super::ray::Ray;
+use super::super::ray::{Differentials, Ray};
+use super::super::utils::gamma;
 use super::super::vector3::Vector3;
 
 pub struct RotateY {
-    pub object: Box<Hittable>,
-    pub sin_theta: f32,
-    pub cos_theta: f32,
+    pub object: Box<dyn Hittable>,
+    pub sin_theta: Float,
+    pub cos_theta: Float,
     pub aabb: Aabb,
 }
 
 impl RotateY {
-    pub fn new(object: Box<Hittable>, angle: f32) -> Self {
-        let radians: f32 = (PI / 180.0) * angle;
+    pub fn new(object: Box<dyn Hittable>, angle: Float) -> Self {
+        let radians: Float = (PI / 180.0) * angle;
         let sin_theta = radians.sin();
         let cos_theta = radians.cos();
         let aabb = object.bounding_box(0.0, 1.0);
-        let mut min = Vector3::new(std::f32::MAX, std::f32::MAX, std::f32::MAX);
-        let mut max = Vector3::new(-std::f32::MAX, -std::f32::MAX, -std::f32::MAX);
-        match aabb {
-            Some(bbox) => {
-                for i in 0..2 {
-                    for j in 0..2 {
-                        for k in 0..2 {
-                            let x = i as f32 * bbox.max.x() + (1 - i) as f32 * bbox.min.x();
-                            let y = j as f32 * bbox.max.y() + (1 - j) as f32 * bbox.min.y();
-                            let z = k as f32 * bbox.max.z() + (1 - k) as f32 * bbox.min.z();
-                            let newx: f32 = cos_theta * x + sin_theta * z;
-                            let newz: f32 = -sin_theta * x + cos_theta * z;
-                            let tester: Vector3 = Vector3::new(newx, y, newz);
-                            for c in 0..3 {
-                                if tester[c] > max[c] {
-                                    max[c] = tester[c];
-                                }
-                                if tester[c] < min[c] {
-                                    min[c] = tester[c];
-                                }
+        let mut min = Vector3::new(Float::MAX, Float::MAX, Float::MAX);
+        let mut max = Vector3::new(-Float::MAX, -Float::MAX, -Float::MAX);
+        if let Some(bbox) = aabb {
+            for i in 0..2 {
+                for j in 0..2 {
+                    for k in 0..2 {
+                        let x = i as Float * bbox.max.x() + (1 - i) as Float * bbox.min.x();
+                        let y = j as Float * bbox.max.y() + (1 - j) as Float * bbox.min.y();
+                        let z = k as Float * bbox.max.z() + (1 - k) as Float * bbox.min.z();
+                        let newx: Float = cos_theta * x + sin_theta * z;
+                        let newz: Float = -sin_theta * x + cos_theta * z;
+                        let tester: Vector3 = Vector3::new(newx, y, newz);
+                        for c in 0..3 {
+                            if tester[c] > max[c] {
+                                max[c] = tester[c];
+                            }
+                            if tester[c] < min[c] {
+                                min[c] = tester[c];
                             }
                         }
                     }
                 }
             }
-            None => {}
-        };
+        }
         RotateY {
             object,
             sin_theta,
@@ -53,31 +52,161 @@ impl RotateY {
             aabb: Aabb::new(min, max),
         }
     }
+
+    // rotate a vector from world space into object space
+    fn to_object(&self, v: Vector3) -> Vector3 {
+        Vector3::new(
+            self.cos_theta * v[0] - self.sin_theta * v[2],
+            v[1],
+            self.sin_theta * v[0] + self.cos_theta * v[2],
+        )
+    }
+
+    // rotate a vector from object space back to world space
+    fn to_world(&self, v: Vector3) -> Vector3 {
+        Vector3::new(
+            self.cos_theta * v[0] + self.sin_theta * v[2],
+            v[1],
+            -self.sin_theta * v[0] + self.cos_theta * v[2],
+        )
+    }
 }
 
 impl Hittable for RotateY {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord, &Material)> {
-        let mut origin = r.origin();
-        let mut direction = r.direction();
-        origin[0] = self.cos_theta * r.origin()[0] - self.sin_theta * r.origin()[2];
-        origin[2] = self.sin_theta * r.origin()[0] + self.cos_theta * r.origin()[2];
-        direction[0] = self.cos_theta * r.direction()[0] - self.sin_theta * r.direction()[2];
-        direction[2] = self.sin_theta * r.direction()[0] + self.cos_theta * r.direction()[2];
-        let rotated_r = Ray::new(origin, direction, r.time);
-        match self.object.hit(&rotated_r, t_min, t_max) {
-            Some((rec, mat)) => {
-                let mut p = rec.p;
-                let mut normal = rec.normal;
-                p[0] = self.cos_theta * rec.p[0] + self.sin_theta * rec.p[2];
-                p[2] = -self.sin_theta * rec.p[0] + self.cos_theta * rec.p[2];
-                normal[0] = self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
-                normal[2] = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
-                Some((HitRecord::new(rec.u, rec.v, rec.t, p, normal), mat))
-            }
-            None => None,
-        }
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<(HitRecord, &dyn Material)> {
+        let rotated_r = Ray::new(
+            self.to_object(r.origin()),
+            self.to_object(r.direction()),
+            r.time,
+        )
+        .with_wavelengths(r.wavelengths)
+        .with_differentials(r.differentials.map(|d| Differentials {
+            rx_origin: self.to_object(d.rx_origin),
+            rx_direction: self.to_object(d.rx_direction),
+            ry_origin: self.to_object(d.ry_origin),
+            ry_direction: self.to_object(d.ry_direction),
+        }));
+        self.object
+            .hit(&rotated_r, t_min, t_max)
+            .map(|(mut rec, mat)| {
+                // the rotation rounds both the point and the rotated ray origin
+                let rounding = Vector3::new(
+                    (self.cos_theta * rec.p[0]).abs() + (self.sin_theta * rec.p[2]).abs(),
+                    rec.p[1].abs(),
+                    (self.sin_theta * rec.p[0]).abs() + (self.cos_theta * rec.p[2]).abs(),
+                );
+                let error = rec.p_error;
+                rec.p_error = Vector3::new(
+                    (self.cos_theta * error[0]).abs() + (self.sin_theta * error[2]).abs(),
+                    error[1],
+                    (self.sin_theta * error[0]).abs() + (self.cos_theta * error[2]).abs(),
+                ) * (1.0 + gamma(3))
+                    + 2.0 * gamma(3) * rounding;
+                rec.p = self.to_world(rec.p);
+                rec.normal = self.to_world(rec.normal);
+                rec.shading_normal = self.to_world(rec.shading_normal);
+                rec.dpdu = self.to_world(rec.dpdu);
+                rec.dpdv = self.to_world(rec.dpdv);
+                rec.dndu = self.to_world(rec.dndu);
+                rec.dndv = self.to_world(rec.dndv);
+                rec.dpdx = self.to_world(rec.dpdx);
+                rec.dpdy = self.to_world(rec.dpdy);
+                (rec, mat)
+            })
     }
-    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<Aabb> {
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
         Some(self.aabb)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::super::super::materials::lambertian::Lambertian;
+    use super::super::super::objects::object_list::ObjectList;
+    use super::super::super::objects::sphere::Sphere;
+    use super::super::super::texture::solid;
+    use super::super::super::vector3::Color;
+    use super::super::flip_normals::FlipNormals;
+    use super::super::translate::Translate;
+    use super::*;
+
+    fn sphere() -> Sphere {
+        Sphere {
+            center: Vector3::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+            material: Arc::new(Lambertian {
+                albedo: solid(Color::new(0.5, 0.5, 0.5)),
+            }),
+        }
+    }
+
+    fn close(a: Vector3, b: Vector3) -> bool {
+        (a - b).length() < 1e-4
+    }
+
+    fn differentials(origin: Vector3, dx: Vector3, direction: Vector3) -> Differentials {
+        Differentials {
+            rx_origin: origin + dx,
+            rx_direction: direction,
+            ry_origin: origin + Vector3::new(0.0, 0.01, 0.0),
+            ry_direction: direction,
+        }
+    }
+
+    #[test]
+    fn instanced_hit_matches_the_object_space_hit() {
+        let rotate = RotateY::new(Box::new(FlipNormals::new(Box::new(sphere()))), 90.0);
+        let frame = RotateY::new(Box::new(sphere()), 90.0);
+        let to_world = |v| frame.to_world(v);
+        let offset = Vector3::new(5.0, 0.0, 0.0);
+        let instance = Translate {
+            object: Box::new(rotate),
+            offset,
+        };
+        let world = ObjectList::new(vec![Box::new(sphere()), Box::new(instance)]);
+
+        let origin = Vector3::new(5.0, 0.0, -5.0);
+        let direction = Vector3::new(0.0, 0.0, 1.0);
+        let r = Ray::new(origin, direction, 0.0).with_differentials(Some(differentials(
+            origin,
+            Vector3::new(0.01, 0.0, 0.0),
+            direction,
+        )));
+        let (rec, _) = world
+            .hit(&r, 0.0, Float::MAX)
+            .expect("ray aims at the instance");
+
+        // the same ray in the space of the sphere
+        let local_origin = Vector3::new(5.0, 0.0, 0.0);
+        let local_direction = Vector3::new(-1.0, 0.0, 0.0);
+        let local_r = Ray::new(local_origin, local_direction, 0.0).with_differentials(Some(
+            // the offset in x turns into one in z
+            differentials(local_origin, Vector3::new(0.0, 0.0, 0.01), local_direction),
+        ));
+        let (local, _) = FlipNormals::new(Box::new(sphere()))
+            .hit(&local_r, 0.0, Float::MAX)
+            .unwrap();
+
+        assert_eq!(rec.object_id, 1);
+        assert!(!rec.front_face);
+        assert!((rec.t - 4.0).abs() < 1e-4);
+        assert!(close(rec.p, Vector3::new(5.0, 0.0, -1.0)));
+        assert!(close(rec.normal, Vector3::new(0.0, 0.0, -1.0)));
+        assert!(close(rec.shading_normal, rec.normal));
+        assert!(close(rec.dpdu, to_world(local.dpdu)));
+        assert!(close(rec.dpdv, to_world(local.dpdv)));
+        // the error bounds grow through the transforms and still cover the point
+        assert!(rec.p_error.length() >= local.p_error.length());
+        let exact = Vector3::new(5.0, 0.0, -1.0);
+        for i in 0..3 {
+            assert!((rec.p[i] - exact[i]).abs() <= rec.p_error[i]);
+        }
+        // the neighbouring rays moved with the instance
+        assert!((rec.footprint.dudx - local.footprint.dudx).abs() < 1e-4);
+        assert!((rec.footprint.dvdy - local.footprint.dvdy).abs() < 1e-4);
+        assert!(rec.footprint.dudx != 0.0 || rec.footprint.dvdx != 0.0);
+        assert!(close(rec.dpdx, to_world(local.dpdx)));
+    }
+}
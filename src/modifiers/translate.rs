@@ -1,29 +1,39 @@
 use super::super::aabb::Aabb;
+use super::super::float::Float;
 use super::super::hittable::{HitRecord, Hittable};
 use super::super::material::Material;
-use super::super::ray::Ray;
+use super::super::ray::{Differentials, Ray};
+use super::super::utils::gamma;
 use super::super::vector3::Vector3;
 
 pub struct Translate {
-    pub object: Box<Hittable>,
+    pub object: Box<dyn Hittable>,
     pub offset: Vector3,
 }
 
 impl Hittable for Translate {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord, &Material)> {
-        let moved_ray: Ray = Ray::new(r.origin() - self.offset, r.direction(), r.time);
-        match self.object.hit(&moved_ray, t_min, t_max) {
-            Some((rec, mat)) => Some((
-                HitRecord::new(rec.u, rec.v, rec.t, rec.p + self.offset, rec.normal),
-                mat,
-            )),
-            None => None,
-        }
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<(HitRecord, &dyn Material)> {
+        // the neighbouring rays move along so footprints stay the same
+        let moved_ray: Ray = Ray::new(r.origin() - self.offset, r.direction(), r.time)
+            .with_wavelengths(r.wavelengths)
+            .with_differentials(r.differentials.map(|d| Differentials {
+                rx_origin: d.rx_origin - self.offset,
+                ry_origin: d.ry_origin - self.offset,
+                ..d
+            }));
+        self.object
+            .hit(&moved_ray, t_min, t_max)
+            .map(|(mut rec, mat)| {
+                let local = rec.p;
+                rec.p += self.offset;
+                // cover the rounding of both the point and the moved ray origin
+                rec.p_error += gamma(2) * (local.abs() + rec.p.abs());
+                (rec, mat)
+            })
     }
-    fn bounding_box(&self, t0: f32, t1: f32) -> Option<Aabb> {
-        match self.object.bounding_box(t0, t1) {
-            Some(aabb) => Some(Aabb::new(aabb.min + self.offset, aabb.max + self.offset)),
-            None => None,
-        }
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
+        self.object
+            .bounding_box(t0, t1)
+            .map(|aabb| Aabb::new(aabb.min + self.offset, aabb.max + self.offset))
     }
 }
@@ -0,0 +1,526 @@
+use super::super::aabb::{surrounding_box, Aabb, Aabb4, SlabRay, SlabTest};
+use super::super::float::Float;
+use super::super::hittable::{HitRecord, Hittable};
+use super::super::material::Material;
+use super::super::ray::Ray;
+
+// traversal holds up to three siblings of every level on its stack, and
+// with the count halved by every split the depth is log4 of the objects,
+// so this is enough for 4^21 of them
+const STACK_SIZE: usize = 64;
+// rays traced together, one bit each in the active masks
+const PACKET_SIZE: usize = 64;
+// packets with fewer rays left than this fall back to single rays
+const MIN_PACKET_RAYS: u32 = 4;
+
+// object indices paired with their bounding boxes while building
+type Items = Vec<(usize, Aabb)>;
+
+#[derive(Copy, Clone)]
+enum Child {
+    Empty,
+    Node(usize),
+    Leaf(usize),
+}
+
+struct Node {
+    bounds: Aabb4,
+    children: [Child; 4],
+}
+
+// bounding volume hierarchy with four children per node, all four
+// child boxes are tested against the ray in a single SIMD slab test
+pub struct Bvh4 {
+    nodes: Vec<Node>,
+    objects: Vec<Box<dyn Hittable>>,
+    root: Child,
+    aabb: Option<Aabb>,
+    slab_test: SlabTest,
+}
+
+fn centroid(aabb: &Aabb, axis: usize) -> Float {
+    0.5 * (aabb.min[axis] + aabb.max[axis])
+}
+
+fn bounds_of(items: &[(usize, Aabb)]) -> Aabb {
+    let mut aabb = items[0].1;
+    for (_, item_aabb) in items.iter().skip(1) {
+        aabb = surrounding_box(&aabb, item_aabb);
+    }
+    aabb
+}
+
+// sort along the axis where the centroids are spread the most and cut in half
+fn split(mut items: Items) -> (Items, Items) {
+    let mut min = [Float::MAX; 3];
+    let mut max = [-Float::MAX; 3];
+    for (_, aabb) in &items {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(centroid(aabb, axis));
+            max[axis] = max[axis].max(centroid(aabb, axis));
+        }
+    }
+    let mut axis = 0;
+    for a in 1..3 {
+        if max[a] - min[a] > max[axis] - min[axis] {
+            axis = a;
+        }
+    }
+    items.sort_by(|a, b| {
+        centroid(&a.1, axis)
+            .partial_cmp(&centroid(&b.1, axis))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let right = items.split_off(items.len() / 2);
+    (items, right)
+}
+
+// the subtree over the items and how many levels of nodes it has
+fn build(items: Items, nodes: &mut Vec<Node>) -> (Child, usize) {
+    if items.is_empty() {
+        return (Child::Empty, 0);
+    }
+    if items.len() == 1 {
+        return (Child::Leaf(items[0].0), 0);
+    }
+    // two levels of binary splits give up to four groups
+    let (left, right) = split(items);
+    let mut groups: Vec<Items> = Vec::with_capacity(4);
+    for half in [left, right] {
+        if half.len() > 1 {
+            let (a, b) = split(half);
+            groups.push(a);
+            groups.push(b);
+        } else {
+            groups.push(half);
+        }
+    }
+
+    let mut bounds = Aabb4::empty();
+    let mut children = [Child::Empty; 4];
+    let mut depth = 0;
+    for (lane, group) in groups.into_iter().enumerate() {
+        bounds.set(lane, &bounds_of(&group));
+        let (child, child_depth) = build(group, nodes);
+        children[lane] = child;
+        depth = depth.max(child_depth);
+    }
+    nodes.push(Node { bounds, children });
+    (Child::Node(nodes.len() - 1), depth + 1)
+}
+
+impl Bvh4 {
+    pub fn new(list: Vec<Box<dyn Hittable>>, t0: Float, t1: Float) -> Self {
+        let items: Items = list
+            .iter()
+            .enumerate()
+            .map(|(i, object)| match object.bounding_box(t0, t1) {
+                Some(aabb) => (i, aabb),
+                None => panic!["No bounding box"],
+            })
+            .collect();
+        let aabb = if items.is_empty() {
+            None
+        } else {
+            Some(bounds_of(&items))
+        };
+        let mut nodes: Vec<Node> = Vec::with_capacity(list.len() / 2 + 1);
+        let (root, depth) = build(items, &mut nodes);
+        assert!(
+            3 * depth < STACK_SIZE,
+            "a Bvh4 {} levels deep overflows the traversal stack",
+            depth
+        );
+        Bvh4 {
+            nodes,
+            objects: list,
+            root,
+            aabb,
+            slab_test: SlabTest::Simd,
+        }
+    }
+
+    pub fn with_slab_test(mut self, slab_test: SlabTest) -> Self {
+        self.slab_test = slab_test;
+        self
+    }
+}
+
+// lanes of the boxes that were hit, from the closest to the farthest
+fn front_to_back(mask: u32, entry: &[Float; 4]) -> ([usize; 4], usize) {
+    let mut order = [0; 4];
+    let mut count = 0;
+    for (lane, &t_entry) in entry.iter().enumerate() {
+        if mask & (1 << lane) != 0 {
+            let mut i = count;
+            while i > 0 && entry[order[i - 1]] > t_entry {
+                order[i] = order[i - 1];
+                i -= 1;
+            }
+            order[i] = lane;
+            count += 1;
+        }
+    }
+    (order, count)
+}
+
+impl Bvh4 {
+    // single ray traversal of the subtree below `start`
+    fn hit_from(
+        &self,
+        start: usize,
+        r: &Ray,
+        slab_ray: &SlabRay,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<(HitRecord, &dyn Material)> {
+        let mut closest_so_far = t_max;
+        let mut closest_hit: Option<(HitRecord, &dyn Material)> = None;
+        let mut stack = [0; STACK_SIZE];
+        stack[0] = start;
+        let mut stack_len = 1;
+
+        while stack_len > 0 {
+            stack_len -= 1;
+            let node = &self.nodes[stack[stack_len]];
+            let (mask, entry) =
+                node.bounds
+                    .hit_with(self.slab_test, slab_ray, t_min, closest_so_far);
+            if mask == 0 {
+                continue;
+            }
+
+            // visit the children front to back so closer hits
+            // shrink the interval for the ones behind them
+            let (order, count) = front_to_back(mask, &entry);
+            for &lane in order[..count].iter().rev() {
+                if let Child::Node(child_node) = node.children[lane] {
+                    stack[stack_len] = child_node;
+                    stack_len += 1;
+                }
+            }
+            for &lane in order[..count].iter() {
+                if let Child::Leaf(object) = node.children[lane] {
+                    if let Some((mut rec, mat)) = self.objects[object].hit(r, t_min, closest_so_far)
+                    {
+                        // the index in the list like an `ObjectList` would give
+                        rec.object_id = object;
+                        closest_so_far = rec.t;
+                        closest_hit = Some((rec, mat));
+                    }
+                }
+            }
+        }
+
+        closest_hit
+    }
+
+    // traverse with up to PACKET_SIZE rays at once, a node is visited when any
+    // active ray hits its box and once too few rays are left in a subtree
+    // they finish it one at a time
+    fn hit_packet_from<'a>(
+        &'a self,
+        start: usize,
+        rays: &[Ray],
+        slab_rays: &[SlabRay],
+        t_min: Float,
+        t_max: &mut [Float],
+        hits: &mut [Option<(HitRecord, &'a dyn Material)>],
+    ) {
+        let mut stack = [(0, 0); STACK_SIZE];
+        stack[0] = (start, active_mask(rays.len()));
+        let mut stack_len = 1;
+
+        while stack_len > 0 {
+            stack_len -= 1;
+            let (node_index, active) = stack[stack_len];
+            if active.count_ones() < MIN_PACKET_RAYS {
+                for i in active_rays(active) {
+                    let r = &rays[i];
+                    if let Some((rec, mat)) =
+                        self.hit_from(node_index, r, &slab_rays[i], t_min, t_max[i])
+                    {
+                        t_max[i] = rec.t;
+                        hits[i] = Some((rec, mat));
+                    }
+                }
+                continue;
+            }
+            let node = &self.nodes[node_index];
+
+            let mut child_active: [u64; 4] = [0; 4];
+            let mut child_entry = [Float::MAX; 4];
+            let mut mask = 0;
+            for i in active_rays(active) {
+                let (ray_mask, entry) =
+                    node.bounds
+                        .hit_with(self.slab_test, &slab_rays[i], t_min, t_max[i]);
+                mask |= ray_mask;
+                for lane in 0..4 {
+                    if ray_mask & (1 << lane) != 0 {
+                        child_active[lane] |= 1 << i;
+                        child_entry[lane] = child_entry[lane].min(entry[lane]);
+                    }
+                }
+            }
+            if mask == 0 {
+                continue;
+            }
+
+            // children are ordered by the closest entry of any ray in the packet
+            let (order, count) = front_to_back(mask, &child_entry);
+            for &lane in order[..count].iter().rev() {
+                if let Child::Node(child_node) = node.children[lane] {
+                    stack[stack_len] = (child_node, child_active[lane]);
+                    stack_len += 1;
+                }
+            }
+            for &lane in order[..count].iter() {
+                if let Child::Leaf(object) = node.children[lane] {
+                    for i in active_rays(child_active[lane]) {
+                        if let Some((mut rec, mat)) =
+                            self.objects[object].hit(&rays[i], t_min, t_max[i])
+                        {
+                            rec.object_id = object;
+                            t_max[i] = rec.t;
+                            hits[i] = Some((rec, mat));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn active_mask(count: usize) -> u64 {
+    if count >= PACKET_SIZE {
+        u64::MAX
+    } else {
+        (1 << count) - 1
+    }
+}
+
+// indices of the rays whose bit is set
+fn active_rays(mut active: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if active == 0 {
+            return None;
+        }
+        let i = active.trailing_zeros() as usize;
+        active &= active - 1;
+        Some(i)
+    })
+}
+
+impl Hittable for Bvh4 {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<(HitRecord, &dyn Material)> {
+        match self.root {
+            Child::Node(node) => self.hit_from(node, r, &SlabRay::new(r), t_min, t_max),
+            Child::Leaf(object) => {
+                let (mut rec, mat) = self.objects[object].hit(r, t_min, t_max)?;
+                rec.object_id = object;
+                Some((rec, mat))
+            }
+            Child::Empty => None,
+        }
+    }
+    fn hit_packet(
+        &self,
+        rays: &[Ray],
+        t_min: Float,
+        t_max: &mut [Float],
+    ) -> Vec<Option<(HitRecord, &dyn Material)>> {
+        let mut hits: Vec<Option<(HitRecord, &dyn Material)>> = rays.iter().map(|_| None).collect();
+        let node = match self.root {
+            Child::Node(node) => node,
+            _ => {
+                for (i, r) in rays.iter().enumerate() {
+                    hits[i] = self.hit(r, t_min, t_max[i]);
+                    if let Some((rec, _)) = &hits[i] {
+                        t_max[i] = rec.t;
+                    }
+                }
+                return hits;
+            }
+        };
+        let slab_rays: Vec<SlabRay> = rays.iter().map(SlabRay::new).collect();
+        for (start, chunk) in rays.chunks(PACKET_SIZE).enumerate() {
+            let range = start * PACKET_SIZE..start * PACKET_SIZE + chunk.len();
+            self.hit_packet_from(
+                node,
+                chunk,
+                &slab_rays[range.clone()],
+                t_min,
+                &mut t_max[range.clone()],
+                &mut hits[range],
+            );
+        }
+        hits
+    }
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        self.aabb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::materials::lambertian::Lambertian;
+    use super::super::super::texture::solid;
+    use super::super::super::vector3::{Color, Vector3};
+    use super::super::bvh_tree::BvhTree;
+    use super::super::object_list::ObjectList;
+    use super::super::sphere::Sphere;
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::sync::Arc;
+
+    fn spheres(rng: &mut StdRng, count: usize) -> Vec<Box<dyn Hittable>> {
+        (0..count)
+            .map(|_| {
+                Box::new(Sphere {
+                    center: Vector3::new(
+                        100.0 * rng.gen::<Float>(),
+                        100.0 * rng.gen::<Float>(),
+                        100.0 * rng.gen::<Float>(),
+                    ),
+                    radius: 1.0 + 4.0 * rng.gen::<Float>(),
+                    material: Arc::new(Lambertian {
+                        albedo: solid(Color::new(0.5, 0.5, 0.5)),
+                    }),
+                }) as Box<dyn Hittable>
+            })
+            .collect()
+    }
+
+    fn assert_packet_matches_single(bvh: &Bvh4, rays: &[Ray]) {
+        let mut t_max = vec![Float::MAX; rays.len()];
+        let packet = bvh.hit_packet(rays, 0.0, &mut t_max);
+        assert!(packet.iter().any(|hit| hit.is_some()));
+        for ((r, packet_hit), t_max) in rays.iter().zip(&packet).zip(&t_max) {
+            let single = bvh.hit(r, 0.0, Float::MAX);
+            match (single, packet_hit) {
+                (None, None) => assert_eq!(*t_max, Float::MAX),
+                (Some((a, _)), Some((b, _))) => {
+                    assert_eq!(a.t, b.t);
+                    assert_eq!(a.object_id, b.object_id);
+                    assert_eq!(*t_max, b.t);
+                }
+                _ => panic!("packet and single ray disagree on hitting"),
+            }
+        }
+    }
+
+    // a tile of nearly parallel rays like the primary rays of a pixel tile,
+    // more than fit in one packet
+    #[test]
+    fn packet_hits_match_single_hits_coherent() {
+        let bvh = Bvh4::new(spheres(&mut StdRng::seed_from_u64(1), 500), 0.0, 1.0);
+        let origin = Vector3::new(-50.0, 50.0, -50.0);
+        let rays: Vec<Ray> = (0..100)
+            .map(|i| {
+                let (x, y) = ((i % 10) as Float, (i / 10) as Float);
+                let target = Vector3::new(40.0 + x, 40.0 + y, 50.0);
+                Ray::new(origin, target - origin, 0.0)
+            })
+            .collect();
+        assert_packet_matches_single(&bvh, &rays);
+    }
+
+    // rays going every which way split the packet up until they finish
+    // one at a time
+    #[test]
+    fn packet_hits_match_single_hits_incoherent() {
+        let bvh = Bvh4::new(spheres(&mut StdRng::seed_from_u64(2), 500), 0.0, 1.0);
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut point = || {
+            Vector3::new(
+                100.0 * rng.gen::<Float>(),
+                100.0 * rng.gen::<Float>(),
+                100.0 * rng.gen::<Float>(),
+            )
+        };
+        let rays: Vec<Ray> = (0..200)
+            .map(|_| {
+                let (origin, target) = (point(), point());
+                Ray::new(origin, target - origin, 0.0)
+            })
+            .collect();
+        assert_packet_matches_single(&bvh, &rays);
+    }
+
+    // ID AOVs come out the same whichever structure holds the world
+    #[test]
+    fn object_ids_match_the_other_structures() {
+        let list = || spheres(&mut StdRng::seed_from_u64(4), 200);
+        let bvh = Bvh4::new(list(), 0.0, 1.0);
+        let binary = BvhTree::new(list(), 0.0, 1.0);
+        let objects = ObjectList::new(list());
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut hits = 0;
+        for _ in 0..200 {
+            let origin = Vector3::new(-50.0, 100.0 * rng.gen::<Float>(), -50.0);
+            let target = Vector3::new(
+                100.0 * rng.gen::<Float>(),
+                100.0 * rng.gen::<Float>(),
+                100.0,
+            );
+            let r = Ray::new(origin, target - origin, 0.0);
+            let id = |hit: Option<(HitRecord, &dyn Material)>| hit.map(|(rec, _)| rec.object_id);
+            let expected = id(objects.hit(&r, 0.0, Float::MAX));
+            assert_eq!(id(bvh.hit(&r, 0.0, Float::MAX)), expected);
+            assert_eq!(id(binary.hit(&r, 0.0, Float::MAX)), expected);
+            hits += expected.is_some() as usize;
+        }
+        assert!(hits > 0);
+    }
+
+    // median splits on the count keep the tree shallow even when
+    // every centroid is the same
+    #[test]
+    fn coincident_objects_stay_shallow() {
+        let sphere = || {
+            Box::new(Sphere {
+                center: Vector3::new(0.0, 0.0, 10.0),
+                radius: 1.0,
+                material: Arc::new(Lambertian {
+                    albedo: solid(Color::new(0.5, 0.5, 0.5)),
+                }),
+            }) as Box<dyn Hittable>
+        };
+        let list: Vec<Box<dyn Hittable>> = (0..5000).map(|_| sphere()).collect();
+        let items: Items = list
+            .iter()
+            .enumerate()
+            .map(|(i, object)| (i, object.bounding_box(0.0, 1.0).unwrap()))
+            .collect();
+        let (_, depth) = build(items, &mut Vec::new());
+        // 4^7 > 5000
+        assert!(depth <= 7);
+        let bvh = Bvh4::new(list, 0.0, 1.0);
+        let r = Ray::new(
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+            0.0,
+        );
+        let (rec, _) = bvh.hit(&r, 0.0, Float::MAX).unwrap();
+        assert!((rec.t - 9.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn empty_list() {
+        let bvh = Bvh4::new(Vec::new(), 0.0, 1.0);
+        let ray = || {
+            Ray::new(
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(0.0, 0.0, 1.0),
+                0.0,
+            )
+        };
+        assert!(bvh.hit(&ray(), 0.0, Float::MAX).is_none());
+        assert!(bvh.bounding_box(0.0, 1.0).is_none());
+        let mut t_max = vec![Float::MAX; 2];
+        let hits = bvh.hit_packet(&[ray(), ray()], 0.0, &mut t_max);
+        assert!(hits.iter().all(|hit| hit.is_none()));
+    }
+}
@@ -2,12 +2,14 @@ use rand::Rng;
 use std::cmp::Ordering;
 
 use super::super::aabb::{surrounding_box, Aabb};
+use super::super::float::Float;
 use super::super::hittable::{HitRecord, Hittable};
 use super::super::material::Material;
 use super::super::ray::Ray;
 
 pub enum BinaryTree {
-    Leaf(Box<Hittable>),
+    // the object and its index in the list the tree was built from
+    Leaf(Box<dyn Hittable>, usize),
     Node(Box<BvhTree>, Box<BvhTree>),
 }
 
@@ -16,12 +18,12 @@ pub struct BvhTree {
     pub aabb: Aabb,
 }
 
-fn box_x_compare(a: &Box<Hittable>, b: &Box<Hittable>) -> Ordering {
+fn box_x_compare(a: &dyn Hittable, b: &dyn Hittable) -> Ordering {
     let left_aabb = a.bounding_box(0.0, 0.0);
     let right_aabb = b.bounding_box(0.0, 0.0);
     match (left_aabb, right_aabb) {
         (Some(lbb), Some(rbb)) => {
-            let delta: f32 = lbb.min.x() - rbb.min.x();
+            let delta: Float = lbb.min.x() - rbb.min.x();
             if delta < 0.0 {
                 Ordering::Less
             } else if delta == 0.0 {
@@ -34,12 +36,12 @@ fn box_x_compare(a: &Box<Hittable>, b: &Box<Hittable>) -> Ordering {
     }
 }
 
-fn box_y_compare(a: &Box<Hittable>, b: &Box<Hittable>) -> Ordering {
+fn box_y_compare(a: &dyn Hittable, b: &dyn Hittable) -> Ordering {
     let left_aabb = a.bounding_box(0.0, 0.0);
     let right_aabb = b.bounding_box(0.0, 0.0);
     match (left_aabb, right_aabb) {
         (Some(lbb), Some(rbb)) => {
-            let delta: f32 = lbb.min.y() - rbb.min.y();
+            let delta: Float = lbb.min.y() - rbb.min.y();
             if delta < 0.0 {
                 Ordering::Less
             } else if delta == 0.0 {
@@ -52,12 +54,12 @@ fn box_y_compare(a: &Box<Hittable>, b: &Box<Hittable>) -> Ordering {
     }
 }
 
-fn box_z_compare(a: &Box<Hittable>, b: &Box<Hittable>) -> Ordering {
+fn box_z_compare(a: &dyn Hittable, b: &dyn Hittable) -> Ordering {
     let left_aabb = a.bounding_box(0.0, 0.0);
     let right_aabb = b.bounding_box(0.0, 0.0);
     match (left_aabb, right_aabb) {
         (Some(lbb), Some(rbb)) => {
-            let delta: f32 = lbb.min.z() - rbb.min.z();
+            let delta: Float = lbb.min.z() - rbb.min.z();
             if delta < 0.0 {
                 Ordering::Less
             } else if delta == 0.0 {
@@ -71,32 +73,37 @@ fn box_z_compare(a: &Box<Hittable>, b: &Box<Hittable>) -> Ordering {
 }
 
 impl BvhTree {
-    pub fn new(mut list: Vec<Box<Hittable>>, t0: f32, t1: f32) -> Self {
+    pub fn new(list: Vec<Box<dyn Hittable>>, t0: Float, t1: Float) -> Self {
+        BvhTree::build(list.into_iter().enumerate().collect(), t0, t1)
+    }
+
+    fn build(mut list: Vec<(usize, Box<dyn Hittable>)>, t0: Float, t1: Float) -> Self {
         let mut rng = rand::thread_rng();
         let axis: usize = rng.gen_range(0, 3);
         match axis {
-            0 => list.sort_by(box_x_compare),
-            1 => list.sort_by(box_y_compare),
-            _ => list.sort_by(box_z_compare),
+            0 => list.sort_by(|a, b| box_x_compare(a.1.as_ref(), b.1.as_ref())),
+            1 => list.sort_by(|a, b| box_y_compare(a.1.as_ref(), b.1.as_ref())),
+            _ => list.sort_by(|a, b| box_z_compare(a.1.as_ref(), b.1.as_ref())),
         };
         let list_length = list.len();
 
         match list_length {
             1 => {
-                let hittable = list.pop().unwrap();
+                let (object, hittable) = list.pop().unwrap();
                 let bbox = hittable.bounding_box(t0, t1);
 
-                return BvhTree {
-                    binary_tree: BinaryTree::Leaf(hittable),
+                BvhTree {
+                    binary_tree: BinaryTree::Leaf(hittable, object),
                     aabb: match bbox {
                         Some(bb) => bb,
                         None => panic!["No bounding box"],
                     },
-                };
+                }
             }
             _ => {
-                let mut vec1: Vec<Box<Hittable>> = Vec::with_capacity(list_length / 2 + 1);
-                let mut vec2: Vec<Box<Hittable>> = Vec::with_capacity(list_length / 2);
+                let mut vec1: Vec<(usize, Box<dyn Hittable>)> =
+                    Vec::with_capacity(list_length / 2 + 1);
+                let mut vec2: Vec<(usize, Box<dyn Hittable>)> = Vec::with_capacity(list_length / 2);
 
                 for (i, el) in list.into_iter().enumerate() {
                     if i < list_length / 2 {
@@ -105,8 +112,8 @@ impl BvhTree {
                         vec2.push(el);
                     }
                 }
-                let left = BvhTree::new(vec1, t0, t1);
-                let right = BvhTree::new(vec2, t0, t1);
+                let left = BvhTree::build(vec1, t0, t1);
+                let right = BvhTree::build(vec2, t0, t1);
                 let left_bbox = left.bounding_box(t0, t1);
                 let right_bbox = right.bounding_box(t0, t1);
                 let aabb = match (left_bbox, right_bbox) {
@@ -115,30 +122,28 @@ impl BvhTree {
                     (None, Some(rbb)) => rbb,
                     (None, None) => panic!["No bounding box"],
                 };
-                return BvhTree {
+                BvhTree {
                     binary_tree: BinaryTree::Node(Box::new(left), Box::new(right)),
                     aabb,
-                };
+                }
             }
         }
     }
 }
 
 impl Hittable for BvhTree {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord, &Material)> {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<(HitRecord, &dyn Material)> {
         match &self.binary_tree {
-            BinaryTree::Leaf(hittable) => hittable.hit(r, t_min, t_max),
+            BinaryTree::Leaf(hittable, object) => {
+                let (mut rec, mat) = hittable.hit(r, t_min, t_max)?;
+                // the index in the list like an `ObjectList` would give
+                rec.object_id = *object;
+                Some((rec, mat))
+            }
             BinaryTree::Node(left, right) => {
                 if self.aabb.hit(r, t_min, t_max) {
-                    let left_rec = match left.hit(r, t_min, t_max) {
-                        Some((lr, lm)) => Some((lr, lm)),
-                        None => None,
-                    };
-
-                    let right_rec = match right.hit(r, t_min, t_max) {
-                        Some((rr, rm)) => Some((rr, rm)),
-                        None => None,
-                    };
+                    let left_rec = left.hit(r, t_min, t_max);
+                    let right_rec = right.hit(r, t_min, t_max);
 
                     match (left_rec, right_rec) {
                         (Some((lr, lm)), Some((rr, rm))) => {
@@ -157,7 +162,7 @@ impl Hittable for BvhTree {
             }
         }
     }
-    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<Aabb> {
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
         Some(self.aabb)
     }
 }
@@ -1,65 +1,61 @@
-use rand::Rng;
+use std::sync::Arc;
 
 use super::super::aabb::Aabb;
+use super::super::float::Float;
 use super::super::hittable::{HitRecord, Hittable};
 use super::super::material::Material;
+use super::super::materials::isotropic::Isotropic;
+use super::super::medium::free_flight;
 use super::super::ray::Ray;
 use super::super::texture::Texture;
-use super::super::vector3::Vector3;
 
 pub struct ConstantMedium {
-    boundary: Box<Hittable>,
-    density: f32,
-    phase_function: Material,
+    boundary: Box<dyn Hittable>,
+    density: Float,
+    phase_function: Isotropic,
 }
 
 impl ConstantMedium {
-    pub fn new(boundary: Box<Hittable>, density: f32, texture: Texture) -> Self {
+    pub fn new(boundary: Box<dyn Hittable>, density: Float, texture: Arc<dyn Texture>) -> Self {
         ConstantMedium {
             boundary,
             density,
-            phase_function: Material::Isotropic { texture },
+            phase_function: Isotropic { texture },
         }
     }
 }
 
 impl Hittable for ConstantMedium {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord, &Material)> {
-        let mut rng = rand::thread_rng();
-        match self.boundary.hit(r, -std::f32::MAX, std::f32::MAX) {
-            Some((mut rec1, _mat1)) => match self.boundary.hit(r, rec1.t + 0.0001, std::f32::MAX) {
-                Some((mut rec2, _mat2)) => {
-                    if rec1.t < t_min {
-                        rec1.t = t_min
-                    }
-                    if rec2.t > t_max {
-                        rec2.t = t_max
-                    }
-                    if rec1.t >= rec2.t {
-                        return None;
-                    }
-                    if rec1.t < 0.0 {
-                        rec1.t = 0.0;
-                    }
-                    let distance_inside_boundary: f32 = (rec2.t - rec1.t) * r.direction().length();
-                    let hit_distance: f32 = -(1.0 / self.density) * rng.gen::<f32>().ln();
-                    if hit_distance < distance_inside_boundary {
-                        let t: f32 = rec1.t + hit_distance / r.direction().length();
-                        let p = r.point_at_parameter(t);
-                        let normal = Vector3::new(1.0, 0.0, 0.0); // random
-                        return Some((
-                            HitRecord::new(0.0, 0.0, t, p, normal),
-                            &self.phase_function,
-                        ));
-                    }
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<(HitRecord, &dyn Material)> {
+        if let Some((mut rec1, _mat1)) = self.boundary.hit(r, -Float::MAX, Float::MAX) {
+            // continue from just past the entry point to find the exit
+            let inner = rec1.spawn_ray(r.direction(), r.time);
+            if let Some((mut rec2, _mat2)) = self.boundary.hit(&inner, 0.0, Float::MAX) {
+                rec2.t += rec1.t;
+                if rec1.t < t_min {
+                    rec1.t = t_min
                 }
-                None => {}
-            },
-            None => {}
+                if rec2.t > t_max {
+                    rec2.t = t_max
+                }
+                if rec1.t >= rec2.t {
+                    return None;
+                }
+                if rec1.t < 0.0 {
+                    rec1.t = 0.0;
+                }
+                let distance_inside_boundary: Float = (rec2.t - rec1.t) * r.direction().length();
+                let hit_distance: Float = free_flight(self.density);
+                if hit_distance < distance_inside_boundary {
+                    let t: Float = rec1.t + hit_distance / r.direction().length();
+                    let p = r.point_at_parameter(t);
+                    return Some((HitRecord::in_medium(t, p, r), &self.phase_function));
+                }
+            }
         }
         None
     }
-    fn bounding_box(&self, t0: f32, t1: f32) -> Option<Aabb> {
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
         self.boundary.bounding_box(t0, t1)
     }
 }
@@ -1,3 +1,4 @@
+pub mod bvh4;
 pub mod bvh_tree;
 pub mod constant_medium;
 pub mod cube;
@@ -1,61 +1,80 @@
-use std::f32::consts::PI;
+use std::sync::Arc;
+
+use super::super::float::consts::PI;
+use super::super::float::Float;
 
 use super::super::aabb::{surrounding_box, Aabb};
 use super::super::hittable::{HitRecord, Hittable};
 use super::super::material::Material;
 use super::super::ray::Ray;
+use super::super::utils::gamma;
 use super::super::vector3::{dot, Vector3};
+use super::sphere::get_sphere_tangents;
 
 pub struct MovingSphere {
     pub center0: Vector3,
     pub center1: Vector3,
-    pub time0: f32,
-    pub time1: f32,
-    pub radius: f32,
-    pub material: Material,
+    pub time0: Float,
+    pub time1: Float,
+    pub radius: Float,
+    pub material: Arc<dyn Material>,
 }
 
 impl MovingSphere {
-    pub fn center_at_time(&self, time: f32) -> Vector3 {
+    pub fn center_at_time(&self, time: Float) -> Vector3 {
         ((time - self.time0) / (self.time1 - self.time0)) * (self.center1 - self.center0)
             + self.center0
     }
-    pub fn get_sphere_uv(&self, p: &Vector3) -> (f32, f32) {
-        let phi: f32 = p.z().atan2(p.x());
-        let theta: f32 = p.y().asin();
+    pub fn get_sphere_uv(&self, p: &Vector3) -> (Float, Float) {
+        let phi: Float = p.z().atan2(p.x());
+        let theta: Float = p.y().asin();
         (1.0 - (phi + PI) / (2.0 * PI), (theta + PI / 2.0) / PI)
     }
 }
 
 impl Hittable for MovingSphere {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord, &Material)> {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<(HitRecord, &dyn Material)> {
         let center = self.center_at_time(r.time);
         let oc: Vector3 = r.origin() - center;
-        let a: f32 = dot(r.direction(), r.direction());
-        let b: f32 = dot(oc, r.direction());
-        let c: f32 = dot(oc, oc) - self.radius * self.radius;
-        let discriminant: f32 = b * b - a * c;
+        let a: Float = dot(r.direction(), r.direction());
+        let b: Float = dot(oc, r.direction());
+        let c: Float = dot(oc, oc) - self.radius * self.radius;
+        let discriminant: Float = b * b - a * c;
         if discriminant > 0.0 {
-            let mut temp: f32 = (-b - (b * b - a * c).sqrt()) / a;
-            if temp < t_max && temp > t_min {
-                let t = temp;
-                let p = r.point_at_parameter(t);
-                let normal = (p - center) / self.radius;
-                let (u, v) = self.get_sphere_uv(&((p - center) / self.radius));
-                return Some((HitRecord::new(u, v, t, p, normal), &self.material));
+            // numerically stable form so the root closest to zero keeps its sign
+            let q: Float = -(b + b.signum() * discriminant.sqrt());
+            let mut roots = [q / a, c / q];
+            if roots[0] > roots[1] {
+                roots.swap(0, 1);
             }
-            temp = (-b + (b * b - a * c).sqrt()) / a;
-            if temp < t_max && temp > t_min {
-                let t = temp;
-                let p = r.point_at_parameter(t);
-                let normal = (p - center) / self.radius;
-                let (u, v) = self.get_sphere_uv(&((p - center) / self.radius));
-                return Some((HitRecord::new(u, v, t, p, normal), &self.material));
+            for temp in &roots {
+                if *temp < t_max && *temp > t_min {
+                    let t = *temp;
+                    // project the point back onto the surface to tighten its error
+                    let mut local = r.point_at_parameter(t) - center;
+                    local *= self.radius / local.length();
+                    let p = local + center;
+                    let normal = local / self.radius;
+                    let (u, v) = self.get_sphere_uv(&normal);
+                    let (dpdu, dpdv) = get_sphere_tangents(&normal, self.radius);
+                    let mut rec = HitRecord::new(u, v, t, p, normal, dpdu, dpdv);
+                    rec.p_error = gamma(5) * local.abs() + gamma(3) * p.abs();
+                    rec.p_object = local;
+                    rec.dndu = dpdu / self.radius;
+                    rec.dndv = dpdv / self.radius;
+                    rec.set_face_normal(r);
+                    rec.set_footprint(r);
+                    // look further along the ray through holes in the surface
+                    if !self.material.is_opaque(r, &rec) {
+                        continue;
+                    }
+                    return Some((rec, &*self.material));
+                }
             }
         }
         None
     }
-    fn bounding_box(&self, t0: f32, t1: f32) -> Option<Aabb> {
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
         Some(surrounding_box(
             &Aabb::new(
                 self.center0 - Vector3::new(self.radius, self.radius, self.radius),
@@ -1,46 +1,62 @@
 use rand::Rng;
 
 use super::super::aabb::{surrounding_box, Aabb};
+use super::super::float::Float;
 use super::super::hittable::{HitRecord, Hittable};
 use super::super::material::Material;
 use super::super::ray::Ray;
 use super::super::vector3::Vector3;
 
 pub struct ObjectList {
-    pub list: Vec<Box<Hittable>>,
+    pub list: Vec<Box<dyn Hittable>>,
 }
 
 impl ObjectList {
-    pub fn new(list: Vec<Box<Hittable>>) -> Self {
+    pub fn new(list: Vec<Box<dyn Hittable>>) -> Self {
         ObjectList { list }
     }
 }
 
 impl Hittable for ObjectList {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord, &Material)> {
-        let mut closest_so_far: f32 = t_max;
-        let mut hit_objects: Vec<(HitRecord, &Material)> = vec![];
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<(HitRecord, &dyn Material)> {
+        let mut closest_so_far: Float = t_max;
+        let mut closest_hit: Option<(HitRecord, &dyn Material)> = None;
 
-        for element in self.list.iter() {
-            match element.hit(r, t_min, closest_so_far) {
-                Some((rec, mat)) => {
-                    closest_so_far = rec.t;
-                    hit_objects.push((rec, mat))
-                }
-                None => {}
+        for (i, element) in self.list.iter().enumerate() {
+            if let Some((mut rec, mat)) = element.hit(r, t_min, closest_so_far) {
+                closest_so_far = rec.t;
+                // nested lists run first, so the outermost list wins
+                // and the id ends up being the index in the world list
+                rec.object_id = i;
+                closest_hit = Some((rec, mat));
             }
         }
 
-        if !hit_objects.is_empty() {
-            match hit_objects.pop() {
-                Some(entry) => Some(entry),
-                None => None,
+        closest_hit
+    }
+    fn hit_packet(
+        &self,
+        rays: &[Ray],
+        t_min: Float,
+        t_max: &mut [Float],
+    ) -> Vec<Option<(HitRecord, &dyn Material)>> {
+        let mut closest_hits: Vec<Option<(HitRecord, &dyn Material)>> =
+            rays.iter().map(|_| None).collect();
+
+        for (i, element) in self.list.iter().enumerate() {
+            // t_max is lowered by every hit, so anything returned is closer
+            let hits = element.hit_packet(rays, t_min, t_max);
+            for (closest_hit, hit) in closest_hits.iter_mut().zip(hits) {
+                if let Some((mut rec, mat)) = hit {
+                    rec.object_id = i;
+                    *closest_hit = Some((rec, mat));
+                }
             }
-        } else {
-            None
         }
+
+        closest_hits
     }
-    fn bounding_box(&self, t0: f32, t1: f32) -> Option<Aabb> {
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
         if self.list.is_empty() {
             return None;
         }
@@ -64,9 +80,9 @@ impl Hittable for ObjectList {
 
         Some(hit_bbox)
     }
-    fn pdf_value(&self, o: Vector3, v: Vector3) -> f32 {
-        let weight: f32 = 1.0 / self.list.len() as f32;
-        let mut sum: f32 = 0.0;
+    fn pdf_value(&self, o: Vector3, v: Vector3) -> Float {
+        let weight: Float = 1.0 / self.list.len() as Float;
+        let mut sum: Float = 0.0;
         for object in &self.list {
             sum += weight * object.pdf_value(o, v);
         }
@@ -74,7 +90,7 @@ impl Hittable for ObjectList {
     }
     fn random(&self, o: Vector3) -> Vector3 {
         let mut rng = rand::thread_rng();
-        let i: usize = (rng.gen::<f32>() * self.list.len() as f32) as usize;
+        let i: usize = (rng.gen::<Float>() * self.list.len() as Float) as usize;
         self.list[i].random(o)
     }
 }
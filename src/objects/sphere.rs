@@ -1,74 +1,175 @@
-use std::f32::consts::PI;
+use std::sync::Arc;
+
+use super::super::float::consts::PI;
+use super::super::float::Float;
 
 use super::super::aabb::Aabb;
 use super::super::hittable::{HitRecord, Hittable};
 use super::super::material::Material;
+use super::super::onb::ONB;
 use super::super::ray::Ray;
+use super::super::utils::{gamma, random_on_unit_sphere, random_to_sphere};
 use super::super::vector3::{dot, Vector3};
-use super::super::onb::ONB;
-use super::super::random_to_sphere;
 
 pub struct Sphere {
     pub center: Vector3,
-    pub radius: f32,
-    pub material: Material,
+    pub radius: Float,
+    pub material: Arc<dyn Material>,
+}
+
+// derivatives of the point on the sphere along u and v,
+// p is the hit point on the unit sphere centered at the origin
+pub fn get_sphere_tangents(p: &Vector3, radius: Float) -> (Vector3, Vector3) {
+    let dpdu = 2.0 * PI * radius * Vector3::new(p.z(), 0.0, -p.x());
+    let cos_theta = (p.x() * p.x() + p.z() * p.z()).sqrt();
+    if cos_theta < 1e-6 {
+        // at the poles the v direction is undefined so pick any tangent
+        let uvw = ONB::new(*p);
+        return (uvw.u(), uvw.v());
+    }
+    let dpdv = PI
+        * radius
+        * Vector3::new(
+            -p.y() * p.x() / cos_theta,
+            cos_theta,
+            -p.y() * p.z() / cos_theta,
+        );
+    (dpdu, dpdv)
 }
 
 impl Sphere {
-    pub fn get_sphere_uv(&self, p: &Vector3) -> (f32, f32) {
-        let phi: f32 = p.z().atan2(p.x());
-        let theta: f32 = p.y().asin();
+    pub fn get_sphere_uv(&self, p: &Vector3) -> (Float, Float) {
+        let phi: Float = p.z().atan2(p.x());
+        let theta: Float = p.y().asin();
         (1.0 - (phi + PI) / (2.0 * PI), (theta + PI / 2.0) / PI)
     }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord, &Material)> {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<(HitRecord, &dyn Material)> {
         let oc: Vector3 = r.origin() - self.center;
-        let a: f32 = dot(r.direction(), r.direction());
-        let b: f32 = dot(oc, r.direction());
-        let c: f32 = dot(oc, oc) - self.radius * self.radius;
-        let discriminant: f32 = b * b - a * c;
+        let a: Float = dot(r.direction(), r.direction());
+        let b: Float = dot(oc, r.direction());
+        let c: Float = dot(oc, oc) - self.radius * self.radius;
+        let discriminant: Float = b * b - a * c;
         if discriminant > 0.0 {
-            let mut temp: f32 = (-b - (b * b - a * c).sqrt()) / a;
-            if temp < t_max && temp > t_min {
-                let t = temp;
-                let p = r.point_at_parameter(t);
-                let normal = (p - self.center) / self.radius;
-                let (u, v) = self.get_sphere_uv(&((p - self.center) / self.radius));
-                return Some((HitRecord::new(u, v, t, p, normal), &self.material));
+            // numerically stable form so the root closest to zero keeps its sign
+            let q: Float = -(b + b.signum() * discriminant.sqrt());
+            let mut roots = [q / a, c / q];
+            if roots[0] > roots[1] {
+                roots.swap(0, 1);
             }
-            temp = (-b + (b * b - a * c).sqrt()) / a;
-            if temp < t_max && temp > t_min {
-                let t = temp;
-                let p = r.point_at_parameter(t);
-                let normal = (p - self.center) / self.radius;
-                let (u, v) = self.get_sphere_uv(&((p - self.center) / self.radius));
-                return Some((HitRecord::new(u, v, t, p, normal), &self.material));
+            for temp in &roots {
+                if *temp < t_max && *temp > t_min {
+                    let t = *temp;
+                    // project the point back onto the surface to tighten its error
+                    let mut local = r.point_at_parameter(t) - self.center;
+                    local *= self.radius / local.length();
+                    let p = local + self.center;
+                    let normal = local / self.radius;
+                    let (u, v) = self.get_sphere_uv(&normal);
+                    let (dpdu, dpdv) = get_sphere_tangents(&normal, self.radius);
+                    let mut rec = HitRecord::new(u, v, t, p, normal, dpdu, dpdv);
+                    rec.p_error = gamma(5) * local.abs() + gamma(3) * p.abs();
+                    rec.p_object = local;
+                    rec.dndu = dpdu / self.radius;
+                    rec.dndv = dpdv / self.radius;
+                    rec.set_face_normal(r);
+                    rec.set_footprint(r);
+                    // look further along the ray through holes in the surface
+                    if !self.material.is_opaque(r, &rec) {
+                        continue;
+                    }
+                    return Some((rec, &*self.material));
+                }
             }
         }
         None
     }
-    fn bounding_box(&self, t0: f32, t1: f32) -> Option<Aabb> {
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
         Some(Aabb::new(
             self.center - Vector3::new(self.radius, self.radius, self.radius),
             self.center + Vector3::new(self.radius, self.radius, self.radius),
         ))
     }
-    fn pdf_value(&self, o: Vector3, v: Vector3) -> f32 {
-        match self.hit(&Ray::new(o, v, 0.0), 0.001, std::f32::MAX) {
-            Some((rec, material)) => {
-                let cos_theta_max: f32 = (1.0 - self.radius.powf(2.0) / (self.center - o).squared_length()).sqrt();
-                let solid_angle: f32 = 2.0 * PI * (1.0 - cos_theta_max);
+    fn pdf_value(&self, o: Vector3, v: Vector3) -> Float {
+        // from inside every direction hits the sphere, see `random`
+        if (self.center - o).squared_length() <= self.radius * self.radius {
+            return 1.0 / (4.0 * PI);
+        }
+        match self.hit(&Ray::new(o, v, 0.0), 0.0, Float::MAX) {
+            Some(_) => {
+                let cos_theta_max: Float =
+                    (1.0 - self.radius.powf(2.0) / (self.center - o).squared_length()).sqrt();
+                let solid_angle: Float = 2.0 * PI * (1.0 - cos_theta_max);
                 1.0 / solid_angle
-            },
-            None => 0.0
+            }
+            None => 0.0,
         }
     }
     fn random(&self, o: Vector3) -> Vector3 {
         let direction = self.center - o;
-        let distance_squared: f32 = direction.squared_length();
+        let distance_squared: Float = direction.squared_length();
+        // the cone around the sphere only exists from outside of it
+        if distance_squared <= self.radius * self.radius {
+            return random_on_unit_sphere();
+        }
         let uvw: ONB = ONB::new(direction);
         uvw.local_vec(&random_to_sphere(self.radius, distance_squared))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::materials::lambertian::Lambertian;
+    use super::super::super::texture::solid;
+    use super::super::super::vector3::{unit_vector, Color};
+    use super::*;
+
+    fn sphere(center: Vector3, radius: Float) -> Sphere {
+        Sphere {
+            center,
+            radius,
+            material: Arc::new(Lambertian {
+                albedo: solid(Color::new(0.5, 0.5, 0.5)),
+            }),
+        }
+    }
+
+    // the ground of `random_scene` seen from far above and to the side
+    #[test]
+    fn hit_from_far_away() {
+        let ground = sphere(Vector3::new(0.0, -1000.0, 0.0), 1000.0);
+        let origin = Vector3::new(5e4, 3e4, -2e4);
+        let target = Vector3::new(4.0, 0.0, 1.0);
+        let r = Ray::new(origin, unit_vector(target - origin), 0.0);
+        let (rec, _) = ground
+            .hit(&r, 0.0, Float::MAX)
+            .expect("ray aims at the sphere");
+        assert!(rec.front_face);
+        // the hit point is on the surface up to its error bounds
+        let distance = (rec.p - ground.center).length() - ground.radius;
+        assert!(distance.abs() <= rec.p_error.length() + gamma(3) * ground.radius);
+        assert!((rec.p - target).length() < 1e-2 * (origin - target).length());
+        // rays leaving it on either side don't hit it again right away
+        let back = rec.spawn_ray(-r.direction(), 0.0);
+        assert!(ground.hit(&back, 0.0, Float::MAX).is_none());
+        let through = rec.spawn_ray(r.direction(), 0.0);
+        let (far, _) = ground.hit(&through, 0.0, Float::MAX).expect("far side");
+        // the chord is 2 r cos(theta)
+        let chord = 2.0 * ground.radius * dot(-r.direction(), rec.normal);
+        assert!((far.t - chord).abs() < 1e-3 * chord);
+        assert!(!far.front_face);
+    }
+
+    #[test]
+    fn miss_from_far_away() {
+        let ball = sphere(Vector3::new(0.0, 1.0, 0.0), 1.0);
+        let r = Ray::new(
+            Vector3::new(0.0, 1.0, 1e5),
+            Vector3::new(0.0, 2.1e-5, -1.0),
+            0.0,
+        );
+        assert!(ball.hit(&r, 0.0, Float::MAX).is_none());
+    }
+}
@@ -1,4 +1,4 @@
-use super::vector3::{cross, unit_vector, Vector3};
+use super::vector3::{cross, dot, unit_vector, Vector3};
 
 pub struct ONB {
     axis: [Vector3; 3],
@@ -7,16 +7,26 @@ pub struct ONB {
 impl ONB {
     pub fn new(n: Vector3) -> Self {
         let w: Vector3 = unit_vector(n);
-        let a: Vector3;
-        if w.x().abs() > 0.9 {
-            a = Vector3::new(0.0, 1.0, 0.0);
+        let a: Vector3 = if w.x().abs() > 0.9 {
+            Vector3::new(0.0, 1.0, 0.0)
         } else {
-            a = Vector3::new(1.0, 0.0, 0.0);
-        }
+            Vector3::new(1.0, 0.0, 0.0)
+        };
         let v = unit_vector(cross(w, a));
         let u = cross(w, v);
         ONB { axis: [u, v, w] }
     }
+    // frame around n with u following the tangent t, for anisotropic materials
+    pub fn from_tangent(n: Vector3, t: Vector3) -> Self {
+        let w: Vector3 = unit_vector(n);
+        let t: Vector3 = t - dot(t, w) * w;
+        if t.squared_length() < 1.0e-12 {
+            return ONB::new(n);
+        }
+        let u = unit_vector(t);
+        let v = cross(w, u);
+        ONB { axis: [u, v, w] }
+    }
     pub fn u(&self) -> Vector3 {
         self.axis[0]
     }
@@ -26,10 +36,11 @@ impl ONB {
     pub fn w(&self) -> Vector3 {
         self.axis[2]
     }
-    pub fn local(&self, a: f32, b: f32, c: f32) -> Vector3 {
-        a * self.u() + b * self.v() + c * self.w()
-    }
     pub fn local_vec(&self, a: &Vector3) -> Vector3 {
         a.x() * self.u() + a.y() * self.v() + a.z() * self.w()
     }
+    // inverse of local_vec, world direction to frame coordinates
+    pub fn to_local(&self, a: &Vector3) -> Vector3 {
+        Vector3::new(dot(*a, self.u()), dot(*a, self.v()), dot(*a, self.w()))
+    }
 }
@@ -1,13 +1,17 @@
-use std::f32::consts;
+use super::float::consts;
+use super::float::Float;
 use rand::Rng;
+use std::sync::Arc;
 
 use super::hittable::Hittable;
+use super::material::fresnel_dielectric;
+use super::microfacet::{refract, Gtr1, TrowbridgeReitz};
 use super::onb::ONB;
-use super::random_cosine_direction;
+use super::utils::{random_cosine_direction, random_on_unit_sphere};
 use super::vector3::{dot, unit_vector, Vector3};
 
 pub trait PDF {
-    fn value(&self, direction: Vector3) -> f32;
+    fn value(&self, direction: Vector3) -> Float;
     fn generate(&self) -> Vector3;
 }
 
@@ -22,8 +26,8 @@ impl CosinePDF {
 }
 
 impl PDF for CosinePDF {
-    fn value(&self, direction: Vector3) -> f32 {
-        let cosine: f32 = dot(unit_vector(direction), self.uvw.w());
+    fn value(&self, direction: Vector3) -> Float {
+        let cosine: Float = dot(unit_vector(direction), self.uvw.w());
         if cosine > 0.0 {
             cosine / consts::PI
         } else {
@@ -35,13 +39,266 @@ impl PDF for CosinePDF {
     }
 }
 
-pub struct HittablePDF {
+// every direction above the surface equally, for lobes
+// that can't be sampled in closed form
+pub struct UniformPDF {
+    uvw: ONB,
+}
+
+impl UniformPDF {
+    pub fn new(w: Vector3) -> Self {
+        UniformPDF { uvw: ONB::new(w) }
+    }
+}
+
+impl PDF for UniformPDF {
+    fn value(&self, direction: Vector3) -> Float {
+        if dot(direction, self.uvw.w()) > 0.0 {
+            1.0 / (2.0 * consts::PI)
+        } else {
+            0.0
+        }
+    }
+    fn generate(&self) -> Vector3 {
+        let direction = random_on_unit_sphere();
+        if dot(direction, self.uvw.w()) < 0.0 {
+            -direction
+        } else {
+            direction
+        }
+    }
+}
+
+// directions reflected about GGX microfacet normals that are visible
+// from the outgoing direction
+pub struct GgxPDF {
+    uvw: ONB,
+    // direction towards where the ray came from, in the local frame
+    wo: Vector3,
+    distribution: TrowbridgeReitz,
+}
+
+impl GgxPDF {
+    pub fn new(uvw: ONB, wo: Vector3, distribution: TrowbridgeReitz) -> Self {
+        GgxPDF {
+            uvw,
+            wo,
+            distribution,
+        }
+    }
+}
+
+impl PDF for GgxPDF {
+    fn value(&self, direction: Vector3) -> Float {
+        let wi = self.uvw.to_local(&unit_vector(direction));
+        if wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let h = unit_vector(self.wo + wi);
+        // jacobian of the reflection about h
+        self.distribution.visible_pdf(self.wo, h) / (4.0 * dot(self.wo, h))
+    }
+    fn generate(&self) -> Vector3 {
+        let mut rng = rand::thread_rng();
+        let h = self
+            .distribution
+            .sample_visible(self.wo, rng.gen::<Float>(), rng.gen::<Float>());
+        let wi = 2.0 * dot(self.wo, h) * h - self.wo;
+        self.uvw.local_vec(&wi)
+    }
+}
+
+// GGX reflection or refraction through a rough interface (Walter et al. 2007),
+// picking between the two by the fresnel reflectance of the sampled microfacet
+pub struct RoughDielectricPDF {
+    uvw: ONB,
+    wo: Vector3,
+    // index on the far side of the surface over the one on the ray side
+    eta: Float,
+    distribution: TrowbridgeReitz,
+}
+
+impl RoughDielectricPDF {
+    pub fn new(uvw: ONB, wo: Vector3, eta: Float, distribution: TrowbridgeReitz) -> Self {
+        RoughDielectricPDF {
+            uvw,
+            wo,
+            eta,
+            distribution,
+        }
+    }
+}
+
+impl PDF for RoughDielectricPDF {
+    // the density of everything `generate` can give, reflections can end up
+    // below the surface and refractions above it, so both lobes are tried
+    // on either side instead of picking one by the side of the direction
+    fn value(&self, direction: Vector3) -> Float {
+        let wi = self.uvw.to_local(&unit_vector(direction));
+        // the sampled microfacets face up and towards wo
+        let visible = |h: Vector3| {
+            let cosine = dot(self.wo, h);
+            if h.z() <= 0.0 || cosine <= 0.0 {
+                return None;
+            }
+            Some((cosine, self.distribution.visible_pdf(self.wo, h)))
+        };
+
+        let mut pdf = 0.0;
+        let reflected = self.wo + wi;
+        if reflected.squared_length() > 0.0 {
+            let h = unit_vector(reflected);
+            if let Some((cosine, pdf_h)) = visible(h) {
+                let reflectance = fresnel_dielectric(cosine, self.eta);
+                pdf += pdf_h * reflectance / (4.0 * cosine);
+            }
+        }
+        let refracted = self.wo + self.eta * wi;
+        if refracted.squared_length() > 0.0 {
+            let mut h = unit_vector(refracted);
+            if dot(self.wo, h) < 0.0 {
+                h = -h;
+            }
+            if let Some((cosine, pdf_h)) = visible(h) {
+                // refraction goes through the microfacet
+                if dot(wi, h) < 0.0 {
+                    let reflectance = fresnel_dielectric(cosine, self.eta);
+                    let denom = dot(wi, h) + cosine / self.eta;
+                    pdf += pdf_h * (1.0 - reflectance) * dot(wi, h).abs() / (denom * denom);
+                }
+            }
+        }
+        pdf
+    }
+    fn generate(&self) -> Vector3 {
+        let mut rng = rand::thread_rng();
+        let h = self
+            .distribution
+            .sample_visible(self.wo, rng.gen::<Float>(), rng.gen::<Float>());
+        let reflectance = fresnel_dielectric(dot(self.wo, h), self.eta);
+        let wi = match refract(self.wo, h, self.eta) {
+            Some(refracted) if rng.gen::<Float>() >= reflectance => refracted,
+            _ => 2.0 * dot(self.wo, h) * h - self.wo,
+        };
+        self.uvw.local_vec(&wi)
+    }
+}
+
+// reflection about GTR1 normals, sampled by d(h) * cos(h)
+pub struct Gtr1PDF {
+    uvw: ONB,
+    wo: Vector3,
+    distribution: Gtr1,
+}
+
+impl Gtr1PDF {
+    pub fn new(uvw: ONB, wo: Vector3, distribution: Gtr1) -> Self {
+        Gtr1PDF {
+            uvw,
+            wo,
+            distribution,
+        }
+    }
+}
+
+impl PDF for Gtr1PDF {
+    fn value(&self, direction: Vector3) -> Float {
+        let wi = self.uvw.to_local(&unit_vector(direction));
+        if wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let h = unit_vector(self.wo + wi);
+        self.distribution.d(h) * h.z() / (4.0 * dot(self.wo, h))
+    }
+    fn generate(&self) -> Vector3 {
+        let mut rng = rand::thread_rng();
+        let h = self
+            .distribution
+            .sample(rng.gen::<Float>(), rng.gen::<Float>());
+        let wi = 2.0 * dot(self.wo, h) * h - self.wo;
+        self.uvw.local_vec(&wi)
+    }
+}
+
+// probabilities of half vectors in bands of their angle to the normal
+pub struct HalfAngleTable {
+    // cosines at the band boundaries, from the normal down to the horizon
+    pub edges: Vec<Float>,
+    // cumulative probability of the bands, from 0 to 1
+    pub cdf: Vec<Float>,
+}
+
+impl HalfAngleTable {
+    // the band a cosine falls in
+    fn band(&self, cosine: Float) -> usize {
+        let bands = self.edges.len() - 1;
+        self.edges[1..]
+            .iter()
+            .position(|&edge| cosine >= edge)
+            .unwrap_or(bands - 1)
+    }
+
+    // density of a half vector, uniform over the solid angle of its band
+    fn value(&self, cosine: Float) -> Float {
+        let i = self.band(cosine);
+        let solid_angle = 2.0 * consts::PI * (self.edges[i] - self.edges[i + 1]);
+        // bands this close to the normal can round to nothing
+        if solid_angle <= 0.0 {
+            return 0.0;
+        }
+        (self.cdf[i + 1] - self.cdf[i]) / solid_angle
+    }
+}
+
+// reflection about half vectors drawn from a table, for measured materials
+pub struct TabulatedPDF {
+    uvw: ONB,
+    wo: Vector3,
+    table: Arc<HalfAngleTable>,
+}
+
+impl TabulatedPDF {
+    pub fn new(uvw: ONB, wo: Vector3, table: Arc<HalfAngleTable>) -> Self {
+        TabulatedPDF { uvw, wo, table }
+    }
+}
+
+impl PDF for TabulatedPDF {
+    fn value(&self, direction: Vector3) -> Float {
+        let wi = self.uvw.to_local(&unit_vector(direction));
+        if wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let h = unit_vector(self.wo + wi);
+        self.table.value(h.z()) / (4.0 * dot(self.wo, h))
+    }
+    fn generate(&self) -> Vector3 {
+        let mut rng = rand::thread_rng();
+        let pick = rng.gen::<Float>();
+        let bands = self.table.edges.len() - 1;
+        let i = self.table.cdf[1..]
+            .iter()
+            .position(|&cdf| pick < cdf)
+            .unwrap_or(bands - 1);
+        let (top, bottom) = (self.table.edges[i], self.table.edges[i + 1]);
+        let cos_theta = bottom + (top - bottom) * rng.gen::<Float>();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * consts::PI * rng.gen::<Float>();
+        let h = Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        let wi = 2.0 * dot(self.wo, h) * h - self.wo;
+        self.uvw.local_vec(&wi)
+    }
+}
+
+// directions towards the hittable as seen from o, the lights of a
+// scene are built once and borrowed by every bounce
+pub struct HittablePDF<'a> {
     pub o: Vector3,
-    pub hittable: Box<Hittable>,
+    pub hittable: &'a dyn Hittable,
 }
 
-impl PDF for HittablePDF {
-    fn value(&self, direction: Vector3) -> f32 {
+impl PDF for HittablePDF<'_> {
+    fn value(&self, direction: Vector3) -> Float {
         self.hittable.pdf_value(self.o, direction)
     }
     fn generate(&self) -> Vector3 {
@@ -49,28 +306,240 @@ impl PDF for HittablePDF {
     }
 }
 
-pub struct MixturePDF {
-    pdf: [Box<PDF>; 2],
+pub struct MixturePDF<'a> {
+    pdf: [Box<dyn PDF + 'a>; 2],
 }
 
-impl MixturePDF {
-    pub fn new(p0: Box<PDF>, p1: Box<PDF>) -> Self {
-        MixturePDF {
-            pdf: [p0, p1]
-        }
+impl<'a> MixturePDF<'a> {
+    pub fn new(p0: Box<dyn PDF + 'a>, p1: Box<dyn PDF + 'a>) -> Self {
+        MixturePDF { pdf: [p0, p1] }
     }
 }
 
-impl PDF for MixturePDF {
-    fn value(&self, direction: Vector3) -> f32 {
+impl PDF for MixturePDF<'_> {
+    fn value(&self, direction: Vector3) -> Float {
         0.5 * self.pdf[0].value(direction) + 0.5 * self.pdf[1].value(direction)
     }
     fn generate(&self) -> Vector3 {
         let mut rng = rand::thread_rng();
-        if rng.gen::<f32>() < 0.5 {
+        if rng.gen::<Float>() < 0.5 {
             self.pdf[0].generate()
         } else {
             self.pdf[1].generate()
         }
     }
 }
+
+// picks one of several pdfs with a probability proportional to its weight,
+// used to sample materials made of multiple lobes
+pub struct WeightedPDF {
+    pdfs: Vec<(Float, Box<dyn PDF>)>,
+}
+
+impl WeightedPDF {
+    pub fn new(pdfs: Vec<(Float, Box<dyn PDF>)>) -> Self {
+        let total: Float = pdfs.iter().map(|(weight, _)| weight).sum();
+        WeightedPDF {
+            pdfs: pdfs
+                .into_iter()
+                .filter(|(weight, _)| *weight > 0.0)
+                .map(|(weight, pdf)| (weight / total, pdf))
+                .collect(),
+        }
+    }
+}
+
+impl PDF for WeightedPDF {
+    fn value(&self, direction: Vector3) -> Float {
+        self.pdfs
+            .iter()
+            .map(|(weight, pdf)| weight * pdf.value(direction))
+            .sum()
+    }
+    fn generate(&self) -> Vector3 {
+        let mut rng = rand::thread_rng();
+        let mut pick = rng.gen::<Float>();
+        for (weight, pdf) in &self.pdfs {
+            if pick < *weight {
+                return pdf.generate();
+            }
+            pick -= weight;
+        }
+        // rounding can leave the pick just past the last weight
+        self.pdfs[self.pdfs.len() - 1].1.generate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the sphere is cut into bins of equal solid angle, evenly spaced in
+    // the cosine to the z axis and in the azimuth
+    const Z_BINS: usize = 8;
+    const PHI_BINS: usize = 8;
+    // cells each bin is split into along both axes to integrate the pdfs
+    const CELLS: usize = 24;
+    const SAMPLES: usize = 250_000;
+
+    fn bin(direction: Vector3) -> usize {
+        let d = unit_vector(direction);
+        let z = ((d.z() + 1.0) / 2.0 * Z_BINS as Float) as usize;
+        let phi = (d.y().atan2(d.x()) + consts::PI) / (2.0 * consts::PI);
+        let phi = (phi * PHI_BINS as Float) as usize;
+        z.min(Z_BINS - 1) * PHI_BINS + phi.min(PHI_BINS - 1)
+    }
+
+    // the integral of the pdf over each bin
+    fn expected(pdf: &dyn PDF) -> Vec<Float> {
+        let (zs, phis) = (Z_BINS * CELLS, PHI_BINS * CELLS);
+        let cell = 4.0 * consts::PI / (zs * phis) as Float;
+        let mut bins = vec![0.0; Z_BINS * PHI_BINS];
+        for i in 0..zs {
+            let z = -1.0 + 2.0 * (i as Float + 0.5) / zs as Float;
+            let r = (1.0 - z * z).sqrt();
+            for j in 0..phis {
+                let phi = -consts::PI + 2.0 * consts::PI * (j as Float + 0.5) / phis as Float;
+                let direction = Vector3::new(r * phi.cos(), r * phi.sin(), z);
+                bins[(i / CELLS) * PHI_BINS + j / CELLS] += pdf.value(direction) * cell;
+            }
+        }
+        bins
+    }
+
+    // the fraction of the generated directions that fall in each bin
+    fn observed(pdf: &dyn PDF) -> Vec<Float> {
+        let mut bins = vec![0.0; Z_BINS * PHI_BINS];
+        for _ in 0..SAMPLES {
+            bins[bin(pdf.generate())] += 1.0 / SAMPLES as Float;
+        }
+        bins
+    }
+
+    // `value` integrates to the fraction of the samples it describes, the
+    // others are lost below the horizon, and the histogram of `generate`
+    // follows it everywhere above. Returns the integral
+    fn assert_generate_matches_value(pdf: &dyn PDF, above: impl Fn(usize) -> bool) -> Float {
+        let expected = expected(pdf);
+        let observed = observed(pdf);
+        let total: Float = expected.iter().sum();
+        assert!(total <= 1.01, "integrates to {}", total);
+        let mut kept = 0.0;
+        for (i, (e, o)) in expected.iter().zip(&observed).enumerate() {
+            if above(i / PHI_BINS) {
+                kept += o;
+                assert!(
+                    (e - o).abs() < 0.003 + 0.05 * e,
+                    "bin {}: expected {} observed {}",
+                    i,
+                    e,
+                    o
+                );
+            }
+        }
+        assert!(
+            (kept - total).abs() < 0.01,
+            "{} kept, {} integral",
+            kept,
+            total
+        );
+        total
+    }
+
+    fn upper(z_bin: usize) -> bool {
+        z_bin >= Z_BINS / 2
+    }
+
+    fn frame() -> ONB {
+        ONB::new(Vector3::new(0.0, 0.0, 1.0))
+    }
+
+    fn direction(cos_theta: Float, phi: Float) -> Vector3 {
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+
+    // the visible normals as a pdf of their own
+    struct VisibleNormals {
+        wo: Vector3,
+        distribution: TrowbridgeReitz,
+    }
+
+    impl PDF for VisibleNormals {
+        fn value(&self, h: Vector3) -> Float {
+            self.distribution.visible_pdf(self.wo, unit_vector(h))
+        }
+        fn generate(&self) -> Vector3 {
+            let mut rng = rand::thread_rng();
+            self.distribution
+                .sample_visible(self.wo, rng.gen::<Float>(), rng.gen::<Float>())
+        }
+    }
+
+    #[test]
+    fn visible_normals_anisotropic() {
+        for &(alpha_x, alpha_y) in &[(0.5, 0.5), (0.15, 0.6), (0.8, 0.25)] {
+            let pdf = VisibleNormals {
+                wo: direction(0.5, 0.7),
+                distribution: TrowbridgeReitz { alpha_x, alpha_y },
+            };
+            let total = assert_generate_matches_value(&pdf, upper);
+            assert!((total - 1.0).abs() < 0.01, "integrates to {}", total);
+        }
+    }
+
+    #[test]
+    fn ggx_reflection() {
+        for &(alpha_x, alpha_y) in &[(0.4, 0.4), (0.2, 0.7)] {
+            let pdf = GgxPDF::new(
+                frame(),
+                direction(0.6, -1.2),
+                TrowbridgeReitz { alpha_x, alpha_y },
+            );
+            let total = assert_generate_matches_value(&pdf, upper);
+            // some of the reflections go below the horizon
+            assert!(total > 0.8 && total < 1.0, "integrates to {}", total);
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_reflection_and_refraction() {
+        let distribution = TrowbridgeReitz::from_roughness(0.5, 0.5);
+        // entering glass and leaving it, where some of it is reflected inside
+        for &eta in &[1.5, 1.0 / 1.5] {
+            let pdf = RoughDielectricPDF::new(frame(), direction(0.8, 0.3), eta, distribution);
+            let total = assert_generate_matches_value(&pdf, |_| true);
+            assert!(total > 0.95, "integrates to {}", total);
+        }
+    }
+
+    #[test]
+    fn gtr1_reflection() {
+        let pdf = Gtr1PDF::new(frame(), direction(0.7, 2.0), Gtr1 { alpha: 0.3 });
+        let total = assert_generate_matches_value(&pdf, upper);
+        // the long tail loses a lot below the horizon
+        assert!(total > 0.6 && total < 0.8, "integrates to {}", total);
+    }
+
+    #[test]
+    fn tabulated_reflection() {
+        let table = HalfAngleTable {
+            edges: vec![1.0, 0.95, 0.8, 0.5, 0.0],
+            cdf: vec![0.0, 0.4, 0.7, 0.9, 1.0],
+        };
+        let pdf = TabulatedPDF::new(frame(), direction(0.9, 0.0), Arc::new(table));
+        let total = assert_generate_matches_value(&pdf, upper);
+        // the widest band reaches the horizon
+        assert!(total > 0.65 && total < 0.85, "integrates to {}", total);
+    }
+
+    #[test]
+    fn mixture_of_cosine_and_uniform() {
+        let pdf = MixturePDF::new(
+            Box::new(CosinePDF::new(Vector3::new(0.0, 0.0, 1.0))),
+            Box::new(UniformPDF::new(Vector3::new(1.0, 0.0, 0.0))),
+        );
+        let total = assert_generate_matches_value(&pdf, |_| true);
+        assert!((total - 1.0).abs() < 0.01, "integrates to {}", total);
+    }
+}
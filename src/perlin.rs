@@ -1,17 +1,19 @@
+use super::float::Float;
 use super::vector3::{dot, unit_vector, Vector3};
 use rand::Rng;
 
-fn perlin_interp(c: [[[Vector3; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f32 {
+#[allow(clippy::needless_range_loop)]
+fn perlin_interp(c: [[[Vector3; 2]; 2]; 2], u: Float, v: Float, w: Float) -> Float {
     let uu = u * u * (3.0 - 2.0 * u);
     let vv = v * v * (3.0 - 2.0 * v);
     let ww = w * w * (3.0 - 2.0 * w);
-    let mut accum: f32 = 0.0;
+    let mut accum: Float = 0.0;
     for i in 0..2 {
         for j in 0..2 {
             for k in 0..2 {
-                let i_f32: f32 = i as f32;
-                let j_f32: f32 = j as f32;
-                let k_f32: f32 = k as f32;
+                let i_f32: Float = i as Float;
+                let j_f32: Float = j as Float;
+                let k_f32: Float = k as Float;
                 let weight_v: Vector3 = Vector3::new(u - i_f32, v - j_f32, w - k_f32);
                 accum += (i_f32 * uu + (1.0 - i_f32) * (1.0 - uu))
                     * (j_f32 * vv + (1.0 - j_f32) * (1.0 - vv))
@@ -26,11 +28,11 @@ fn perlin_interp(c: [[[Vector3; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f32 {
 fn perlin_generate() -> [Vector3; 256] {
     let mut p = [Vector3::new(0.0, 0.0, 0.0); 256];
     let mut rng = rand::thread_rng();
-    for i in 0..256 {
-        p[i] = unit_vector(Vector3::new(
-            -1.0 + 2.0 * rng.gen::<f32>(),
-            -1.0 + 2.0 * rng.gen::<f32>(),
-            -1.0 + 2.0 * rng.gen::<f32>(),
+    for item in p.iter_mut() {
+        *item = unit_vector(Vector3::new(
+            -1.0 + 2.0 * rng.gen::<Float>(),
+            -1.0 + 2.0 * rng.gen::<Float>(),
+            -1.0 + 2.0 * rng.gen::<Float>(),
         ));
     }
     p
@@ -39,17 +41,15 @@ fn perlin_generate() -> [Vector3; 256] {
 fn permute(p: &mut [usize; 256]) {
     let mut rng = rand::thread_rng();
     for i in (0..256).rev() {
-        let target: usize = (rng.gen::<f32>() * (i + 1) as f32) as usize;
-        let tmp: usize = p[i];
-        p[i] = p[target];
-        p[target] = tmp;
+        let target: usize = (rng.gen::<Float>() * (i + 1) as Float) as usize;
+        p.swap(i, target);
     }
 }
 
 fn perlin_generate_perm() -> [usize; 256] {
     let mut p: [usize; 256] = [0; 256];
-    for i in 0..256 {
-        p[i] = i;
+    for (i, item) in p.iter_mut().enumerate() {
+        *item = i;
     }
     permute(&mut p);
     p
@@ -73,14 +73,11 @@ impl Perlin {
         }
     }
 
-    pub fn turb(&self, p: &Vector3, depth: Option<usize>) -> f32 {
-        let mut accum: f32 = 0.0;
+    pub fn turb(&self, p: &Vector3, depth: Option<usize>) -> Float {
+        let mut accum: Float = 0.0;
         let mut temp_p: Vector3 = Vector3::new(p.x(), p.y(), p.z());
-        let mut weight: f32 = 1.0;
-        let depth = match depth {
-            Some(v) => v,
-            None => 7,
-        };
+        let mut weight: Float = 1.0;
+        let depth = depth.unwrap_or(7);
         for _ in 0..depth {
             accum += weight * self.noise(&temp_p);
             weight *= 0.5;
@@ -89,10 +86,11 @@ impl Perlin {
         accum.abs()
     }
 
-    pub fn noise(&self, p: &Vector3) -> f32 {
-        let u: f32 = p.x() - p.x().floor();
-        let v: f32 = p.y() - p.y().floor();
-        let w: f32 = p.z() - p.z().floor();
+    #[allow(clippy::needless_range_loop)]
+    pub fn noise(&self, p: &Vector3) -> Float {
+        let u: Float = p.x() - p.x().floor();
+        let v: Float = p.y() - p.y().floor();
+        let w: Float = p.z() - p.z().floor();
         let i: isize = p.x().floor() as isize;
         let j: isize = p.y().floor() as isize;
         let k: isize = p.z().floor() as isize;
@@ -109,3 +107,9 @@ impl Perlin {
         perlin_interp(c, u, v, w)
     }
 }
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Perlin::new()
+    }
+}
@@ -0,0 +1,223 @@
+use super::color::{self, ColorSpace};
+use super::material::luminance;
+use super::utils::clamp;
+use super::vector3::Color;
+
+// the gray the average of the image lands on with auto exposure
+const MIDDLE_GRAY: f32 = 0.18;
+
+// how radiance from the film is squeezed into what a display can show
+#[derive(Copy, Clone)]
+pub enum ToneMapper {
+    // nothing above 1 survives, bright lights and highlights clip
+    Clamp,
+    // L / (1 + L) of the luminance, nothing clips but whites turn gray
+    Reinhard,
+    // Reinhard reaching 1 at the white luminance, the brightest
+    // pixel of the image when there is none
+    ExtendedReinhard { white: Option<f32> },
+    // the filmic curve of John Hable for Uncharted 2
+    Hable,
+    // the fit of the ACES reference and output transforms by Stephen Hill
+    Aces,
+}
+
+// the exposure and tone mapping that turn the film into an image
+pub struct PostProcess {
+    // in stops, every one doubles the brightness
+    pub exposure: f32,
+    // also exposes so the log average luminance becomes middle gray,
+    // `exposure` then compensates on top of that
+    pub auto_exposure: bool,
+    pub tone_mapper: ToneMapper,
+}
+
+fn hable(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+// rows of the matrices are the input channels mixed into one output one
+const ACES_INPUT: [[f32; 3]; 3] = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+];
+
+const ACES_OUTPUT: [[f32; 3]; 3] = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+];
+
+fn mat(m: &[[f32; 3]; 3], c: Color) -> Color {
+    let row = |r: &[f32; 3]| r[0] * c.r() + r[1] * c.g() + r[2] * c.b();
+    Color::new(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+fn aces(c: Color) -> Color {
+    let fit = |v: f32| {
+        (v * (v + 0.024_578_6) - 0.000_090_537) / (v * (0.983_729 * v + 0.432_951) + 0.238_081)
+    };
+    let c = mat(&ACES_INPUT, c);
+    mat(&ACES_OUTPUT, Color::new(fit(c.r()), fit(c.g()), fit(c.b())))
+}
+
+impl ToneMapper {
+    // the linear Rec.709 color squeezed into [0, 1], `brightest` is the
+    // largest luminance in the image
+    fn map(self, c: Color, brightest: f32) -> Color {
+        // the Reinhard curves scale the luminance so hues stay the same
+        let scale_luminance = |curve: &dyn Fn(f32) -> f32| {
+            let l = luminance(c);
+            if l > 0.0 {
+                c * (curve(l) / l)
+            } else {
+                c
+            }
+        };
+        let c = match self {
+            ToneMapper::Clamp => c,
+            ToneMapper::Reinhard => scale_luminance(&|l| l / (1.0 + l)),
+            ToneMapper::ExtendedReinhard { white } => {
+                let white = white.unwrap_or(brightest).max(1e-4);
+                scale_luminance(&|l| l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            ToneMapper::Hable => {
+                // the exposure bias and white point of the original
+                let white = hable(11.2);
+                let curve = |v: f32| hable(2.0 * v) / white;
+                Color::new(curve(c.r()), curve(c.g()), curve(c.b()))
+            }
+            ToneMapper::Aces => aces(c),
+        };
+        Color::new(
+            clamp(c.r(), 0.0, 1.0),
+            clamp(c.g(), 0.0, 1.0),
+            clamp(c.b(), 0.0, 1.0),
+        )
+    }
+}
+
+impl PostProcess {
+    // the film in linear Rec.709 scaled by the exposure
+    fn exposed(&self, film: &[Color], space: ColorSpace) -> Vec<Color> {
+        let display: Vec<Color> = film.iter().map(|&c| space.convert_to_rec709(c)).collect();
+        let mut stops = self.exposure;
+        if self.auto_exposure {
+            // the log average ignores the few very bright pixels of lights
+            let delta = 1e-4;
+            let log_sum: f32 = display
+                .iter()
+                .map(|&c| (delta + luminance(c).max(0.0)).ln())
+                .sum();
+            let average = (log_sum / display.len().max(1) as f32).exp();
+            stops += (MIDDLE_GRAY / average).log2();
+        }
+        let scale = 2.0_f32.powf(stops);
+        display.into_iter().map(|c| c * scale).collect()
+    }
+
+    // the film in the working space as 8-bit sRGB, three bytes a pixel
+    pub fn develop(&self, film: &[Color], space: ColorSpace) -> Vec<u8> {
+        let exposed = self.exposed(film, space);
+        let brightest = exposed.iter().map(|&c| luminance(c)).fold(0.0, f32::max);
+        let mut buffer = Vec::with_capacity(3 * exposed.len());
+        for c in exposed {
+            let c = self.tone_mapper.map(c, brightest);
+            for channel in &[c.r(), c.g(), c.b()] {
+                buffer.push((255.99 * color::linear_to_srgb(*channel)) as u8);
+            }
+        }
+        buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPPERS: [ToneMapper; 5] = [
+        ToneMapper::Clamp,
+        ToneMapper::Reinhard,
+        ToneMapper::ExtendedReinhard { white: Some(4.0) },
+        ToneMapper::Hable,
+        ToneMapper::Aces,
+    ];
+
+    fn gray(v: f32) -> Color {
+        Color::new(v, v, v)
+    }
+
+    fn in_range(c: Color) -> bool {
+        (0..3).all(|i| (0.0..=1.0).contains(&c[i]))
+    }
+
+    #[test]
+    fn curves_start_at_black_and_only_go_up() {
+        for mapper in &MAPPERS {
+            assert!(mapper.map(gray(0.0), 10.0).length() < 1e-6);
+            let mut last = 0.0;
+            for i in 1..=400 {
+                let mapped = mapper.map(gray(i as f32 * 0.05), 20.0);
+                for c in 0..3 {
+                    assert!(mapped[c] >= last - 1e-6);
+                }
+                last = mapped.r();
+            }
+        }
+    }
+
+    #[test]
+    fn extended_reinhard_reaches_one_at_white() {
+        let given = ToneMapper::ExtendedReinhard { white: Some(4.0) };
+        assert!((luminance(given.map(gray(4.0), 100.0)) - 1.0).abs() < 1e-5);
+        // without one the brightest pixel is white
+        let brightest = ToneMapper::ExtendedReinhard { white: None };
+        assert!((luminance(brightest.map(gray(7.5), 7.5)) - 1.0).abs() < 1e-5);
+        assert!(luminance(brightest.map(gray(3.0), 7.5)) < 1.0);
+    }
+
+    #[test]
+    fn filmic_curves_stay_in_range() {
+        for mapper in &[ToneMapper::Hable, ToneMapper::Aces] {
+            for &c in &[
+                gray(1e-3),
+                gray(1.0),
+                gray(1e4),
+                Color::new(50.0, 0.0, 0.0),
+                Color::new(0.0, 0.2, 30.0),
+            ] {
+                assert!(in_range(mapper.map(c, 1.0)));
+            }
+            // bright values come close to white without going over
+            assert!(mapper.map(gray(1e4), 1.0).r() > 0.95);
+        }
+    }
+
+    #[test]
+    fn auto_exposure_lands_on_middle_gray() {
+        for &v in &[0.01, 0.5, 40.0] {
+            let post = PostProcess {
+                exposure: 0.0,
+                auto_exposure: true,
+                tone_mapper: ToneMapper::Clamp,
+            };
+            let exposed = post.exposed(&[gray(v); 16], ColorSpace::Rec709);
+            for c in exposed {
+                assert!(
+                    (luminance(c) - MIDDLE_GRAY).abs() < 2e-3,
+                    "{}",
+                    luminance(c)
+                );
+            }
+            // a stop more is twice as bright
+            let brighter = PostProcess {
+                exposure: 1.0,
+                ..post
+            };
+            let exposed = brighter.exposed(&[gray(v); 4], ColorSpace::Rec709);
+            assert!((luminance(exposed[0]) - 2.0 * MIDDLE_GRAY).abs() < 4e-3);
+        }
+    }
+}
@@ -1,14 +1,44 @@
+use super::float::Float;
+use super::spectrum::Wavelengths;
 use super::vector3::Vector3;
 
+// rays through the neighbouring pixels in x and y, they tell how much of
+// the scene a pixel covers where the ray hits
+#[derive(Copy, Clone)]
+pub struct Differentials {
+    pub rx_origin: Vector3,
+    pub rx_direction: Vector3,
+    pub ry_origin: Vector3,
+    pub ry_direction: Vector3,
+}
+
 pub struct Ray {
     pub a: Vector3,
     pub b: Vector3,
-    pub time: f32,
+    pub time: Float,
+    // what the colors of the path mean in spectral mode
+    pub wavelengths: Option<Wavelengths>,
+    // only kept by camera rays and their specular bounces
+    pub differentials: Option<Differentials>,
 }
 
 impl Ray {
-    pub fn new(a: Vector3, b: Vector3, time: f32) -> Ray {
-        Ray { a, b, time }
+    pub fn new(a: Vector3, b: Vector3, time: Float) -> Ray {
+        Ray {
+            a,
+            b,
+            time,
+            wavelengths: None,
+            differentials: None,
+        }
+    }
+    pub fn with_wavelengths(mut self, wavelengths: Option<Wavelengths>) -> Ray {
+        self.wavelengths = wavelengths;
+        self
+    }
+    pub fn with_differentials(mut self, differentials: Option<Differentials>) -> Ray {
+        self.differentials = differentials;
+        self
     }
     pub fn origin(&self) -> Vector3 {
         Vector3::new(self.a.e[0], self.a.e[1], self.a.e[2])
@@ -16,7 +46,7 @@ impl Ray {
     pub fn direction(&self) -> Vector3 {
         Vector3::new(self.b.e[0], self.b.e[1], self.b.e[2])
     }
-    pub fn point_at_parameter(&self, t: f32) -> Vector3 {
+    pub fn point_at_parameter(&self, t: Float) -> Vector3 {
         self.a + t * self.b
     }
 }
@@ -0,0 +1,284 @@
+use super::float::{to_f32, Float};
+use super::vector3::Color;
+
+// wavelengths in nanometers of the visible range spectra are sampled over
+pub const LAMBDA_MIN: Float = 380.0;
+pub const LAMBDA_MAX: Float = 780.0;
+
+fn gaussian(x: Float, mu: Float, sigma_low: Float, sigma_high: Float) -> Float {
+    let sigma = if x < mu { sigma_low } else { sigma_high };
+    let t = (x - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+// CIE 1931 color matching functions, the multi-lobe fit of Wyman, Sloan and
+// Shirley 2013
+pub fn cie_xyz(lambda: Float) -> (Float, Float, Float) {
+    let x = 1.056 * gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * gaussian(lambda, 501.1, 20.4, 26.2);
+    let y =
+        0.821 * gaussian(lambda, 568.8, 46.9, 40.5) + 0.286 * gaussian(lambda, 530.9, 16.3, 31.1);
+    let z =
+        1.217 * gaussian(lambda, 437.0, 11.8, 36.0) + 0.681 * gaussian(lambda, 459.0, 26.0, 13.8);
+    (x, y, z)
+}
+
+// XYZ to linear sRGB with a D65 white point
+pub fn xyz_to_rgb(x: Float, y: Float, z: Float) -> Color {
+    Color::new(
+        to_f32(3.2406 * x - 1.5372 * y - 0.4986 * z),
+        to_f32(-0.9689 * x + 1.8758 * y + 0.0415 * z),
+        to_f32(0.0557 * x - 0.2040 * y + 1.0570 * z),
+    )
+}
+
+// RGB of a reflectance spectrum sampled evenly over the visible range, scaled
+// so that reflecting everything stays white
+pub fn reflectance_to_rgb(samples: &[Float]) -> Color {
+    let step = (LAMBDA_MAX - LAMBDA_MIN) / (samples.len() - 1) as Float;
+    let mut xyz = (0.0, 0.0, 0.0);
+    let mut white = (0.0, 0.0, 0.0);
+    for (i, sample) in samples.iter().enumerate() {
+        let (x, y, z) = cie_xyz(LAMBDA_MIN + i as Float * step);
+        xyz = (xyz.0 + sample * x, xyz.1 + sample * y, xyz.2 + sample * z);
+        white = (white.0 + x, white.1 + y, white.2 + z);
+    }
+    let rgb = xyz_to_rgb(xyz.0, xyz.1, xyz.2) / xyz_to_rgb(white.0, white.1, white.2);
+    Color::new(rgb.r().max(0.0), rgb.g().max(0.0), rgb.b().max(0.0))
+}
+
+// spectra of the primaries in Smits 1999, in ten bins evenly spaced
+// from 380 to 720nm, mixing them gives smooth reflectances for colors
+const SMITS_MAX: Float = 720.0;
+const SMITS_WHITE: [Float; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [Float; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [Float; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [Float; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [Float; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [Float; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [Float; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+// a Smits spectrum at a wavelength, interpolated between the bin centers
+fn smits(spectrum: &[Float; 10], lambda: Float) -> Float {
+    let width = (SMITS_MAX - LAMBDA_MIN) / 10.0;
+    let x = ((lambda - LAMBDA_MIN) / width - 0.5).clamp(0.0, 9.0);
+    let i = (x as usize).min(8);
+    let t = x - i as Float;
+    spectrum[i] * (1.0 - t) + spectrum[i + 1] * t
+}
+
+// value at a wavelength of a smooth spectrum with the color
+pub fn rgb_to_spectrum(rgb: Color, lambda: Float) -> f32 {
+    let (r, g, b) = (rgb.r() as Float, rgb.g() as Float, rgb.b() as Float);
+    let at = |spectrum: &[Float; 10]| smits(spectrum, lambda);
+    let value = if r <= g && r <= b {
+        r * at(&SMITS_WHITE)
+            + if g <= b {
+                (g - r) * at(&SMITS_CYAN) + (b - g) * at(&SMITS_BLUE)
+            } else {
+                (b - r) * at(&SMITS_CYAN) + (g - b) * at(&SMITS_GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * at(&SMITS_WHITE)
+            + if r <= b {
+                (r - g) * at(&SMITS_MAGENTA) + (b - r) * at(&SMITS_BLUE)
+            } else {
+                (b - g) * at(&SMITS_MAGENTA) + (r - b) * at(&SMITS_RED)
+            }
+    } else {
+        b * at(&SMITS_WHITE)
+            + if r <= g {
+                (r - b) * at(&SMITS_YELLOW) + (g - r) * at(&SMITS_GREEN)
+            } else {
+                (g - b) * at(&SMITS_YELLOW) + (r - g) * at(&SMITS_RED)
+            }
+    };
+    to_f32(value.max(0.0))
+}
+
+// the wavelengths a path carries in spectral mode, one per channel of its
+// colors, the hero wavelength is the first and the others are spread evenly
+// from it over the visible range (Wilkie et al. 2014)
+#[derive(Copy, Clone)]
+pub struct Wavelengths {
+    pub lambda: [Float; 3],
+    // only the hero is left, after something that splits wavelengths apart
+    pub single: bool,
+}
+
+impl Wavelengths {
+    pub fn sample(u: Float) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let at = |offset: Float| LAMBDA_MIN + ((u + offset / 3.0) % 1.0) * range;
+        Wavelengths {
+            lambda: [at(0.0), at(1.0), at(2.0)],
+            single: false,
+        }
+    }
+
+    pub fn hero(&self) -> Float {
+        self.lambda[0]
+    }
+
+    // the weight that keeps the estimate the same when the
+    // others are dropped, which doesn't happen twice
+    pub fn collapse(&mut self) -> Color {
+        if self.single {
+            return Color::new(1.0, 1.0, 1.0);
+        }
+        self.single = true;
+        Color::new(3.0, 0.0, 0.0)
+    }
+
+    // an RGB reflectance or emission at the wavelengths
+    pub fn upsample(&self, rgb: Color) -> Color {
+        Color::new(
+            rgb_to_spectrum(rgb, self.lambda[0]),
+            rgb_to_spectrum(rgb, self.lambda[1]),
+            rgb_to_spectrum(rgb, self.lambda[2]),
+        )
+    }
+
+    // linear RGB of radiance carried at the wavelengths, white is what
+    // `white_response` gives for the constant spectrum
+    pub fn to_rgb(self, radiance: Color, white: Color) -> Color {
+        let mut xyz = (0.0, 0.0, 0.0);
+        for (i, lambda) in self.lambda.iter().enumerate() {
+            let (x, y, z) = cie_xyz(*lambda);
+            let value = radiance[i] as Float;
+            xyz = (xyz.0 + value * x, xyz.1 + value * y, xyz.2 + value * z);
+        }
+        xyz_to_rgb(xyz.0, xyz.1, xyz.2) / white
+    }
+}
+
+// average response of `Wavelengths::to_rgb` to a constant spectrum
+// over all the wavelengths it can be sampled at
+pub fn white_response() -> Color {
+    const STEPS: usize = 400;
+    let mut white = Color::new(0.0, 0.0, 0.0);
+    for i in 0..STEPS {
+        let wavelengths = Wavelengths::sample((i as Float + 0.5) / STEPS as Float);
+        let radiance = Color::new(1.0, 1.0, 1.0);
+        white += wavelengths.to_rgb(radiance, Color::new(1.0, 1.0, 1.0));
+    }
+    white / STEPS as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the stratified average over the wavelengths of the rgb of a
+    // spectrum, like the pixels of a spectral render
+    fn render(spectrum: impl Fn(Float) -> f32, collapse: bool) -> Color {
+        const STEPS: usize = 3000;
+        let white = white_response();
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        for i in 0..STEPS {
+            let mut wavelengths = Wavelengths::sample((i as Float + 0.5) / STEPS as Float);
+            let radiance = Color::new(
+                spectrum(wavelengths.lambda[0]),
+                spectrum(wavelengths.lambda[1]),
+                spectrum(wavelengths.lambda[2]),
+            );
+            let weight = if collapse {
+                wavelengths.collapse()
+            } else {
+                Color::new(1.0, 1.0, 1.0)
+            };
+            sum += wavelengths.to_rgb(weight * radiance, white);
+        }
+        sum / STEPS as f32
+    }
+
+    fn close(a: Color, b: Color, tolerance: f32) -> bool {
+        (0..3).all(|i| (a[i] - b[i]).abs() < tolerance)
+    }
+
+    // the fit has the equal energy white point, so the three functions
+    // cover the same area, and y peaks at 1 around 555nm
+    #[test]
+    fn cie_fit_is_balanced() {
+        let mut area = (0.0, 0.0, 0.0);
+        for lambda in 360..=830 {
+            let (x, y, z) = cie_xyz(lambda as Float);
+            area = (area.0 + x, area.1 + y, area.2 + z);
+        }
+        assert!((area.1 - 106.86).abs() < 2.0, "{}", area.1);
+        assert!((area.0 / area.1 - 1.0).abs() < 0.02);
+        assert!((area.2 / area.1 - 1.0).abs() < 0.02);
+        assert!((cie_xyz(555.0).1 - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn white_round_trips_to_white() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        assert!(close(reflectance_to_rgb(&[1.0; 40]), white, 1e-4));
+        for lambda in (380..780).step_by(10) {
+            assert!((rgb_to_spectrum(white, lambda as Float) - 1.0).abs() < 1e-3);
+        }
+        assert!(close(render(|_| 1.0, false), white, 1e-3));
+        let gray = Color::new(0.4, 0.4, 0.4);
+        let upsampled = render(|lambda| rgb_to_spectrum(gray, lambda), false);
+        assert!(close(upsampled, gray, 5e-3));
+    }
+
+    // the smits spectra only come close for saturated colors
+    #[test]
+    fn colors_round_trip_close() {
+        for &rgb in &[
+            Color::new(0.8, 0.3, 0.1),
+            Color::new(0.1, 0.6, 0.2),
+            Color::new(0.2, 0.3, 0.9),
+        ] {
+            let samples: Vec<Float> = (0..=80)
+                .map(|i| rgb_to_spectrum(rgb, LAMBDA_MIN + 5.0 * i as Float) as Float)
+                .collect();
+            let back = reflectance_to_rgb(&samples);
+            assert!(close(back, rgb, 0.1), "{:?} to {:?}", rgb.e, back.e);
+        }
+    }
+
+    #[test]
+    fn wavelengths_cover_the_range_evenly() {
+        for i in 0..100 {
+            let wavelengths = Wavelengths::sample(i as Float / 100.0);
+            let mut lambda = wavelengths.lambda;
+            lambda.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert!(lambda[0] >= LAMBDA_MIN && lambda[2] < LAMBDA_MAX);
+            let third = (LAMBDA_MAX - LAMBDA_MIN) / 3.0;
+            assert!((lambda[1] - lambda[0] - third).abs() < 1e-3);
+            assert!((lambda[2] - lambda[1] - third).abs() < 1e-3);
+        }
+    }
+
+    // following the hero alone gives the same image on average
+    #[test]
+    fn collapse_is_unbiased() {
+        let spectrum = |lambda: Float| to_f32(1.0 + ((lambda - 380.0) / 60.0).sin());
+        let all = render(spectrum, false);
+        let hero = render(spectrum, true);
+        assert!(close(all, hero, 0.01), "{:?} against {:?}", all.e, hero.e);
+
+        let mut wavelengths = Wavelengths::sample(0.3);
+        assert!(wavelengths.collapse() == Color::new(3.0, 0.0, 0.0));
+        assert!(wavelengths.single);
+        assert!(wavelengths.collapse() == Color::new(1.0, 1.0, 1.0));
+    }
+}
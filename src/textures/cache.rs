@@ -0,0 +1,26 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use super::super::color::Encoding;
+use super::image_texture::MipMap;
+
+type Images = HashMap<(PathBuf, Encoding), Arc<MipMap>>;
+
+static IMAGES: OnceLock<Mutex<Images>> = OnceLock::new();
+
+// the file is only read the first time it is loaded
+pub fn load(path: &str, encoding: Encoding) -> Result<Arc<MipMap>, image::ImageError> {
+    let key = (
+        std::fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path)),
+        encoding,
+    );
+    let images = IMAGES.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(image) = images.lock().unwrap().get(&key) {
+        return Ok(image.clone());
+    }
+    // decoded without holding the lock, when two threads race for the same
+    // file both decode it and the first one to finish is kept
+    let image = Arc::new(MipMap::new(&image::open(path)?, encoding));
+    Ok(images.lock().unwrap().entry(key).or_insert(image).clone())
+}
@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use super::super::float::{to_f32, Float};
+use super::super::hittable::HitRecord;
+use super::super::texture::Texture;
+use super::super::vector3::Color;
+
+// `frequency` checks along each unit of u and v
+pub struct CheckerTexture {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub frequency: Float,
+}
+
+// integral of the function that is 1 on odd checks and 0 on even ones
+fn odd_integral(x: Float) -> Float {
+    let half = x / 2.0;
+    half.floor() + 2.0 * (half - half.floor() - 0.5).max(0.0)
+}
+
+// the share of odd checks along one axis between x - dx and x + dx,
+// at the point itself when that stays inside one check
+fn odd_share(x: Float, dx: Float) -> Float {
+    if (x - dx).floor() == (x + dx).floor() {
+        x.floor().rem_euclid(2.0)
+    } else if dx > 1.0 {
+        // too many checks to tell apart
+        0.5
+    } else {
+        (odd_integral(x + dx) - odd_integral(x - dx)) / (2.0 * dx)
+    }
+}
+
+impl Texture for CheckerTexture {
+    // the share of odd checks in a box around the point as wide as the
+    // footprint, a check is odd where one of the axes is odd but not both
+    fn value(&self, rec: &HitRecord) -> Color {
+        let (s, t) = (rec.u * self.frequency, rec.v * self.frequency);
+        let footprint = &rec.footprint;
+        let ds = footprint.dudx.abs().max(footprint.dudy.abs()) * self.frequency;
+        let dt = footprint.dvdx.abs().max(footprint.dvdy.abs()) * self.frequency;
+        let (s_odd, t_odd) = (odd_share(s, ds), odd_share(t, dt));
+        let odd = to_f32(s_odd + t_odd - 2.0 * s_odd * t_odd);
+        if odd <= 0.0 {
+            self.even.value(rec)
+        } else if odd >= 1.0 {
+            self.odd.value(rec)
+        } else {
+            self.even.value(rec) * (1.0 - odd) + self.odd.value(rec) * odd
+        }
+    }
+}
+
+// checks in space, cubes `1 / frequency` wide
+pub struct SolidCheckerTexture {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub frequency: Float,
+}
+
+impl SolidCheckerTexture {
+    fn pick(&self, rec: &HitRecord) -> &dyn Texture {
+        let p = self.frequency * rec.p;
+        if (p.x().floor() + p.y().floor() + p.z().floor()) as i64 % 2 == 0 {
+            &*self.even
+        } else {
+            &*self.odd
+        }
+    }
+}
+
+impl Texture for SolidCheckerTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        self.pick(rec).value(rec)
+    }
+
+    fn alpha(&self, rec: &HitRecord) -> f32 {
+        self.pick(rec).alpha(rec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::texture::{solid, Footprint};
+    use super::super::super::vector3::Vector3;
+    use super::*;
+
+    fn hit(u: Float, v: Float, footprint: Footprint) -> HitRecord {
+        let p = Vector3::new(u, v, 0.0);
+        let (normal, dpdu) = (Vector3::new(0.0, 0.0, 1.0), Vector3::new(1.0, 0.0, 0.0));
+        let mut rec = HitRecord::new(u, v, 1.0, p, normal, dpdu, Vector3::new(0.0, 1.0, 0.0));
+        rec.footprint = footprint;
+        rec
+    }
+
+    fn footprint(du: Float, dv: Float) -> Footprint {
+        Footprint {
+            dudx: du,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: dv,
+        }
+    }
+
+    fn checker() -> CheckerTexture {
+        CheckerTexture {
+            even: solid(Color::new(0.0, 0.0, 0.0)),
+            odd: solid(Color::new(1.0, 1.0, 1.0)),
+            frequency: 1.0,
+        }
+    }
+
+    #[test]
+    fn points_pick_one_check() {
+        let checker = checker();
+        assert!(checker.value(&hit(0.5, 0.5, footprint(0.0, 0.0))).r() == 0.0);
+        assert!(checker.value(&hit(1.5, 0.5, footprint(0.0, 0.0))).r() == 1.0);
+        assert!(checker.value(&hit(-0.5, 0.5, footprint(0.0, 0.0))).r() == 1.0);
+    }
+
+    // a footprint across an edge in one axis and none in the other
+    // used to divide zero by zero
+    #[test]
+    fn zero_width_axis_is_point_sampled() {
+        let checker = checker();
+        let across_u = checker.value(&hit(1.0, 0.5, footprint(0.25, 0.0))).r();
+        assert!((across_u - 0.5).abs() < 1e-6);
+        let across_v = checker.value(&hit(0.5, 1.0, footprint(0.0, 0.25))).r();
+        assert!((across_v - 0.5).abs() < 1e-6);
+        let inside_v = checker.value(&hit(1.25, 0.5, footprint(0.5, 0.0))).r();
+        assert!((inside_v - 0.75).abs() < 1e-6);
+    }
+
+    #[test]
+    fn wide_footprints_are_gray() {
+        let gray = checker().value(&hit(0.3, 0.7, footprint(3.0, 0.0))).r();
+        assert!((gray - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn solid_checks_follow_the_point() {
+        let solid_checker = SolidCheckerTexture {
+            even: solid(Color::new(0.0, 0.0, 0.0)),
+            odd: solid(Color::new(1.0, 1.0, 1.0)),
+            frequency: 2.0,
+        };
+        assert!(
+            solid_checker
+                .value(&hit(0.25, 0.25, footprint(0.0, 0.0)))
+                .r()
+                == 0.0
+        );
+        assert!(
+            solid_checker
+                .value(&hit(0.75, 0.25, footprint(0.0, 0.0)))
+                .r()
+                == 1.0
+        );
+    }
+}
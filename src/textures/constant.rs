@@ -0,0 +1,17 @@
+use super::super::hittable::HitRecord;
+use super::super::texture::Texture;
+use super::super::vector3::Color;
+
+pub struct ConstantTexture {
+    pub color: Color,
+}
+
+impl Texture for ConstantTexture {
+    fn value(&self, _rec: &HitRecord) -> Color {
+        self.color
+    }
+
+    fn uniform(&self) -> Option<Color> {
+        Some(self.color)
+    }
+}
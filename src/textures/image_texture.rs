@@ -0,0 +1,480 @@
+use std::sync::Arc;
+
+use image::DynamicImage;
+
+use super::super::color::{self, Encoding};
+use super::super::float::{to_f32, Float};
+use super::super::hittable::HitRecord;
+use super::super::texture::{Footprint, Texture};
+use super::super::vector3::Color;
+use super::mapping::Wrap;
+
+// how much longer than wide the ellipse of an EWA lookup can be, longer
+// ones are widened which blurs a bit but bounds the texels they cover
+const MAX_ANISOTROPY: Float = 8.0;
+
+const EWA_ALPHA: Float = 2.0;
+
+#[derive(Copy, Clone)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    // trilinear mip mapping
+    Trilinear,
+    // Heckbert's elliptical weighted average
+    Ewa,
+}
+
+// one mip level in rgba, premultiplied by alpha
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<[f32; 4]>,
+}
+
+impl MipLevel {
+    fn from_image(img: &DynamicImage, encoding: Encoding) -> Self {
+        let img = img.to_rgba();
+        let (width, height) = img.dimensions();
+        let space = color::working_space();
+        let decoded: Vec<f32> = (0..256)
+            .map(|i| match encoding {
+                Encoding::Srgb => color::srgb_to_linear(i as f32 / 255.0),
+                Encoding::Linear => i as f32 / 255.0,
+            })
+            .collect();
+        // alpha is coverage, never encoded
+        let texels = img
+            .pixels()
+            .map(|pixel| {
+                let [r, g, b] = [0, 1, 2].map(|c| decoded[pixel[c] as usize]);
+                let c = match encoding {
+                    Encoding::Srgb => space.convert_from_rec709(Color::new(r, g, b)),
+                    Encoding::Linear => Color::new(r, g, b),
+                };
+                let alpha = pixel[3] as f32 / 255.0;
+                [c.r() * alpha, c.g() * alpha, c.b() * alpha, alpha]
+            })
+            .collect();
+        MipLevel {
+            width: width as usize,
+            height: height as usize,
+            texels,
+        }
+    }
+
+    fn downsample(&self) -> Self {
+        let (columns, rows) = (box_taps(self.width), box_taps(self.height));
+        let (width, height) = (columns.len(), rows.len());
+        let mut texels = Vec::with_capacity(width * height);
+        for row in &rows {
+            for column in &columns {
+                let mut sum = [0.0; 4];
+                for &(y, wy) in row {
+                    for &(x, wx) in column {
+                        let texel = self.texels[y * self.width + x];
+                        for c in 0..4 {
+                            sum[c] += wx * wy * texel[c];
+                        }
+                    }
+                }
+                texels.push(sum);
+            }
+        }
+        MipLevel {
+            width,
+            height,
+            texels,
+        }
+    }
+
+    fn texel(&self, x: isize, y: isize, wrap: Wrap) -> [f32; 4] {
+        let x = wrap.index(x, self.width);
+        let y = wrap.index(y, self.height);
+        self.texels[y * self.width + x]
+    }
+
+    // texel coordinates of the texture coordinates, v goes up the image
+    fn coordinates(&self, u: Float, v: Float) -> (Float, Float) {
+        (u * self.width as Float, (1.0 - v) * self.height as Float)
+    }
+
+    fn nearest(&self, u: Float, v: Float, wrap: Wrap) -> [f32; 4] {
+        let (x, y) = self.coordinates(u, v);
+        self.texel(x.floor() as isize, (y - 0.001).floor() as isize, wrap)
+    }
+
+    fn bilinear(&self, u: Float, v: Float, wrap: Wrap) -> [f32; 4] {
+        let (x, y) = self.coordinates(u, v);
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (to_f32(x - x0), to_f32(y - y0));
+        let (x0, y0) = (x0 as isize, y0 as isize);
+        let mut value = [0.0; 4];
+        for (dx, dy, weight) in &[
+            (0, 0, (1.0 - tx) * (1.0 - ty)),
+            (1, 0, tx * (1.0 - ty)),
+            (0, 1, (1.0 - tx) * ty),
+            (1, 1, tx * ty),
+        ] {
+            let texel = self.texel(x0 + dx, y0 + dy, wrap);
+            for c in 0..4 {
+                value[c] += weight * texel[c];
+            }
+        }
+        value
+    }
+
+    fn ewa(&self, u: Float, v: Float, axes: [(Float, Float); 2], wrap: Wrap) -> [f32; 4] {
+        let (s, t) = self.coordinates(u, v);
+        let (s, t) = (s - 0.5, t - 0.5);
+        let (w, h) = (self.width as Float, self.height as Float);
+        // in texels, flipped in t like the coordinates
+        let [(ds0, dt0), (ds1, dt1)] = [
+            (axes[0].0 * w, -axes[0].1 * h),
+            (axes[1].0 * w, -axes[1].1 * h),
+        ];
+        // the implicit ellipse a s^2 + b s t + c t^2 = 1, grown by a texel
+        // so it always covers some
+        let mut a = dt0 * dt0 + dt1 * dt1 + 1.0;
+        let mut b = -2.0 * (ds0 * dt0 + ds1 * dt1);
+        let mut c = ds0 * ds0 + ds1 * ds1 + 1.0;
+        let inv_f = 1.0 / (a * c - b * b * 0.25);
+        a *= inv_f;
+        b *= inv_f;
+        c *= inv_f;
+        let det = -b * b + 4.0 * a * c;
+        let inv_det = 1.0 / det;
+        let s_extent = 2.0 * inv_det * (det * c).sqrt();
+        let t_extent = 2.0 * inv_det * (det * a).sqrt();
+        let (s0, s1) = (
+            (s - s_extent).ceil() as isize,
+            (s + s_extent).floor() as isize,
+        );
+        let (t0, t1) = (
+            (t - t_extent).ceil() as isize,
+            (t + t_extent).floor() as isize,
+        );
+
+        let mut sum = [0.0; 4];
+        let mut weights = 0.0;
+        for it in t0..=t1 {
+            let tt = it as Float - t;
+            for is in s0..=s1 {
+                let ss = is as Float - s;
+                let r2 = a * ss * ss + b * ss * tt + c * tt * tt;
+                if r2 < 1.0 {
+                    let weight = to_f32((-EWA_ALPHA * r2).exp() - (-EWA_ALPHA).exp());
+                    let texel = self.texel(is, it, wrap);
+                    for c in 0..4 {
+                        sum[c] += weight * texel[c];
+                    }
+                    weights += weight;
+                }
+            }
+        }
+        if weights <= 0.0 {
+            return self.bilinear(u, v, wrap);
+        }
+        sum.map(|c| c / weights)
+    }
+}
+
+// the texels and weights each texel of a row of `n` halved averages, two
+// texels for even rows, for odd ones three overlapping ones with the
+// weights of a box 2 + 1 / half texels wide (Wimmer and Bittner)
+fn box_taps(n: usize) -> Vec<Vec<(usize, f32)>> {
+    if n == 1 {
+        return vec![vec![(0, 1.0)]];
+    }
+    let half = n / 2;
+    (0..half)
+        .map(|x| {
+            if n.is_multiple_of(2) {
+                vec![(2 * x, 0.5), (2 * x + 1, 0.5)]
+            } else {
+                let n = n as f32;
+                vec![
+                    (2 * x, (half - x) as f32 / n),
+                    (2 * x + 1, half as f32 / n),
+                    (2 * x + 2, (x + 1) as f32 / n),
+                ]
+            }
+        })
+        .collect()
+}
+
+pub struct MipMap {
+    levels: Vec<MipLevel>,
+}
+
+impl MipMap {
+    pub fn new(img: &DynamicImage, encoding: Encoding) -> Self {
+        let mut levels = vec![MipLevel::from_image(img, encoding)];
+        loop {
+            let last = &levels[levels.len() - 1];
+            if last.width == 1 && last.height == 1 {
+                break;
+            }
+            let next = last.downsample();
+            levels.push(next);
+        }
+        MipMap { levels }
+    }
+}
+
+pub struct ImageTexture {
+    image: Arc<MipMap>,
+    pub filter: Filter,
+    pub wrap: Wrap,
+}
+
+impl ImageTexture {
+    pub fn new(img: DynamicImage) -> Self {
+        Self::shared(Arc::new(MipMap::new(&img, Encoding::Srgb)))
+    }
+
+    pub fn shared(image: Arc<MipMap>) -> Self {
+        ImageTexture {
+            image,
+            filter: Filter::Trilinear,
+            wrap: Wrap::Clamp,
+        }
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    // the continuous level whose texels are as wide as the width
+    fn level(&self, width: Float) -> Float {
+        let size = self.image.levels[0].width.max(self.image.levels[0].height) as Float;
+        (width * size).max(1e-8).log2().max(0.0)
+    }
+
+    // the lookup in the levels either side of the continuous one, blended
+    fn at_level(&self, level: Float, lookup: impl Fn(&MipLevel) -> [f32; 4]) -> [f32; 4] {
+        let last = self.image.levels.len() - 1;
+        let lower = (level.floor() as usize).min(last);
+        if lower == last {
+            return lookup(&self.image.levels[last]);
+        }
+        let t = to_f32(level - lower as Float);
+        let (a, b) = (
+            lookup(&self.image.levels[lower]),
+            lookup(&self.image.levels[lower + 1]),
+        );
+        [0, 1, 2, 3].map(|c| (1.0 - t) * a[c] + t * b[c])
+    }
+
+    fn lookup(&self, u: Float, v: Float, footprint: &Footprint) -> [f32; 4] {
+        let wrap = self.wrap;
+        match self.filter {
+            Filter::Nearest => self.image.levels[0].nearest(u, v, wrap),
+            Filter::Bilinear => self.image.levels[0].bilinear(u, v, wrap),
+            Filter::Trilinear => {
+                let width = footprint
+                    .dudx
+                    .abs()
+                    .max(footprint.dvdx.abs())
+                    .max(footprint.dudy.abs())
+                    .max(footprint.dvdy.abs());
+                self.at_level(self.level(width), |level| level.bilinear(u, v, wrap))
+            }
+            Filter::Ewa => {
+                let mut major = (footprint.dudx, footprint.dvdx);
+                let mut minor = (footprint.dudy, footprint.dvdy);
+                let length = |(du, dv): (Float, Float)| (du * du + dv * dv).sqrt();
+                if length(minor) > length(major) {
+                    std::mem::swap(&mut major, &mut minor);
+                }
+                let (major_length, mut minor_length) = (length(major), length(minor));
+                if minor_length == 0.0 {
+                    return self.image.levels[0].bilinear(u, v, wrap);
+                }
+                if minor_length * MAX_ANISOTROPY < major_length {
+                    let scale = major_length / (minor_length * MAX_ANISOTROPY);
+                    minor = (minor.0 * scale, minor.1 * scale);
+                    minor_length *= scale;
+                }
+                // the minor axis picks the level so the ellipse
+                // covers a few texels across at most
+                self.at_level(self.level(minor_length), |level| {
+                    level.ewa(u, v, [major, minor], wrap)
+                })
+            }
+        }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        let [r, g, b, alpha] = self.lookup(rec.u, rec.v, &rec.footprint);
+        if alpha > 0.0 {
+            Color::new(r, g, b) / alpha
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    fn alpha(&self, rec: &HitRecord) -> f32 {
+        self.lookup(rec.u, rec.v, &rec.footprint)[3]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::hittable::fixtures::flat;
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn level(width: usize, height: usize, value: impl Fn(usize, usize) -> f32) -> MipLevel {
+        let mut texels = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let v = value(x, y);
+                texels.push([v, v, v, v]);
+            }
+        }
+        MipLevel {
+            width,
+            height,
+            texels,
+        }
+    }
+
+    fn mean(level: &MipLevel) -> f32 {
+        level.texels.iter().map(|t| t[0]).sum::<f32>() / level.texels.len() as f32
+    }
+
+    #[test]
+    fn every_texel_weighs_the_same() {
+        for n in 1..12 {
+            let taps = box_taps(n);
+            assert_eq!(taps.len(), (n / 2).max(1));
+            let mut total = vec![0.0; n];
+            for texel in &taps {
+                let sum: f32 = texel.iter().map(|(_, w)| w).sum();
+                assert!((sum - 1.0).abs() < 1e-6);
+                for &(i, w) in texel {
+                    total[i] += w;
+                }
+            }
+            for weight in total {
+                assert!((weight - taps.len() as f32 / n as f32).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn odd_sizes_keep_the_average() {
+        // a bright last column used to count twice in the last texel
+        let mut level = level(7, 5, |x, y| if x == 6 { 10.0 } else { (x + y) as f32 });
+        let average = mean(&level);
+        while level.width > 1 || level.height > 1 {
+            level = level.downsample();
+            assert!((mean(&level) - average).abs() < 1e-4);
+        }
+    }
+
+    fn hit(u: Float, v: Float, footprint: Footprint) -> HitRecord {
+        let mut rec = flat(u, v);
+        rec.footprint = footprint;
+        rec
+    }
+
+    #[test]
+    fn alpha_is_filtered_over_the_footprint() {
+        // a checkerboard of opaque and clear texels
+        let img = RgbaImage::from_fn(8, 8, |x, y| {
+            Rgba([255, 255, 255, 255 * ((x + y) % 2) as u8])
+        });
+        let texture = ImageTexture::new(DynamicImage::ImageRgba8(img));
+        let center = 1.5 / 8.0;
+        let point = texture.alpha(&hit(center, center, Footprint::default()));
+        assert!(point == 0.0 || point == 1.0);
+        let wide = Footprint {
+            dudx: 1.0,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: 1.0,
+        };
+        let filtered = texture.alpha(&hit(center, center, wide));
+        assert!((filtered - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn clear_texels_leave_the_color_alone() {
+        // red leaves with a clear texel of some other color between them
+        let leaves = |clear: [u8; 3]| {
+            let img = RgbaImage::from_fn(4, 4, |x, y| {
+                if (x, y) == (1, 1) {
+                    Rgba([clear[0], clear[1], clear[2], 0])
+                } else {
+                    Rgba([200, 0, 0, 255])
+                }
+            });
+            ImageTexture::new(DynamicImage::ImageRgba8(img))
+        };
+        let red = Color::new(color::srgb_to_linear(200.0 / 255.0), 0.0, 0.0);
+        let wide = Footprint {
+            dudx: 0.5,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: 0.5,
+        };
+        for filter in &[Filter::Bilinear, Filter::Trilinear, Filter::Ewa] {
+            for clear in &[[0, 0, 0], [255, 255, 255]] {
+                let texture = leaves(*clear).with_filter(*filter);
+                let value = texture.value(&hit(0.5, 0.5, wide));
+                assert!((value - red).length() < 1e-4, "{:?}", value.e);
+            }
+        }
+    }
+
+    // a row of four texels of increasing coverage, alpha is never encoded
+    // so the values come back as they are
+    fn ramp() -> ImageTexture {
+        let img = RgbaImage::from_fn(4, 1, |x, _| Rgba([255, 255, 255, 85 * x as u8]));
+        ImageTexture::new(DynamicImage::ImageRgba8(img))
+    }
+
+    #[test]
+    fn filters_read_between_texels() {
+        let at = |filter: Filter, footprint: Footprint| {
+            ramp().with_filter(filter).alpha(&hit(0.5, 0.5, footprint))
+        };
+        let point = Footprint::default();
+        let nearest = at(Filter::Nearest, point);
+        assert!((nearest - 1.0 / 3.0).abs() < 1e-6 || (nearest - 2.0 / 3.0).abs() < 1e-6);
+        assert!((at(Filter::Bilinear, point) - 0.5).abs() < 1e-6);
+        // without a footprint the pyramid is read at its finest level
+        assert!((at(Filter::Trilinear, point) - 0.5).abs() < 1e-6);
+        assert!((at(Filter::Ewa, point) - 0.5).abs() < 1e-6);
+        let wide = Footprint {
+            dudx: 0.5,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: 0.25,
+        };
+        assert!((at(Filter::Ewa, wide) - 0.5).abs() < 0.05);
+        assert!((at(Filter::Trilinear, wide) - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn wraps_outside_the_image() {
+        let at = |wrap: Wrap| {
+            let texture = ramp().with_filter(Filter::Nearest).with_wrap(wrap);
+            texture.alpha(&hit(-0.375, 0.5, Footprint::default()))
+        };
+        assert!((at(Wrap::Repeat) - 2.0 / 3.0).abs() < 1e-6);
+        assert!((at(Wrap::Mirror) - 1.0 / 3.0).abs() < 1e-6);
+        assert_eq!(at(Wrap::Clamp), 0.0);
+    }
+}
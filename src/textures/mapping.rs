@@ -0,0 +1,303 @@
+use std::sync::Arc;
+
+use super::super::float::consts;
+use super::super::float::{to_f32, Float};
+use super::super::hittable::HitRecord;
+use super::super::texture::{Footprint, Texture};
+use super::super::vector3::{dot, unit_vector, Color, Vector3};
+
+#[derive(Copy, Clone)]
+pub enum Mapping {
+    Uv,
+    // the point along two directions, their lengths scale the texture
+    Planar { s: Vector3, t: Vector3 },
+    // longitude and latitude around the center like on a `Sphere`
+    Spherical { center: Vector3 },
+    // the angle around the vertical axis through the center and the height
+    Cylindrical { center: Vector3 },
+    // a planar mapping along each axis, blended by how much the normal faces
+    // it raised to the sharpness, for surfaces with no usable parameterization
+    Triplanar { sharpness: Float },
+    // solid textures like noise read the point in space, either where it is
+    // in the scene or where it is on the object before it was moved around
+    World,
+    Object,
+}
+
+// scale, then rotation in degrees, then offset
+#[derive(Copy, Clone)]
+pub struct UvTransform {
+    pub scale: (Float, Float),
+    pub rotation: Float,
+    pub offset: (Float, Float),
+}
+
+impl UvTransform {
+    pub const IDENTITY: UvTransform = UvTransform {
+        scale: (1.0, 1.0),
+        rotation: 0.0,
+        offset: (0.0, 0.0),
+    };
+
+    // only the linear part, for the derivatives
+    fn apply_linear(&self, (u, v): (Float, Float)) -> (Float, Float) {
+        let (sin, cos) = (self.rotation * consts::PI / 180.0).sin_cos();
+        let (u, v) = (u * self.scale.0, v * self.scale.1);
+        (cos * u - sin * v, sin * u + cos * v)
+    }
+
+    fn apply(&self, uv: (Float, Float)) -> (Float, Float) {
+        let (u, v) = self.apply_linear(uv);
+        (u + self.offset.0, v + self.offset.1)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum Wrap {
+    Repeat,
+    // repeats flipping every other copy so the edges meet
+    Mirror,
+    Clamp,
+}
+
+impl Wrap {
+    // the texel of an index along a side with the size
+    pub fn index(self, i: isize, size: usize) -> usize {
+        let size = size as isize;
+        let i = match self {
+            Wrap::Repeat => i.rem_euclid(size),
+            Wrap::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
+            Wrap::Clamp => i.clamp(0, size - 1),
+        };
+        i as usize
+    }
+}
+
+pub struct MappedTexture {
+    pub texture: Arc<dyn Texture>,
+    pub mapping: Mapping,
+    pub transform: UvTransform,
+}
+
+// longitude and latitude of the direction from the center
+fn spherical(p: Vector3, center: Vector3) -> (Float, Float) {
+    let d = unit_vector(p - center);
+    let phi = d.z().atan2(d.x());
+    let theta = d.y().clamp(-1.0, 1.0).asin();
+    (
+        1.0 - (phi + consts::PI) / (2.0 * consts::PI),
+        (theta + consts::FRAC_PI_2) / consts::PI,
+    )
+}
+
+fn cylindrical(p: Vector3, center: Vector3) -> (Float, Float) {
+    let d = p - center;
+    let phi = d.z().atan2(d.x());
+    (1.0 - (phi + consts::PI) / (2.0 * consts::PI), d.y())
+}
+
+impl MappedTexture {
+    // the hit with the texture coordinates and their footprint replaced
+    fn mapped(&self, rec: &HitRecord, mapping: Mapping) -> HitRecord {
+        let mut mapped = rec.clone();
+        let (uv, dx, dy) = match mapping {
+            Mapping::Uv => {
+                let footprint = &rec.footprint;
+                (
+                    (rec.u, rec.v),
+                    (footprint.dudx, footprint.dvdx),
+                    (footprint.dudy, footprint.dvdy),
+                )
+            }
+            Mapping::Planar { s, t } => (
+                (dot(s, rec.p), dot(t, rec.p)),
+                (dot(s, rec.dpdx), dot(t, rec.dpdx)),
+                (dot(s, rec.dpdy), dot(t, rec.dpdy)),
+            ),
+            Mapping::Spherical { center } => Self::differenced(rec, |p| spherical(p, center)),
+            Mapping::Cylindrical { center } => Self::differenced(rec, |p| cylindrical(p, center)),
+            Mapping::Triplanar { .. } => unreachable!("triplanar blends planar lookups"),
+            Mapping::World => return mapped,
+            Mapping::Object => {
+                mapped.p = rec.p_object;
+                return mapped;
+            }
+        };
+        let (u, v) = self.transform.apply(uv);
+        let (dudx, dvdx) = self.transform.apply_linear(dx);
+        let (dudy, dvdy) = self.transform.apply_linear(dy);
+        mapped.u = u;
+        mapped.v = v;
+        mapped.footprint = Footprint {
+            dudx,
+            dvdx,
+            dudy,
+            dvdy,
+        };
+        mapped
+    }
+
+    // coordinates at the point and their changes to the neighbouring pixels,
+    // by finite differences across the seam where u wraps around
+    fn differenced(
+        rec: &HitRecord,
+        map: impl Fn(Vector3) -> (Float, Float),
+    ) -> ((Float, Float), (Float, Float), (Float, Float)) {
+        let (u, v) = map(rec.p);
+        let difference = |d: Vector3| {
+            let (du, dv) = map(rec.p + d);
+            let du = du - u;
+            (du - du.round(), dv - v)
+        };
+        ((u, v), difference(rec.dpdx), difference(rec.dpdy))
+    }
+
+    // the weights of the planar lookups along x, y and z and their axes
+    fn triplanar(&self, rec: &HitRecord, sharpness: Float) -> [(f32, HitRecord); 3] {
+        let n = rec.shading_normal.abs();
+        let weights = [
+            n.x().powf(sharpness),
+            n.y().powf(sharpness),
+            n.z().powf(sharpness),
+        ];
+        let total: Float = weights.iter().sum();
+        let axes = [
+            (Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 1.0, 0.0)),
+            (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0)),
+            (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0)),
+        ];
+        [0, 1, 2].map(|i| {
+            let (s, t) = axes[i];
+            (
+                to_f32(weights[i] / total),
+                self.mapped(rec, Mapping::Planar { s, t }),
+            )
+        })
+    }
+}
+
+impl Texture for MappedTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        match self.mapping {
+            Mapping::Triplanar { sharpness } => self
+                .triplanar(rec, sharpness)
+                .iter()
+                .filter(|(weight, _)| *weight > 0.0)
+                .fold(Color::new(0.0, 0.0, 0.0), |sum, (weight, mapped)| {
+                    sum + self.texture.value(mapped) * *weight
+                }),
+            mapping => self.texture.value(&self.mapped(rec, mapping)),
+        }
+    }
+
+    fn alpha(&self, rec: &HitRecord) -> f32 {
+        match self.mapping {
+            Mapping::Triplanar { sharpness } => self
+                .triplanar(rec, sharpness)
+                .iter()
+                .map(|(weight, mapped)| weight * self.texture.alpha(mapped))
+                .sum(),
+            mapping => self.texture.alpha(&self.mapped(rec, mapping)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // shows what the mapping hands to the texture, the coordinates or
+    // for the solid mappings the point
+    struct Coordinates;
+
+    impl Texture for Coordinates {
+        fn value(&self, rec: &HitRecord) -> Color {
+            Color::new(to_f32(rec.u), to_f32(rec.v), to_f32(rec.p.z()))
+        }
+    }
+
+    fn hit(p: Vector3, normal: Vector3) -> HitRecord {
+        let (dpdu, dpdv) = (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        let mut rec = HitRecord::new(0.25, 0.75, 1.0, p, normal, dpdu, dpdv);
+        rec.p_object = p - Vector3::new(0.0, 0.0, 10.0);
+        rec
+    }
+
+    fn mapped(mapping: Mapping, rec: &HitRecord) -> Color {
+        MappedTexture {
+            texture: Arc::new(Coordinates),
+            mapping,
+            transform: UvTransform::IDENTITY,
+        }
+        .value(rec)
+    }
+
+    fn close(a: Color, b: Color) -> bool {
+        (a - b).length() < 1e-5
+    }
+
+    #[test]
+    fn mappings_pick_the_coordinates() {
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        let rec = hit(Vector3::new(2.0, 0.5, 3.0), up);
+        assert!(close(
+            mapped(Mapping::Uv, &rec),
+            Color::new(0.25, 0.75, 3.0)
+        ));
+        let planar = Mapping::Planar {
+            s: Vector3::new(0.5, 0.0, 0.0),
+            t: Vector3::new(0.0, 0.0, 1.0),
+        };
+        assert!(close(mapped(planar, &rec), Color::new(1.0, 3.0, 3.0)));
+        // a quarter of the way around from +x and halfway up
+        let center = Vector3::new(2.0, 0.5, 0.0);
+        let spherical = mapped(Mapping::Spherical { center }, &rec);
+        assert!(close(spherical, Color::new(0.25, 0.5, 3.0)));
+        let cylindrical = mapped(Mapping::Cylindrical { center }, &rec);
+        assert!(close(cylindrical, Color::new(0.25, 0.0, 3.0)));
+        assert!(close(
+            mapped(Mapping::World, &rec),
+            Color::new(0.25, 0.75, 3.0)
+        ));
+        assert!(close(
+            mapped(Mapping::Object, &rec),
+            Color::new(0.25, 0.75, -7.0)
+        ));
+    }
+
+    #[test]
+    fn triplanar_blends_by_the_normal() {
+        let p = Vector3::new(0.1, 0.2, 0.3);
+        // facing up only the projection along y is seen
+        let sharp = Mapping::Triplanar { sharpness: 4.0 };
+        let rec = hit(p, Vector3::new(0.0, 1.0, 0.0));
+        assert!(close(mapped(sharp, &rec), Color::new(0.1, 0.3, 0.3)));
+        // halfway between x and y both count the same
+        let rec = hit(p, unit_vector(Vector3::new(1.0, 1.0, 0.0)));
+        let expected = (Color::new(0.3, 0.2, 0.3) + Color::new(0.1, 0.3, 0.3)) * 0.5;
+        assert!(close(mapped(sharp, &rec), expected));
+    }
+
+    #[test]
+    fn transform_scales_rotates_and_offsets() {
+        let rec = hit(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        let texture = MappedTexture {
+            texture: Arc::new(Coordinates),
+            mapping: Mapping::Uv,
+            transform: UvTransform {
+                scale: (2.0, 4.0),
+                rotation: 90.0,
+                offset: (1.0, 0.0),
+            },
+        };
+        // (0.5, 3.0) turned a quarter to (-3.0, 0.5) and moved along u
+        assert!(close(texture.value(&rec), Color::new(-2.0, 0.5, 0.0)));
+    }
+}
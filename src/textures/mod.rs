@@ -0,0 +1,7 @@
+pub mod cache;
+pub mod checker;
+pub mod constant;
+pub mod image_texture;
+pub mod mapping;
+pub mod nodes;
+pub mod noise;
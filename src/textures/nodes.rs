@@ -0,0 +1,244 @@
+use std::sync::Arc;
+
+use super::super::hittable::HitRecord;
+use super::super::texture::Texture;
+use super::super::vector3::Color;
+
+pub struct ScaleTexture {
+    pub input: Arc<dyn Texture>,
+    pub factor: f32,
+}
+
+impl Texture for ScaleTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        self.input.value(rec) * self.factor
+    }
+
+    fn alpha(&self, rec: &HitRecord) -> f32 {
+        self.input.alpha(rec)
+    }
+}
+
+pub struct AddTexture {
+    pub a: Arc<dyn Texture>,
+    pub b: Arc<dyn Texture>,
+}
+
+impl Texture for AddTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        self.a.value(rec) + self.b.value(rec)
+    }
+
+    // covered where both inputs are, so holes in either stay open
+    fn alpha(&self, rec: &HitRecord) -> f32 {
+        self.a.alpha(rec) * self.b.alpha(rec)
+    }
+}
+
+pub struct MultiplyTexture {
+    pub a: Arc<dyn Texture>,
+    pub b: Arc<dyn Texture>,
+}
+
+impl Texture for MultiplyTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        self.a.value(rec) * self.b.value(rec)
+    }
+
+    fn alpha(&self, rec: &HitRecord) -> f32 {
+        self.a.alpha(rec) * self.b.alpha(rec)
+    }
+}
+
+// a where the red channel of the mask is 0 and b where it is 1
+pub struct MixTexture {
+    pub a: Arc<dyn Texture>,
+    pub b: Arc<dyn Texture>,
+    pub mask: Arc<dyn Texture>,
+}
+
+impl Texture for MixTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        let weight = self.mask.value(rec).r().clamp(0.0, 1.0);
+        self.a.value(rec) * (1.0 - weight) + self.b.value(rec) * weight
+    }
+
+    fn alpha(&self, rec: &HitRecord) -> f32 {
+        let weight = self.mask.value(rec).r().clamp(0.0, 1.0);
+        self.a.alpha(rec) * (1.0 - weight) + self.b.alpha(rec) * weight
+    }
+}
+
+// the red channel of the input looked up in a gradient of colors at
+// increasing positions, two stops remap a range to another one
+pub struct ColorRamp {
+    pub input: Arc<dyn Texture>,
+    stops: Vec<(f32, Color)>,
+}
+
+impl ColorRamp {
+    pub fn new(input: Arc<dyn Texture>, stops: Vec<(f32, Color)>) -> Result<Self, String> {
+        if stops.is_empty() {
+            return Err("a color ramp needs at least one stop".to_string());
+        }
+        Ok(ColorRamp { input, stops })
+    }
+
+    fn ramp(&self, x: f32) -> Color {
+        let first = self.stops[0];
+        if x <= first.0 {
+            return first.1;
+        }
+        for pair in self.stops.windows(2) {
+            let ((x0, c0), (x1, c1)) = (pair[0], pair[1]);
+            if x <= x1 {
+                let t = if x1 > x0 { (x - x0) / (x1 - x0) } else { 1.0 };
+                return c0 * (1.0 - t) + c1 * t;
+            }
+        }
+        self.stops[self.stops.len() - 1].1
+    }
+}
+
+impl Texture for ColorRamp {
+    fn value(&self, rec: &HitRecord) -> Color {
+        self.ramp(self.input.value(rec).r())
+    }
+
+    fn alpha(&self, rec: &HitRecord) -> f32 {
+        self.input.alpha(rec)
+    }
+}
+
+pub struct InvertTexture {
+    pub input: Arc<dyn Texture>,
+}
+
+impl Texture for InvertTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        Color::new(1.0, 1.0, 1.0) - self.input.value(rec)
+    }
+
+    fn alpha(&self, rec: &HitRecord) -> f32 {
+        self.input.alpha(rec)
+    }
+}
+
+// one channel of the input in all three, 3 is the alpha channel
+pub struct ChannelTexture {
+    pub input: Arc<dyn Texture>,
+    pub channel: usize,
+}
+
+impl Texture for ChannelTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        let value = match self.channel {
+            3 => self.input.alpha(rec),
+            channel => self.input.value(rec)[channel],
+        };
+        Color::new(value, value, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::hittable::fixtures::flat;
+    use super::super::super::texture::solid;
+    use super::*;
+
+    // a color that is partly transparent
+    struct Translucent(Color, f32);
+
+    impl Texture for Translucent {
+        fn value(&self, _rec: &HitRecord) -> Color {
+            self.0
+        }
+
+        fn alpha(&self, _rec: &HitRecord) -> f32 {
+            self.1
+        }
+    }
+
+    fn glass() -> Arc<dyn Texture> {
+        Arc::new(Translucent(Color::new(0.25, 0.5, 0.75), 0.5))
+    }
+
+    #[test]
+    fn arithmetic_nodes() {
+        let rec = flat(0.5, 0.5);
+        let scale = ScaleTexture {
+            input: glass(),
+            factor: 2.0,
+        };
+        assert!(scale.value(&rec) == Color::new(0.5, 1.0, 1.5));
+        assert_eq!(scale.alpha(&rec), 0.5);
+        let add = AddTexture {
+            a: glass(),
+            b: solid(Color::new(0.25, 0.25, 0.25)),
+        };
+        assert!(add.value(&rec) == Color::new(0.5, 0.75, 1.0));
+        assert_eq!(add.alpha(&rec), 0.5);
+        let multiply = MultiplyTexture {
+            a: glass(),
+            b: solid(Color::new(2.0, 0.5, 0.0)),
+        };
+        assert!(multiply.value(&rec) == Color::new(0.5, 0.25, 0.0));
+        assert_eq!(multiply.alpha(&rec), 0.5);
+        let both = MultiplyTexture {
+            a: glass(),
+            b: glass(),
+        };
+        assert_eq!(both.alpha(&rec), 0.25);
+        let invert = InvertTexture { input: glass() };
+        assert!(invert.value(&rec) == Color::new(0.75, 0.5, 0.25));
+        assert_eq!(invert.alpha(&rec), 0.5);
+    }
+
+    #[test]
+    fn mix_follows_the_red_of_the_mask() {
+        let rec = flat(0.5, 0.5);
+        let mix = |weight: f32| MixTexture {
+            a: solid(Color::new(1.0, 0.0, 0.0)),
+            b: glass(),
+            mask: solid(Color::new(weight, 1.0 - weight, 0.0)),
+        };
+        assert!(mix(0.0).value(&rec) == Color::new(1.0, 0.0, 0.0));
+        assert!(mix(1.0).value(&rec) == Color::new(0.25, 0.5, 0.75));
+        assert!(mix(0.5).value(&rec) == Color::new(0.625, 0.25, 0.375));
+        assert_eq!(mix(0.5).alpha(&rec), 0.75);
+        // out of range masks are clamped
+        assert!(mix(3.0).value(&rec) == Color::new(0.25, 0.5, 0.75));
+    }
+
+    #[test]
+    fn ramp_interpolates_between_stops() {
+        let rec = flat(0.5, 0.5);
+        let ramp = |x: f32| {
+            ColorRamp::new(
+                solid(Color::new(x, 0.0, 0.0)),
+                vec![
+                    (0.25, Color::new(0.0, 0.0, 0.0)),
+                    (0.75, Color::new(1.0, 0.5, 0.0)),
+                ],
+            )
+            .unwrap()
+            .value(&rec)
+        };
+        assert!(ramp(0.0) == Color::new(0.0, 0.0, 0.0));
+        assert!(ramp(0.5) == Color::new(0.5, 0.25, 0.0));
+        assert!(ramp(1.0) == Color::new(1.0, 0.5, 0.0));
+        assert!(ColorRamp::new(glass(), Vec::new()).is_err());
+    }
+
+    #[test]
+    fn channel_spreads_one_channel() {
+        let rec = flat(0.5, 0.5);
+        let channel = |channel| ChannelTexture {
+            input: glass(),
+            channel,
+        };
+        assert!(channel(0).value(&rec) == Color::new(0.25, 0.25, 0.25));
+        assert!(channel(2).value(&rec) == Color::new(0.75, 0.75, 0.75));
+        assert!(channel(3).value(&rec) == Color::new(0.5, 0.5, 0.5));
+    }
+}
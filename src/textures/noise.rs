@@ -0,0 +1,21 @@
+use super::super::float::{to_f32, Float};
+use super::super::hittable::HitRecord;
+use super::super::perlin::Perlin;
+use super::super::texture::Texture;
+use super::super::vector3::Color;
+
+pub struct NoiseTexture {
+    pub noise: Perlin,
+    pub scale: Float,
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        let p = &rec.p;
+        // Vector3::new(1.0, 1.0, 1.0) * 0.5 * (1.0 + self.noise.turb(&(self.scale * p), None))
+        // Vector3::new(1.0, 1.0, 1.0) * self.noise.turb(&(self.scale * p), None)
+        let intensity: Float = 0.5
+            * (1.0 + (self.scale * p.z() + 10.0 * self.noise.turb(&(self.scale * *p), None)).sin());
+        Color::new(1.0, 1.0, 1.0) * to_f32(intensity)
+    }
+}
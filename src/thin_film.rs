@@ -0,0 +1,225 @@
+use std::ops::{Add, Div, Mul, Sub};
+use std::sync::Arc;
+
+use super::float::consts;
+use super::float::Float;
+use super::hittable::HitRecord;
+use super::material::ComplexIor;
+use super::spectrum::{reflectance_to_rgb, LAMBDA_MAX, LAMBDA_MIN};
+use super::texture::{self, Texture};
+use super::vector3::Color;
+
+// wavelengths the interference is evaluated at
+const SAMPLES: usize = 17;
+
+// a thin transparent coating like a soap film, oil on water or the oxide
+// layer of anodized metal, whose reflections interfere with the ones of
+// the surface under it, the thickness in nanometers is the red channel
+// of the texture times the scale, so a noise can make it vary like in a
+// soap bubble
+#[derive(Clone)]
+pub struct ThinFilm {
+    pub thickness: Arc<dyn Texture>,
+    pub scale: Float,
+    pub ref_idx: Float,
+}
+
+impl ThinFilm {
+    // a film of the same thickness everywhere
+    pub fn uniform(thickness: Float, ref_idx: Float) -> Self {
+        ThinFilm {
+            thickness: texture::constant(1.0),
+            scale: thickness,
+            ref_idx,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Complex {
+    re: Float,
+    im: Float,
+}
+
+impl Complex {
+    fn new(re: Float, im: Float) -> Self {
+        Complex { re, im }
+    }
+
+    fn norm_squared(self) -> Float {
+        self.re * self.re + self.im * self.im
+    }
+
+    // the root with a non negative imaginary part, so waves
+    // that can't propagate decay instead of growing
+    fn sqrt(self) -> Complex {
+        let r = self.norm_squared().sqrt();
+        let re = (0.5 * (r + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (r - self.re)).max(0.0).sqrt();
+        if self.im < 0.0 {
+            Complex::new(-re, im)
+        } else {
+            Complex::new(re, im)
+        }
+    }
+
+    // e^(i * self)
+    fn exp_i(self) -> Complex {
+        let magnitude = (-self.im).exp();
+        Complex::new(magnitude * self.re.cos(), magnitude * self.re.sin())
+    }
+}
+
+impl From<Float> for Complex {
+    fn from(re: Float) -> Self {
+        Complex::new(re, 0.0)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, other: Complex) -> Complex {
+        let denominator = other.norm_squared();
+        Complex::new(
+            (self.re * other.re + self.im * other.im) / denominator,
+            (self.im * other.re - self.re * other.im) / denominator,
+        )
+    }
+}
+
+// cosine of the angle in a medium of index n, from snell's law
+fn cos_in(n_i: Complex, sin2_i: Float, n: Complex) -> Complex {
+    let ratio = n_i / n;
+    Complex::from(1.0) - ratio * ratio * Complex::from(sin2_i)
+}
+
+// fresnel amplitudes for s and p polarized light from medium i into j
+fn amplitudes(n_i: Complex, cos_i: Complex, n_j: Complex, cos_j: Complex) -> (Complex, Complex) {
+    let s = (n_i * cos_i - n_j * cos_j) / (n_i * cos_i + n_j * cos_j);
+    let p = (n_j * cos_i - n_i * cos_j) / (n_j * cos_i + n_i * cos_j);
+    (s, p)
+}
+
+// complex index at a wavelength, interpolated between
+// the channels which are sampled at 650, 550 and 450nm
+fn index_at(ior: &ComplexIor, lambda: Float) -> Complex {
+    let (a, b, t) = if lambda >= 550.0 {
+        (1, 0, ((lambda - 550.0) / 100.0).min(1.0))
+    } else {
+        (1, 2, ((550.0 - lambda) / 100.0).min(1.0))
+    };
+    let lerp = |c: &Color| (1.0 - t) * c[a] as Float + t * c[b] as Float;
+    Complex::new(lerp(&ior.eta), lerp(&ior.k))
+}
+
+impl ThinFilm {
+    // reflectance of the film over a surface of index `substrate` for light
+    // arriving with the cosine from a medium of index `outside` (Airy summation)
+    pub fn reflectance(
+        &self,
+        cosine: Float,
+        outside: Float,
+        substrate: &ComplexIor,
+        rec: &HitRecord,
+    ) -> Color {
+        let thickness = self.scale * self.thickness.value(rec).r().max(0.0) as Float;
+        let mut samples = [0.0; SAMPLES];
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let lambda =
+                LAMBDA_MIN + i as Float * (LAMBDA_MAX - LAMBDA_MIN) / (SAMPLES - 1) as Float;
+            *sample = self.reflectance_at(thickness, cosine, outside, substrate, lambda);
+        }
+        reflectance_to_rgb(&samples)
+    }
+
+    // the same at a single wavelength for a film of the thickness
+    fn reflectance_at(
+        &self,
+        thickness: Float,
+        cosine: Float,
+        outside: Float,
+        substrate: &ComplexIor,
+        lambda: Float,
+    ) -> Float {
+        let cos1 = Complex::from(cosine.clamp(0.0, 1.0));
+        let sin2 = 1.0 - cos1.re * cos1.re;
+        let n1 = Complex::from(outside);
+        let n2 = Complex::from(self.ref_idx);
+        let cos2 = cos_in(n1, sin2, n2).sqrt();
+        let (r12_s, r12_p) = amplitudes(n1, cos1, n2, cos2);
+        let n3 = index_at(substrate, lambda);
+        let cos3 = cos_in(n1, sin2, n3).sqrt();
+        let (r23_s, r23_p) = amplitudes(n2, cos2, n3, cos3);
+        // phase difference of one round trip through the film
+        let phase = (Complex::from(4.0 * consts::PI * thickness / lambda) * n2 * cos2).exp_i();
+        let airy = |r12: Complex, r23: Complex| {
+            let r = (r12 + r23 * phase) / (Complex::from(1.0) + r12 * r23 * phase);
+            r.norm_squared()
+        };
+        (0.5 * (airy(r12_s, r23_s) + airy(r12_p, r23_p))).min(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::hittable::fixtures::flat;
+    use super::super::material::fresnel_dielectric;
+    use super::*;
+
+    #[test]
+    fn no_film_is_bare_fresnel() {
+        let glass = ComplexIor::dielectric(1.5);
+        let film = ThinFilm::uniform(0.0, 1.33);
+        for &cosine in &[1.0, 0.7, 0.3, 0.05] {
+            let expected = fresnel_dielectric(cosine, 1.5);
+            for lambda in &[400.0, 550.0, 700.0] {
+                let reflectance = film.reflectance_at(0.0, cosine, 1.0, &glass, *lambda);
+                assert!((reflectance - expected).abs() < 1e-5);
+            }
+            let rgb = film.reflectance(cosine, 1.0, &glass, &flat(0.0, 0.0));
+            for i in 0..3 {
+                assert!((rgb[i] as Float - expected).abs() < 1e-3);
+            }
+        }
+    }
+
+    // the coating of lenses, the two reflections cancel where the
+    // film is a quarter of the wavelength thick
+    #[test]
+    fn quarter_wave_coating_is_darkest_at_its_wavelength() {
+        let glass = ComplexIor::dielectric(1.5);
+        let film = ThinFilm::uniform(0.0, 1.38);
+        let thickness = 550.0 / (4.0 * 1.38);
+        let at = |lambda: Float| film.reflectance_at(thickness, 1.0, 1.0, &glass, lambda);
+        let expected: Float = (1.5 - 1.38 * 1.38) / (1.5 + 1.38 * 1.38);
+        assert!((at(550.0) - expected * expected).abs() < 1e-5);
+        for lambda in (400..=700).step_by(25) {
+            assert!(at(lambda as Float) >= at(550.0));
+        }
+        assert!(at(550.0) < fresnel_dielectric(1.0, 1.5));
+    }
+}
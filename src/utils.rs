@@ -1,13 +1,14 @@
+use super::float::consts;
+use super::float::Float;
 use rand::Rng;
-use std::f32::consts;
 
-use super::vector3::{unit_vector, Vector3};
+use super::vector3::{unit_vector, Color, Vector3};
 
 pub fn random_on_unit_sphere() -> Vector3 {
     let mut p: Vector3;
     loop {
         let mut rng = rand::thread_rng();
-        p = 2.0 * Vector3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>())
+        p = 2.0 * Vector3::new(rng.gen::<Float>(), rng.gen::<Float>(), rng.gen::<Float>())
             - Vector3::new(1.0, 1.0, 1.0); // -1 -> 1
         if p.squared_length() < 1.0 {
             break;
@@ -18,19 +19,19 @@ pub fn random_on_unit_sphere() -> Vector3 {
 
 pub fn random_cosine_direction() -> Vector3 {
     let mut rng = rand::thread_rng();
-    let r1 = rng.gen::<f32>();
-    let r2 = rng.gen::<f32>();
+    let r1 = rng.gen::<Float>();
+    let r2 = rng.gen::<Float>();
     let z = (1.0 - r2).sqrt();
     let phi = 2.0 * consts::PI * r1;
-    let x = phi.cos() * 2.0 * r2.sqrt();
-    let y = phi.sin() * 2.0 * r2.sqrt();
+    let x = phi.cos() * r2.sqrt();
+    let y = phi.sin() * r2.sqrt();
     Vector3::new(x, y, z)
 }
 
-pub fn random_to_sphere(radius: f32, distance_squared: f32) -> Vector3 {
+pub fn random_to_sphere(radius: Float, distance_squared: Float) -> Vector3 {
     let mut rng = rand::thread_rng();
-    let r1 = rng.gen::<f32>();
-    let r2 = rng.gen::<f32>();
+    let r1 = rng.gen::<Float>();
+    let r2 = rng.gen::<Float>();
     let z = 1.0 + r2 * ((1.0 - radius.powf(2.0) / distance_squared).sqrt() - 1.0);
     let phi = 2.0 * consts::PI * r1;
     let x = phi.cos() * (1.0 - z.powf(2.0)).sqrt();
@@ -38,7 +39,7 @@ pub fn random_to_sphere(radius: f32, distance_squared: f32) -> Vector3 {
     Vector3::new(x, y, z)
 }
 
-pub fn de_nan(c: Vector3) -> Vector3 {
+pub fn de_nan(c: Color) -> Color {
     let mut temp = c;
     if temp[0].is_nan() {
         temp[0] = 0.0;
@@ -61,3 +62,75 @@ pub fn clamp(input: f32, min: f32, max: f32) -> f32 {
         input
     }
 }
+
+// bound on the relative rounding error of n consecutive float operations
+pub fn gamma(n: i32) -> Float {
+    let machine_epsilon = Float::EPSILON * 0.5;
+    (n as Float * machine_epsilon) / (1.0 - n as Float * machine_epsilon)
+}
+
+pub fn next_float_up(v: Float) -> Float {
+    if v.is_infinite() && v > 0.0 {
+        return v;
+    }
+    // skip -0.0 so the step is taken from +0.0
+    let v = if v == -0.0 { 0.0 } else { v };
+    let bits = v.to_bits();
+    if v >= 0.0 {
+        Float::from_bits(bits + 1)
+    } else {
+        Float::from_bits(bits - 1)
+    }
+}
+
+pub fn next_float_down(v: Float) -> Float {
+    if v.is_infinite() && v < 0.0 {
+        return v;
+    }
+    let v = if v == 0.0 { -0.0 } else { v };
+    let bits = v.to_bits();
+    if v > 0.0 {
+        Float::from_bits(bits - 1)
+    } else {
+        Float::from_bits(bits + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_float_steps_one_ulp() {
+        for &v in &[1.0, -1.0, 1000.0, -1e-3, 555.0] {
+            let up = next_float_up(v);
+            let down = next_float_down(v);
+            assert!(up > v && down < v);
+            // nothing representable in between
+            assert_eq!(up.to_bits().abs_diff(v.to_bits()), 1);
+            assert_eq!(down.to_bits().abs_diff(v.to_bits()), 1);
+        }
+    }
+
+    #[test]
+    fn next_float_around_zero_and_infinity() {
+        assert!(next_float_up(0.0) > 0.0);
+        assert!(next_float_up(-0.0) > 0.0);
+        assert!(next_float_down(0.0) < 0.0);
+        assert!(next_float_down(-0.0) < 0.0);
+        assert_eq!(next_float_up(Float::INFINITY), Float::INFINITY);
+        assert_eq!(next_float_down(Float::NEG_INFINITY), Float::NEG_INFINITY);
+    }
+
+    #[test]
+    fn gamma_bounds_rounding_error() {
+        // the sum of n terms computed in Float is off by at most gamma(n - 1)
+        // relative to the sum of the absolute values
+        let terms: Vec<Float> = (1..=100).map(|i| 1.0 / i as Float).collect();
+        let sum: Float = terms.iter().sum();
+        let exact: f64 = (1..=100).map(|i| 1.0 / i as f64).sum();
+        let error = (sum as f64 - exact).abs();
+        assert!(error <= gamma(100) as f64 * exact);
+        assert!(gamma(1) > 0.0 && gamma(2) > gamma(1));
+    }
+}
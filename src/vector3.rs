@@ -2,55 +2,139 @@ use std::ops::{
   Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
 };
 
-#[derive(Copy, Clone)]
-pub struct Vector3 {
-  pub e: [f32; 3],
+use super::float::Float;
+
+// the element types a vector can hold, geometry uses `Float`
+// while colors always stay `f32`
+pub trait Scalar:
+  Copy
+  + PartialOrd
+  + Add<Output = Self>
+  + Sub<Output = Self>
+  + Mul<Output = Self>
+  + Div<Output = Self>
+  + Neg<Output = Self>
+  + AddAssign
+  + SubAssign
+  + MulAssign
+  + DivAssign
+{
+  fn one() -> Self;
+  fn sqrt(self) -> Self;
+  fn abs(self) -> Self;
+  fn from_f64(v: f64) -> Self;
+  fn to_f64(self) -> f64;
 }
 
-impl Vector3 {
-  pub fn new(x: f32, y: f32, z: f32) -> Vector3 {
-    Vector3 { e: [x, y, z] }
+impl Scalar for f32 {
+  fn one() -> f32 {
+    1.0
   }
-  pub fn x(&self) -> f32 {
+  fn sqrt(self) -> f32 {
+    f32::sqrt(self)
+  }
+  fn abs(self) -> f32 {
+    f32::abs(self)
+  }
+  fn from_f64(v: f64) -> f32 {
+    v as f32
+  }
+  fn to_f64(self) -> f64 {
+    self as f64
+  }
+}
+
+impl Scalar for f64 {
+  fn one() -> f64 {
+    1.0
+  }
+  fn sqrt(self) -> f64 {
+    f64::sqrt(self)
+  }
+  fn abs(self) -> f64 {
+    f64::abs(self)
+  }
+  fn from_f64(v: f64) -> f64 {
+    v
+  }
+  fn to_f64(self) -> f64 {
+    self
+  }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct Vec3<T> {
+  pub e: [T; 3],
+}
+
+pub type Vector3 = Vec3<Float>;
+pub type Color = Vec3<f32>;
+
+impl<T: Scalar> Vec3<T> {
+  pub fn new(x: T, y: T, z: T) -> Vec3<T> {
+    Vec3 { e: [x, y, z] }
+  }
+  pub fn x(&self) -> T {
     self.e[0]
   }
-  pub fn y(&self) -> f32 {
+  pub fn y(&self) -> T {
     self.e[1]
   }
-  pub fn z(&self) -> f32 {
+  pub fn z(&self) -> T {
     self.e[2]
   }
-  pub fn r(&self) -> f32 {
+  pub fn r(&self) -> T {
     self.e[0]
   }
-  pub fn g(&self) -> f32 {
+  pub fn g(&self) -> T {
     self.e[1]
   }
-  pub fn b(&self) -> f32 {
+  pub fn b(&self) -> T {
     self.e[2]
   }
 
-  pub fn length(&self) -> f32 {
+  pub fn length(&self) -> T {
     (self.e[0] * self.e[0] + self.e[1] * self.e[1] + self.e[2] * self.e[2]).sqrt()
   }
 
-  pub fn squared_length(&self) -> f32 {
+  pub fn squared_length(&self) -> T {
     self.e[0] * self.e[0] + self.e[1] * self.e[1] + self.e[2] * self.e[2]
   }
 
+  pub fn abs(&self) -> Vec3<T> {
+    Vec3::new(self.e[0].abs(), self.e[1].abs(), self.e[2].abs())
+  }
+
   pub fn make_unit_vector(&mut self) {
-    let k: f32 = 1.0 / (self.e[0] * self.e[0] + self.e[1] * self.e[1] + self.e[2] * self.e[2]);
+    let k: T = T::one() / (self.e[0] * self.e[0] + self.e[1] * self.e[1] + self.e[2] * self.e[2]);
     self.e[0] *= k;
     self.e[1] *= k;
     self.e[2] *= k;
   }
+
+  // convert between geometry and color precision
+  pub fn to_vector(self) -> Vector3 {
+    Vector3::new(
+      Float::from_f64(self.e[0].to_f64()),
+      Float::from_f64(self.e[1].to_f64()),
+      Float::from_f64(self.e[2].to_f64()),
+    )
+  }
+
+  pub fn to_color(self) -> Color {
+    Color::new(
+      f32::from_f64(self.e[0].to_f64()),
+      f32::from_f64(self.e[1].to_f64()),
+      f32::from_f64(self.e[2].to_f64()),
+    )
+  }
 }
 
-impl Add for Vector3 {
-  type Output = Vector3;
+impl<T: Scalar> Add for Vec3<T> {
+  type Output = Vec3<T>;
 
-  fn add(self, other: Vector3) -> Vector3 {
-    Vector3::new(
+  fn add(self, other: Vec3<T>) -> Vec3<T> {
+    Vec3::new(
       self.e[0] + other.e[0],
       self.e[1] + other.e[1],
       self.e[2] + other.e[2],
@@ -58,11 +142,11 @@ impl Add for Vector3 {
   }
 }
 
-impl Sub for Vector3 {
-  type Output = Vector3;
+impl<T: Scalar> Sub for Vec3<T> {
+  type Output = Vec3<T>;
 
-  fn sub(self, other: Vector3) -> Vector3 {
-    Vector3::new(
+  fn sub(self, other: Vec3<T>) -> Vec3<T> {
+    Vec3::new(
       self.e[0] - other.e[0],
       self.e[1] - other.e[1],
       self.e[2] - other.e[2],
@@ -70,11 +154,11 @@ impl Sub for Vector3 {
   }
 }
 
-impl Sub for &Vector3 {
-  type Output = Vector3;
+impl<T: Scalar> Sub for &Vec3<T> {
+  type Output = Vec3<T>;
 
-  fn sub(self, other: &Vector3) -> Vector3 {
-    Vector3::new(
+  fn sub(self, other: &Vec3<T>) -> Vec3<T> {
+    Vec3::new(
       self.e[0] - other.e[0],
       self.e[1] - other.e[1],
       self.e[2] - other.e[2],
@@ -82,11 +166,11 @@ impl Sub for &Vector3 {
   }
 }
 
-impl Mul for Vector3 {
-  type Output = Vector3;
+impl<T: Scalar> Mul for Vec3<T> {
+  type Output = Vec3<T>;
 
-  fn mul(self, other: Vector3) -> Vector3 {
-    Vector3::new(
+  fn mul(self, other: Vec3<T>) -> Vec3<T> {
+    Vec3::new(
       self.e[0] * other.e[0],
       self.e[1] * other.e[1],
       self.e[2] * other.e[2],
@@ -94,11 +178,11 @@ impl Mul for Vector3 {
   }
 }
 
-impl Div for Vector3 {
-  type Output = Vector3;
+impl<T: Scalar> Div for Vec3<T> {
+  type Output = Vec3<T>;
 
-  fn div(self, other: Vector3) -> Vector3 {
-    Vector3::new(
+  fn div(self, other: Vec3<T>) -> Vec3<T> {
+    Vec3::new(
       self.e[0] / other.e[0],
       self.e[1] / other.e[1],
       self.e[2] / other.e[2],
@@ -106,103 +190,111 @@ impl Div for Vector3 {
   }
 }
 
-impl Mul<f32> for Vector3 {
-  type Output = Vector3;
+impl<T: Scalar> Mul<T> for Vec3<T> {
+  type Output = Vec3<T>;
 
-  fn mul(self, other: f32) -> Vector3 {
-    Vector3::new(self.e[0] * other, self.e[1] * other, self.e[2] * other)
+  fn mul(self, other: T) -> Vec3<T> {
+    Vec3::new(self.e[0] * other, self.e[1] * other, self.e[2] * other)
   }
 }
 
-impl Mul<Vector3> for f32 {
-  type Output = Vector3;
-
-  fn mul(self, other: Vector3) -> Vector3 {
-    Vector3::new(self * other.e[0], self * other.e[1], self * other.e[2])
-  }
+// scalar on the left can't be generic over a foreign type
+macro_rules! impl_scalar_mul {
+  ($t:ty) => {
+    impl Mul<Vec3<$t>> for $t {
+      type Output = Vec3<$t>;
+
+      fn mul(self, other: Vec3<$t>) -> Vec3<$t> {
+        Vec3::new(self * other.e[0], self * other.e[1], self * other.e[2])
+      }
+    }
+
+    impl Mul<&Vec3<$t>> for $t {
+      type Output = Vec3<$t>;
+
+      fn mul(self, other: &Vec3<$t>) -> Vec3<$t> {
+        Vec3::new(self * other.e[0], self * other.e[1], self * other.e[2])
+      }
+    }
+  };
 }
 
-impl Mul<&Vector3> for f32 {
-  type Output = Vector3;
-
-  fn mul(self, other: &Vector3) -> Vector3 {
-    Vector3::new(self * other.e[0], self * other.e[1], self * other.e[2])
-  }
-}
+impl_scalar_mul!(f32);
+impl_scalar_mul!(f64);
 
-impl Div<f32> for Vector3 {
-  type Output = Vector3;
+impl<T: Scalar> Div<T> for Vec3<T> {
+  type Output = Vec3<T>;
 
-  fn div(self, other: f32) -> Vector3 {
-    Vector3::new(self.e[0] / other, self.e[1] / other, self.e[2] / other)
+  fn div(self, other: T) -> Vec3<T> {
+    Vec3::new(self.e[0] / other, self.e[1] / other, self.e[2] / other)
   }
 }
 
-impl Neg for Vector3 {
-  type Output = Vector3;
+impl<T: Scalar> Neg for Vec3<T> {
+  type Output = Vec3<T>;
 
-  fn neg(self) -> Vector3 {
-    Vector3::new(-self.e[0], -self.e[1], -self.e[2])
+  fn neg(self) -> Vec3<T> {
+    Vec3::new(-self.e[0], -self.e[1], -self.e[2])
   }
 }
 
-impl Index<usize> for Vector3 {
-  type Output = f32;
+impl<T> Index<usize> for Vec3<T> {
+  type Output = T;
 
-  fn index(&self, i: usize) -> &f32 {
+  fn index(&self, i: usize) -> &T {
     &self.e[i]
   }
 }
 
-impl IndexMut<usize> for Vector3 {
-  fn index_mut(&mut self, i: usize) -> &mut f32 {
+impl<T> IndexMut<usize> for Vec3<T> {
+  fn index_mut(&mut self, i: usize) -> &mut T {
     &mut self.e[i]
   }
 }
 
-impl AddAssign for Vector3 {
-  fn add_assign(&mut self, other: Vector3) {
+impl<T: Scalar> AddAssign for Vec3<T> {
+  fn add_assign(&mut self, other: Vec3<T>) {
     self.e[0] += other.e[0];
     self.e[1] += other.e[1];
     self.e[2] += other.e[2];
   }
 }
 
-impl SubAssign for Vector3 {
-  fn sub_assign(&mut self, other: Vector3) {
+impl<T: Scalar> SubAssign for Vec3<T> {
+  fn sub_assign(&mut self, other: Vec3<T>) {
     self.e[0] -= other.e[0];
     self.e[1] -= other.e[1];
     self.e[2] -= other.e[2];
   }
 }
 
-impl MulAssign for Vector3 {
-  fn mul_assign(&mut self, other: Vector3) {
+impl<T: Scalar> MulAssign for Vec3<T> {
+  fn mul_assign(&mut self, other: Vec3<T>) {
     self.e[0] *= other.e[0];
     self.e[1] *= other.e[1];
     self.e[2] *= other.e[2];
   }
 }
 
-impl MulAssign<f32> for Vector3 {
-  fn mul_assign(&mut self, other: f32) {
+impl<T: Scalar> MulAssign<T> for Vec3<T> {
+  fn mul_assign(&mut self, other: T) {
     self.e[0] *= other;
     self.e[1] *= other;
     self.e[2] *= other;
   }
 }
 
-impl DivAssign for Vector3 {
-  fn div_assign(&mut self, other: Vector3) {
+impl<T: Scalar> DivAssign for Vec3<T> {
+  fn div_assign(&mut self, other: Vec3<T>) {
     self.e[0] /= other.e[0];
     self.e[1] /= other.e[1];
     self.e[2] /= other.e[2];
   }
 }
 
-impl DivAssign<f32> for Vector3 {
-  fn div_assign(&mut self, other: f32) {
-    let k = 1.0 / other;
+impl<T: Scalar> DivAssign<T> for Vec3<T> {
+  fn div_assign(&mut self, other: T) {
+    let k = T::one() / other;
 
     self.e[0] *= k;
     self.e[1] *= k;
@@ -210,25 +302,68 @@ impl DivAssign<f32> for Vector3 {
   }
 }
 
-impl std::fmt::Display for Vector3 {
+impl<T: std::fmt::Display> std::fmt::Display for Vec3<T> {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     write!(f, "(x: {}, y: {}, z: {})", self.e[0], self.e[1], self.e[2])
   }
 }
 
-pub fn dot(v1: Vector3, v2: Vector3) -> f32 {
+pub fn dot<T: Scalar>(v1: Vec3<T>, v2: Vec3<T>) -> T {
   v1.e[0] * v2.e[0] + v1.e[1] * v2.e[1] + v1.e[2] * v2.e[2]
 }
 
-pub fn cross(v1: Vector3, v2: Vector3) -> Vector3 {
-  Vector3::new(
+pub fn cross<T: Scalar>(v1: Vec3<T>, v2: Vec3<T>) -> Vec3<T> {
+  Vec3::new(
     v1.e[1] * v2.e[2] - v1.e[2] * v2.e[1],
     -(v1.e[0] * v2.e[2] - v1.e[2] * v2.e[0]),
     v1.e[0] * v2.e[1] - v1.e[1] * v2.e[0],
   )
 }
 
-pub fn unit_vector(v: Vector3) -> Vector3 {
+pub fn unit_vector<T: Scalar>(v: Vec3<T>) -> Vec3<T> {
   let length = v.length();
   v / length
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn arithmetic() {
+    let a = Vector3::new(1.0, 2.0, 3.0);
+    let b = Vector3::new(4.0, -5.0, 6.0);
+    assert!(a + b == Vector3::new(5.0, -3.0, 9.0));
+    assert!(a - b == Vector3::new(-3.0, 7.0, -3.0));
+    assert!(a * b == Vector3::new(4.0, -10.0, 18.0));
+    assert!(2.0 * a == Vector3::new(2.0, 4.0, 6.0));
+    assert!(b / 2.0 == Vector3::new(2.0, -2.5, 3.0));
+    assert!(-a == Vector3::new(-1.0, -2.0, -3.0));
+  }
+
+  #[test]
+  fn products() {
+    let x = Vector3::new(1.0, 0.0, 0.0);
+    let y = Vector3::new(0.0, 1.0, 0.0);
+    assert!(cross(x, y) == Vector3::new(0.0, 0.0, 1.0));
+    assert!(cross(y, x) == Vector3::new(0.0, 0.0, -1.0));
+    let a = Vector3::new(1.0, 2.0, 3.0);
+    let b = Vector3::new(4.0, -5.0, 6.0);
+    assert_eq!(dot(a, b), 12.0);
+    assert_eq!(dot(cross(a, b), a), 0.0);
+  }
+
+  #[test]
+  fn lengths() {
+    let v = Vector3::new(3.0, 4.0, 12.0);
+    assert_eq!(v.squared_length(), 169.0);
+    assert_eq!(v.length(), 13.0);
+    assert!((unit_vector(v).length() - 1.0).abs() < 4.0 * Float::EPSILON);
+  }
+
+  #[test]
+  fn precision_conversions() {
+    let c = Color::new(0.25, 0.5, 1.0);
+    assert!(c.to_vector().to_color() == c);
+  }
+}